@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS message_index;
+DROP INDEX IF EXISTS conversation_member_index;
+DROP TABLE messages;
+DROP TABLE conversation_members;
+DROP TABLE conversations;
\ No newline at end of file
@@ -0,0 +1,26 @@
+CREATE TABLE conversations (
+    id SERIAL PRIMARY KEY,
+    title VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    last_message_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE TABLE conversation_members (
+    conversation_id INTEGER NOT NULL REFERENCES conversations(id),
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    joined_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    last_read_at TIMESTAMP,
+    PRIMARY KEY (conversation_id, user_id)
+);
+
+CREATE TABLE messages (
+    id SERIAL PRIMARY KEY,
+    conversation_id INTEGER NOT NULL REFERENCES conversations(id),
+    sender_id INTEGER NOT NULL REFERENCES users(id),
+    content TEXT NOT NULL,
+    media TEXT[],
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX conversation_member_index ON conversation_members USING btree (user_id, conversation_id DESC);
+CREATE INDEX message_index ON messages USING btree (conversation_id, id DESC);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS notification_index;
+DROP TABLE notifications;
+DROP TYPE notification_kind;
+DROP TABLE post_stars;
\ No newline at end of file
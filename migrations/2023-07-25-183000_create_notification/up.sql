@@ -0,0 +1,21 @@
+CREATE TABLE post_stars (
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    post_id INTEGER NOT NULL REFERENCES posts(id),
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    PRIMARY KEY (user_id, post_id)
+);
+
+CREATE TYPE notification_kind AS ENUM ('post_reply', 'comment_reply', 'mention', 'star');
+
+CREATE TABLE notifications (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    actor_id INTEGER NOT NULL REFERENCES users(id),
+    kind notification_kind NOT NULL,
+    post_id INTEGER REFERENCES posts(id),
+    comment_id INTEGER REFERENCES comments(id),
+    read BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX notification_index ON notifications USING btree (user_id, id DESC);
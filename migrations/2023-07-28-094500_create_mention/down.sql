@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS comment_mention_index;
+DROP INDEX IF EXISTS post_mention_index;
+DROP TABLE mentions;
\ No newline at end of file
@@ -0,0 +1,13 @@
+CREATE TABLE mentions (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER REFERENCES posts(id),
+    comment_id INTEGER REFERENCES comments(id),
+    user_id INTEGER REFERENCES users(id),
+    forum_id INTEGER REFERENCES forums(id),
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    CHECK ((post_id IS NULL) <> (comment_id IS NULL)),
+    CHECK ((user_id IS NULL) <> (forum_id IS NULL))
+);
+
+CREATE INDEX post_mention_index ON mentions USING btree (post_id);
+CREATE INDEX comment_mention_index ON mentions USING btree (comment_id);
@@ -0,0 +1,2 @@
+ALTER TABLE posts ADD COLUMN content_html TEXT;
+ALTER TABLE comments ADD COLUMN content_html TEXT;
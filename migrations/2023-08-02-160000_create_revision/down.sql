@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS comment_revision_index;
+DROP INDEX IF EXISTS post_revision_index;
+DROP TABLE comment_revisions;
+DROP TABLE post_revisions;
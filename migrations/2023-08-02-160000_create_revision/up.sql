@@ -0,0 +1,22 @@
+CREATE TABLE post_revisions (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id),
+    title VARCHAR NOT NULL,
+    tags TEXT[],
+    content TEXT,
+    media TEXT[],
+    created_at TIMESTAMP NOT NULL,
+    archived_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE TABLE comment_revisions (
+    id SERIAL PRIMARY KEY,
+    comment_id INTEGER NOT NULL REFERENCES comments(id),
+    content TEXT NOT NULL,
+    media TEXT[],
+    created_at TIMESTAMP NOT NULL,
+    archived_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX post_revision_index ON post_revisions USING btree (post_id, id DESC);
+CREATE INDEX comment_revision_index ON comment_revisions USING btree (comment_id, id DESC);
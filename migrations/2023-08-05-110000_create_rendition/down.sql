@@ -0,0 +1,2 @@
+DROP TABLE renditions;
+DROP TYPE rendition_kind;
@@ -0,0 +1,11 @@
+CREATE TYPE rendition_kind AS ENUM ('original', 'medium', 'thumbnail');
+
+CREATE TABLE renditions (
+    file_id VARCHAR NOT NULL,
+    kind rendition_kind NOT NULL,
+    path VARCHAR NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    PRIMARY KEY (file_id, kind)
+);
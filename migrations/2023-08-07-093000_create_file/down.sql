@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS file_owner_index;
+DROP TABLE files;
@@ -0,0 +1,9 @@
+CREATE TABLE files (
+    id VARCHAR PRIMARY KEY,
+    owner_id INTEGER NOT NULL REFERENCES users(id),
+    size BIGINT NOT NULL,
+    mime VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX file_owner_index ON files USING btree (owner_id);
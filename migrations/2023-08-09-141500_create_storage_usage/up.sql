@@ -0,0 +1,9 @@
+CREATE TABLE storage_usage (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id),
+    used BIGINT NOT NULL DEFAULT 0,
+    -- Overrides the configured quota for this user
+    quota BIGINT
+);
+
+INSERT INTO storage_usage (user_id, used)
+SELECT owner_id, SUM(size) FROM files GROUP BY owner_id;
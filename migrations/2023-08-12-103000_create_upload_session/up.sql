@@ -0,0 +1,14 @@
+CREATE TABLE upload_sessions (
+    id VARCHAR PRIMARY KEY,
+    owner_id INTEGER NOT NULL REFERENCES users(id),
+    filename VARCHAR NOT NULL,
+    size BIGINT NOT NULL,
+    chunk_size INTEGER NOT NULL,
+    chunk_count INTEGER NOT NULL,
+    -- Indexes of the chunks stored so far
+    received INTEGER[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    updated_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX upload_session_owner_index ON upload_sessions USING btree (owner_id);
@@ -0,0 +1,8 @@
+DELETE FROM renditions WHERE kind = 'poster';
+ALTER TYPE rendition_kind RENAME TO rendition_kind_old;
+CREATE TYPE rendition_kind AS ENUM ('original', 'medium', 'thumbnail');
+ALTER TABLE renditions ALTER COLUMN kind TYPE rendition_kind USING kind::text::rendition_kind;
+DROP TYPE rendition_kind_old;
+
+ALTER TABLE files DROP COLUMN kind, DROP COLUMN duration, DROP COLUMN codec;
+DROP TYPE media_kind;
@@ -0,0 +1,13 @@
+CREATE TYPE media_kind AS ENUM ('image', 'video', 'audio', 'other');
+
+ALTER TABLE files
+    ADD COLUMN kind media_kind NOT NULL DEFAULT 'other',
+    -- Seconds, for audio and video
+    ADD COLUMN duration DOUBLE PRECISION,
+    ADD COLUMN codec VARCHAR;
+
+UPDATE files SET kind = 'image' WHERE mime LIKE 'image/%';
+UPDATE files SET kind = 'video' WHERE mime LIKE 'video/%';
+UPDATE files SET kind = 'audio' WHERE mime LIKE 'audio/%';
+
+ALTER TYPE rendition_kind ADD VALUE 'poster';
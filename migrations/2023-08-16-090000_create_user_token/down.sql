@@ -0,0 +1,2 @@
+DROP TABLE user_tokens;
+DROP TYPE token_purpose;
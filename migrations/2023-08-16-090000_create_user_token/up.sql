@@ -0,0 +1,13 @@
+CREATE TYPE token_purpose AS ENUM ('password_reset');
+
+CREATE TABLE user_tokens (
+    -- sha256 of the token, the token itself is only ever mailed
+    token_hash VARCHAR PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose token_purpose NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX user_token_user_index ON user_tokens USING btree (user_id, purpose);
@@ -0,0 +1,11 @@
+DROP TABLE site_settings;
+
+DELETE FROM user_tokens WHERE purpose = 'email_verification';
+ALTER TYPE token_purpose RENAME TO token_purpose_old;
+CREATE TYPE token_purpose AS ENUM ('password_reset');
+ALTER TABLE user_tokens ALTER COLUMN purpose TYPE token_purpose USING purpose::text::token_purpose;
+DROP TYPE token_purpose_old;
+
+DROP INDEX user_email_index;
+ALTER TABLE users DROP COLUMN email_verified_at;
+ALTER TABLE users DROP COLUMN email;
@@ -0,0 +1,16 @@
+ALTER TABLE users ADD COLUMN email VARCHAR;
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMP;
+-- Only verified emails are unique, an unverified one proves nothing and mustn't keep
+-- its actual owner from using it
+CREATE UNIQUE INDEX user_email_index ON users (lower(email))
+    WHERE email_verified_at IS NOT NULL;
+
+ALTER TYPE token_purpose ADD VALUE 'email_verification';
+
+-- Single row of settings admins can change at runtime
+CREATE TABLE site_settings (
+    id BOOLEAN PRIMARY KEY DEFAULT true CHECK (id),
+    require_verified_email BOOLEAN NOT NULL DEFAULT false
+);
+
+INSERT INTO site_settings DEFAULT VALUES;
@@ -0,0 +1,14 @@
+CREATE TYPE api_scope AS ENUM ('read', 'post', 'moderate');
+
+CREATE TABLE api_tokens (
+    id SERIAL PRIMARY KEY,
+    token_hash VARCHAR UNIQUE NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR NOT NULL,
+    scopes api_scope[] NOT NULL,
+    expires_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX api_token_user_index ON api_tokens (user_id);
@@ -0,0 +1,11 @@
+-- Cookie sessions live in redis, these rows let users see and revoke them
+CREATE TABLE user_sessions (
+    id VARCHAR PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    user_agent VARCHAR,
+    ip VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    last_seen_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX user_session_user_index ON user_sessions (user_id);
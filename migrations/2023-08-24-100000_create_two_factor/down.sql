@@ -0,0 +1,8 @@
+DELETE FROM user_tokens WHERE purpose = 'login_challenge';
+ALTER TYPE token_purpose RENAME TO token_purpose_old;
+CREATE TYPE token_purpose AS ENUM ('password_reset', 'email_verification');
+ALTER TABLE user_tokens ALTER COLUMN purpose TYPE token_purpose USING purpose::text::token_purpose;
+DROP TYPE token_purpose_old;
+
+DROP TABLE recovery_codes;
+DROP TABLE totp_secrets;
@@ -0,0 +1,20 @@
+-- Enabled once confirmed, until then the secret is only pending
+CREATE TABLE totp_secrets (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    secret VARCHAR NOT NULL,
+    confirmed_at TIMESTAMP,
+    -- Codes of this step and earlier ones can't be used again
+    last_used_step BIGINT,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE TABLE recovery_codes (
+    code_hash VARCHAR PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    used_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX recovery_code_user_index ON recovery_codes (user_id);
+
+ALTER TYPE token_purpose ADD VALUE 'login_challenge';
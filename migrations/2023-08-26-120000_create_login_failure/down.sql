@@ -0,0 +1,2 @@
+DROP TABLE login_failures;
+DROP TYPE login_failure_kind;
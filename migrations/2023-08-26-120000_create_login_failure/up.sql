@@ -0,0 +1,14 @@
+CREATE TYPE login_failure_kind AS ENUM ('username', 'ip');
+
+-- Unknown usernames are tracked too, so lockouts don't reveal which accounts exist
+CREATE TABLE login_failures (
+    kind login_failure_kind NOT NULL,
+    key VARCHAR NOT NULL,
+    failures INTEGER NOT NULL DEFAULT 1,
+    last_failed_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    locked_until TIMESTAMP,
+    PRIMARY KEY (kind, key)
+);
+
+CREATE INDEX login_failure_locked_index ON login_failures (locked_until);
+CREATE INDEX login_failure_last_failed_index ON login_failures (last_failed_at);
@@ -0,0 +1,11 @@
+-- Accounts of an OpenID Connect provider that can log in as a user
+CREATE TABLE oidc_identities (
+    issuer VARCHAR NOT NULL,
+    subject VARCHAR NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    email VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    PRIMARY KEY (issuer, subject)
+);
+
+CREATE INDEX oidc_identity_user_index ON oidc_identities (user_id);
@@ -0,0 +1,11 @@
+-- Usernames users had before, old names keep resolving to them and can't be taken by
+-- anyone else for a while
+CREATE TABLE username_history (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    username VARCHAR NOT NULL,
+    changed_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX username_history_username_index ON username_history (username, changed_at DESC);
+CREATE INDEX username_history_user_index ON username_history (user_id, changed_at DESC);
@@ -0,0 +1,9 @@
+-- Archives built by `exportMyData`, removed from the storage once they expire
+CREATE TABLE data_exports (
+    path VARCHAR PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    size BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX data_export_created_index ON data_exports (created_at);
@@ -0,0 +1,8 @@
+DROP TABLE forum_roles;
+DROP TYPE forum_role;
+
+ALTER TABLE users ADD COLUMN admin BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE users SET admin = TRUE FROM user_roles r WHERE r.user_id = users.id AND r.role = 'admin';
+
+DROP TABLE user_roles;
+DROP TYPE site_role;
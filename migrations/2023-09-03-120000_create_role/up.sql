@@ -0,0 +1,25 @@
+CREATE TYPE site_role AS ENUM ('user', 'moderator', 'admin');
+
+-- Users without a row are plain users
+CREATE TABLE user_roles (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    role site_role NOT NULL,
+    granted_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+);
+
+INSERT INTO user_roles (user_id, role) SELECT id, 'admin' FROM users WHERE admin;
+
+ALTER TABLE users DROP COLUMN admin;
+
+CREATE TYPE forum_role AS ENUM ('moderator', 'admin');
+
+-- The owner of a forum is its admin without a row
+CREATE TABLE forum_roles (
+    forum_id INTEGER NOT NULL REFERENCES forums(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role forum_role NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+    PRIMARY KEY (forum_id, user_id)
+);
+
+CREATE INDEX forum_role_user_index ON forum_roles (user_id);
@@ -1,5 +1,14 @@
+use actix_session::Session;
+use actix_web::{http::header, HttpRequest};
 use send_wrapper::SendWrapper;
-use std::ops::Deref;
+use serde::de::DeserializeOwned;
+use std::{net::IpAddr, ops::Deref};
+
+use crate::db::models::{
+    api_token::ApiScope,
+    role::{Permission, SiteRole},
+};
+use crate::gql::mutation::{api_token::authenticate_api_token, session::touch_user_session};
 
 #[derive(Clone, Debug)]
 pub struct Shared<T>(SendWrapper<T>);
@@ -18,4 +27,201 @@ impl<T> Deref for Shared<T> {
     }
 }
 
-pub type SharedSession = Shared<actix_session::Session>;
+/// Reverse proxies whose `X-Forwarded-For` is believed, read from `TRUSTED_PROXIES`
+/// as a comma separated list of ips. Without any, the peer address is the client
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+impl TrustedProxies {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let proxies = std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                p.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid trusted proxy: {p}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self(proxies))
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.contains(ip)
+    }
+}
+
+/// Where a request came from, recorded with new sessions
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &HttpRequest, proxies: &TrustedProxies) -> Self {
+        Self {
+            user_agent: req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from),
+            ip: client_ip(req, proxies).map(|ip| ip.to_string()),
+        }
+    }
+}
+
+/// Peer address, or the last `X-Forwarded-For` hop that isn't a trusted proxy.
+/// Hops are read right to left since everything before the first proxy is client input
+fn client_ip(req: &HttpRequest, proxies: &TrustedProxies) -> Option<IpAddr> {
+    let mut ip = req.peer_addr()?.ip();
+    let mut hops = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    while proxies.contains(&ip) {
+        match hops.pop().and_then(|hop| hop.parse().ok()) {
+            Some(hop) => ip = hop,
+            None => break,
+        }
+    }
+    Some(ip)
+}
+
+/// User behind a valid api token
+#[derive(Clone, Debug)]
+pub struct TokenIdentity {
+    pub user_id: i32,
+    pub username: String,
+    pub role: SiteRole,
+    pub scopes: Vec<ApiScope>,
+}
+
+/// Cookie session of a browser, or the identity of an api token sent as a `Bearer` header.
+/// The site role of the user is loaded with every request, so role changes apply right away
+#[derive(Clone)]
+pub enum RequestAuth {
+    Session(Session, SiteRole),
+    Token(TokenIdentity),
+}
+
+impl RequestAuth {
+    /// Reads `id` and `username` like the cookie session has them
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        match self {
+            Self::Session(session, _) => Ok(session.get::<T>(key)?),
+            Self::Token(identity) => {
+                let value = match key {
+                    "id" => serde_json::json!(identity.user_id),
+                    "username" => serde_json::json!(identity.username),
+                    _ => return Ok(None),
+                };
+                Ok(Some(serde_json::from_value(value)?))
+            }
+        }
+    }
+
+    pub fn insert(&self, key: &str, value: impl serde::Serialize) -> anyhow::Result<()> {
+        match self {
+            Self::Session(session, _) => Ok(session.insert(key, value)?),
+            Self::Token(_) => Err(anyhow::Error::msg("Api tokens can't log in")),
+        }
+    }
+
+    pub fn remove(&self, key: &str) {
+        if let Self::Session(session, _) = self {
+            session.remove(key);
+        }
+    }
+
+    /// Logs the cookie session out, tokens stay valid until revoked
+    pub fn purge(&self) {
+        if let Self::Session(session, _) = self {
+            session.purge();
+        }
+    }
+
+    /// Id of the `user_sessions` row of a cookie session
+    pub fn session_id(&self) -> anyhow::Result<Option<String>> {
+        match self {
+            Self::Session(session, _) => Ok(session.get::<String>("sid")?),
+            Self::Token(_) => Ok(None),
+        }
+    }
+
+    pub fn is_token(&self) -> bool {
+        matches!(self, Self::Token(_))
+    }
+
+    /// Cookie sessions can do everything their user can
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        match self {
+            Self::Session(..) => true,
+            Self::Token(identity) => identity.scopes.contains(&scope),
+        }
+    }
+
+    /// `User` when logged out. Tokens only act with a higher role with the moderate scope
+    pub fn role(&self) -> SiteRole {
+        match self {
+            Self::Session(_, role) => *role,
+            Self::Token(_) if !self.has_scope(ApiScope::Moderate) => SiteRole::User,
+            Self::Token(identity) => identity.role,
+        }
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.role().has_permission(permission)
+    }
+}
+
+pub type SharedSession = Shared<RequestAuth>;
+
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// Api token from the `Authorization` header if there is one, the cookie session otherwise.
+/// `None` when the token isn't valid. Cookie sessions that were revoked are logged out,
+/// the others get the current username of their user
+pub async fn request_auth(
+    req: &HttpRequest,
+    session: Session,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<RequestAuth>> {
+    match bearer_token(req) {
+        Some(token) => Ok(authenticate_api_token(token, pool)
+            .await?
+            .map(RequestAuth::Token)),
+        None => {
+            let mut role = SiteRole::User;
+            if session.get::<i32>("id")?.is_some() {
+                let current = match session.get::<String>("sid")? {
+                    Some(sid) => touch_user_session(&sid, pool).await?,
+                    None => None,
+                };
+                match current {
+                    Some((current, username)) => {
+                        role = current;
+                        // Renames from other sessions show up here, the name is checked
+                        // against the password and used for paths
+                        if session.get::<String>("username")?.as_ref() != Some(&username) {
+                            session.insert("username", username)?;
+                        }
+                    }
+                    None => session.purge(),
+                }
+            }
+            Ok(Some(RequestAuth::Session(session, role)))
+        }
+    }
+}
@@ -3,8 +3,26 @@ use std::collections::HashSet;
 use once_cell::sync::Lazy;
 
 pub const UNAUTHEMTICATED_MESSAGE: &str = "Unauthenticated request";
+pub const FORBIDDEN_MESSAGE: &str = "Not allowed";
 pub const RESERVED_USERNAMES: &[&str] = &["admin", "administrator", "moderator", "mod", "system"];
 pub const CDN_PATH: &str = "/cdn";
+pub const MAX_CONVERSATION_MEMBERS: usize = 10;
+pub const THUMBNAIL_SIZE: u32 = 256;
+pub const MEDIUM_SIZE: u32 = 1024;
+pub const MAX_IMAGE_DIMENSION: u32 = 16384;
+pub const PASSWORD_RESET_TTL_MINUTES: i64 = 60;
+pub const EMAIL_VERIFICATION_TTL_HOURS: i64 = 48;
+pub const MAX_API_TOKENS: i64 = 20;
+pub const SESSION_TTL_HOURS: i64 = 24;
+pub const LOGIN_CHALLENGE_TTL_MINUTES: i64 = 5;
+/// How long a fresh login with the identity provider stands in for the password
+pub const REAUTHENTICATION_TTL_MINUTES: i64 = 5;
+pub const TOTP_ISSUER: &str = "rtwalk";
+pub const USERNAME_HOLD_DAYS: i64 = 30;
+pub const USERNAME_CHANGE_INTERVAL_DAYS: i64 = 7;
+pub const DATA_EXPORT_TTL_HOURS: i64 = 24;
+/// Erased accounts are renamed to this followed by their id
+pub const DELETED_USERNAME_PREFIX: &str = "deleted_";
 pub static ALLOWED_USERNAME_CHARS: Lazy<HashSet<char>> = Lazy::new(|| {
     HashSet::from([
         'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r',
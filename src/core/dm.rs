@@ -0,0 +1,157 @@
+use actix::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::{db::models::conversation::Message, gql::mutation::conversation::create_message};
+
+use super::{
+    packet::{DmBroadcast, DmConnect, DmDisconnect, DmOutPacket, ErrorPacket, InMessage, KickUser},
+    RtConfig,
+};
+
+/// Same as `RtServer` but rooms are conversations instead of posts
+pub struct DmServer {
+    sessions: HashMap<String, Recipient<DmOutPacket>>,
+    conversations: HashMap<i32, HashSet<String>>,
+    user_connections: HashMap<i32, HashSet<String>>,
+    config: RtConfig,
+    pool: crate::Pool,
+}
+
+impl DmServer {
+    pub fn new(pool: crate::Pool, config: RtConfig) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            conversations: HashMap::new(),
+            user_connections: HashMap::new(),
+            config,
+            pool,
+        }
+    }
+
+    fn broadcast(&self, message: Message) {
+        if let Some(listners) = self.conversations.get(&message.conversation_id) {
+            for listner in listners {
+                if let Some(addr) = self.sessions.get(listner) {
+                    addr.do_send(DmOutPacket::Message(message.clone()));
+                }
+            }
+        }
+    }
+
+    fn collect_garbage(&mut self) {
+        let sessions = &self.sessions;
+        self.conversations.retain(|_, listners| {
+            listners.retain(|id| sessions.contains_key(id));
+            !listners.is_empty()
+        });
+    }
+}
+
+impl Actor for DmServer {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.config.room_gc_interval, |act, _| {
+            act.collect_garbage();
+        });
+    }
+}
+
+impl Handler<DmConnect> for DmServer {
+    type Result = bool;
+
+    fn handle(&mut self, msg: DmConnect, _: &mut Self::Context) -> Self::Result {
+        let connections = self.user_connections.entry(msg.user_id).or_default();
+        if connections.len() >= self.config.max_connections_per_user {
+            return false;
+        }
+        connections.insert(msg.id.clone());
+
+        self.sessions.insert(msg.id.clone(), msg.addr);
+        self.conversations
+            .entry(msg.conversation_id)
+            .or_default()
+            .insert(msg.id);
+        true
+    }
+}
+
+impl Handler<DmDisconnect> for DmServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: DmDisconnect, _: &mut Self::Context) -> Self::Result {
+        // Refused sessions were never added
+        if self.sessions.remove(&msg.id).is_none() {
+            return;
+        }
+        if let Some(connections) = self.user_connections.get_mut(&msg.user_id) {
+            connections.remove(&msg.id);
+            if connections.is_empty() {
+                self.user_connections.remove(&msg.user_id);
+            }
+        }
+        if let Some(listners) = self.conversations.get_mut(&msg.conversation_id) {
+            listners.remove(&msg.id);
+            if listners.is_empty() {
+                self.conversations.remove(&msg.conversation_id);
+            }
+        }
+    }
+}
+
+impl Handler<InMessage> for DmServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: InMessage, ctx: &mut Self::Context) -> Self::Result {
+        let pool = self.pool.clone();
+        let session_id = msg.session_id;
+        ctx.spawn(
+            async move {
+                create_message(
+                    msg.sender_id,
+                    msg.conversation_id,
+                    msg.content,
+                    msg.media.ids(),
+                    &pool,
+                )
+                .await
+            }
+            .into_actor(self)
+            .map(move |res, act, _| match res {
+                Ok(message) => act.broadcast(message),
+                Err(e) => {
+                    log::error!("{e:?}");
+                    if let Some(addr) = act.sessions.get(&session_id) {
+                        addr.do_send(DmOutPacket::Error(ErrorPacket {
+                            code: 400,
+                            message: e.to_string(),
+                            retry_after: None,
+                        }));
+                    }
+                }
+            }),
+        );
+    }
+}
+
+impl Handler<KickUser> for DmServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: KickUser, _: &mut Self::Context) {
+        if let Some(connections) = self.user_connections.get(&msg.user_id) {
+            for id in connections {
+                if let Some(addr) = self.sessions.get(id) {
+                    addr.do_send(DmOutPacket::Kick(msg.session_id.clone()));
+                }
+            }
+        }
+    }
+}
+
+impl Handler<DmBroadcast> for DmServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: DmBroadcast, _: &mut Self::Context) -> Self::Result {
+        self.broadcast(msg.message);
+    }
+}
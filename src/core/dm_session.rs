@@ -0,0 +1,175 @@
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix::{Actor, ActorContext, Addr, AsyncContext, Handler};
+use actix_web_actors::ws::{self, WebsocketContext};
+
+use super::{
+    dm::DmServer,
+    packet::{DmConnect, DmDisconnect, DmInPacket, DmOutPacket, ErrorPacket, InMessage},
+};
+use crate::db::models::role::{Permission, SiteRole};
+use crate::ratelimit::{Action, RateKey, RateLimiter};
+
+#[derive(Debug)]
+pub struct DmSession {
+    pub id: String,
+    pub hb: Instant,
+    pub heartbeat_interval: Duration,
+    pub session_timeout: Duration,
+    pub conversation_id: i32,
+    pub user_id: i32,
+    pub role: SiteRole,
+    /// Api tokens without the post scope can only read
+    pub can_post: bool,
+    /// Login session the connection was opened with, `None` for api tokens
+    pub login_session: Option<String>,
+    pub addr: Addr<DmServer>,
+    pub limiter: RateLimiter,
+}
+
+impl DmSession {
+    fn hb(&self, ctx: &mut WebsocketContext<Self>) {
+        ctx.run_interval(self.heartbeat_interval, |act, ctx| {
+            if Instant::now().duration_since(act.hb) > act.session_timeout {
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn check_rate_limit(&self, action: Action) -> Result<(), ErrorPacket> {
+        let elevated = self.role.has_permission(Permission::ElevatedRateLimits);
+        self.limiter
+            .check(RateKey::User(self.user_id), action, elevated)
+            .map_err(|e| ErrorPacket {
+                code: 429,
+                message: e.to_string(),
+                retry_after: Some(e.retry_after_secs()),
+            })
+    }
+}
+
+impl Actor for DmSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.hb(ctx);
+
+        self.addr
+            .send(DmConnect {
+                id: self.id.clone(),
+                user_id: self.user_id,
+                conversation_id: self.conversation_id,
+                addr: ctx.address().recipient(),
+            })
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                match res {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        actix::Handler::handle(
+                            act,
+                            DmOutPacket::Error(ErrorPacket {
+                                code: 429,
+                                message: "Too many open connections".into(),
+                                retry_after: None,
+                            }),
+                            ctx,
+                        );
+                        ctx.close(Some(ws::CloseCode::Policy.into()));
+                        ctx.stop();
+                    }
+                    _ => ctx.stop(),
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        self.addr.do_send(DmDisconnect {
+            id: self.id.clone(),
+            user_id: self.user_id,
+            conversation_id: self.conversation_id,
+        });
+        Running::Stop
+    }
+}
+
+impl Handler<DmOutPacket> for DmSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: DmOutPacket, ctx: &mut Self::Context) -> Self::Result {
+        if let DmOutPacket::Kick(session_id) = msg {
+            if session_id.is_none() || session_id == self.login_session {
+                ctx.close(Some(ws::CloseCode::Policy.into()));
+                ctx.stop();
+            }
+            return;
+        }
+        let msg = serde_json::to_string(&msg);
+        match msg {
+            Ok(s) => ctx.text(s),
+            _ => {
+                log::error!("Failed to serialize: {:?}", msg);
+                ctx.stop();
+            }
+        };
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for DmSession {
+    fn handle(&mut self, item: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let item = match item {
+            Err(_) => {
+                ctx.stop();
+                return;
+            }
+            Ok(item) => item,
+        };
+        match item {
+            ws::Message::Text(msg) => {
+                let msg = serde_json::from_str::<DmInPacket>(msg.trim());
+                match msg {
+                    Ok(DmInPacket::Message { content, media }) => {
+                        if !self.can_post {
+                            let e = ErrorPacket {
+                                code: 403,
+                                message: "Api token is missing the Post scope".into(),
+                                retry_after: None,
+                            };
+                            actix::Handler::handle(self, DmOutPacket::Error(e), ctx);
+                            return;
+                        }
+                        if let Err(e) = self.check_rate_limit(Action::Message) {
+                            actix::Handler::handle(self, DmOutPacket::Error(e), ctx);
+                            return;
+                        }
+                        self.addr.do_send(InMessage {
+                            session_id: self.id.clone(),
+                            sender_id: self.user_id,
+                            conversation_id: self.conversation_id,
+                            content,
+                            media,
+                        });
+                    }
+                    Err(_) => ctx.stop(),
+                }
+            }
+            ws::Message::Ping(x) => {
+                self.hb = Instant::now();
+                ctx.pong(&x);
+            }
+            ws::Message::Pong(_) => {
+                self.hb = Instant::now();
+            }
+            ws::Message::Close(reason) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => {}
+        }
+    }
+}
@@ -3,7 +3,9 @@ use std::collections::HashSet;
 use actix::{Actor, Context, Handler, Message, Recipient};
 use async_graphql::{Enum, SimpleObject};
 
-use crate::db::models::{comment::Comment, forum::Forum, post::Post, user::User};
+use crate::db::models::{
+    comment::Comment, forum::Forum, notification::Notification, post::Post, user::User,
+};
 
 #[derive(Default)]
 pub struct EventManager {
@@ -11,6 +13,7 @@ pub struct EventManager {
     forum_event_listeners: HashSet<Recipient<ForumEvent>>,
     post_event_listeners: HashSet<Recipient<PostEvent>>,
     comment_event_listeners: HashSet<Recipient<CommentEvent>>,
+    notification_event_listeners: HashSet<Recipient<NotificationEvent>>,
 }
 
 impl Actor for EventManager {
@@ -26,10 +29,12 @@ impl Handler<Com> for EventManager {
             Com::SubForum(r) => self.forum_event_listeners.insert(r),
             Com::SubPost(r) => self.post_event_listeners.insert(r),
             Com::SubComment(r) => self.comment_event_listeners.insert(r),
+            Com::SubNotification(r) => self.notification_event_listeners.insert(r),
             Com::UnsubUser(r) => self.user_event_listeners.remove(&r),
             Com::UnsubForum(r) => self.forum_event_listeners.remove(&r),
             Com::UnsubPost(r) => self.post_event_listeners.remove(&r),
             Com::UnsubComment(r) => self.comment_event_listeners.remove(&r),
+            Com::UnsubNotification(r) => self.notification_event_listeners.remove(&r),
         };
     }
 }
@@ -74,6 +79,16 @@ impl Handler<CommentEvent> for EventManager {
     }
 }
 
+impl Handler<NotificationEvent> for EventManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotificationEvent, _: &mut Self::Context) -> Self::Result {
+        for listener in &self.notification_event_listeners {
+            listener.do_send(msg.clone());
+        }
+    }
+}
+
 #[derive(Clone, SimpleObject, Debug, Message)]
 #[rtype(result = "()")]
 pub struct UserEvent {
@@ -102,10 +117,17 @@ pub struct CommentEvent {
     pub comment: Comment,
 }
 
+#[derive(Clone, SimpleObject, Debug, Message)]
+#[rtype(result = "()")]
+pub struct NotificationEvent {
+    pub notification: Notification,
+}
+
 #[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
 pub enum UserEventTy {
     UserCreation,
     UserBasicUpdate,
+    UsernameChange,
 }
 #[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
 pub enum ForumEventTy {
@@ -130,8 +152,10 @@ pub enum Com {
     SubForum(Recipient<ForumEvent>),
     SubPost(Recipient<PostEvent>),
     SubComment(Recipient<CommentEvent>),
+    SubNotification(Recipient<NotificationEvent>),
     UnsubUser(Recipient<UserEvent>),
     UnsubForum(Recipient<ForumEvent>),
     UnsubPost(Recipient<PostEvent>),
     UnsubComment(Recipient<CommentEvent>),
+    UnsubNotification(Recipient<NotificationEvent>),
 }
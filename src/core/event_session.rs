@@ -1,6 +1,8 @@
 use actix::{Actor, ActorContext, Addr, AsyncContext, Context, Handler};
 
-use super::event::{Com, CommentEvent, EventManager, ForumEvent, PostEvent, UserEvent};
+use super::event::{
+    Com, CommentEvent, EventManager, ForumEvent, NotificationEvent, PostEvent, UserEvent,
+};
 
 pub struct UserEventSession {
     pub sender: futures::channel::mpsc::Sender<UserEvent>,
@@ -133,3 +135,39 @@ impl Handler<CommentEvent> for CommentEventSession {
         }
     }
 }
+
+// ------------------------------
+
+pub struct NotificationEventSession {
+    pub sender: futures::channel::mpsc::Sender<NotificationEvent>,
+    pub user_id: i32,
+    pub manager: Addr<EventManager>,
+}
+
+impl Actor for NotificationEventSession {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let addr = ctx.address().recipient();
+        self.manager.do_send(Com::SubNotification(addr));
+    }
+
+    fn stopping(&mut self, ctx: &mut Self::Context) -> actix::Running {
+        let addr = ctx.address().recipient();
+        self.manager.do_send(Com::UnsubNotification(addr));
+        actix::Running::Stop
+    }
+}
+
+impl Handler<NotificationEvent> for NotificationEventSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotificationEvent, ctx: &mut Self::Context) -> Self::Result {
+        if msg.notification.user_id == self.user_id {
+            match self.sender.try_send(msg) {
+                Ok(_) => {}
+                Err(_) => ctx.stop(),
+            }
+        }
+    }
+}
@@ -1,33 +1,107 @@
 use actix::prelude::*;
 use std::{
     collections::{HashMap, HashSet},
+    env,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use crate::gql::mutation::comment::create_comment;
+use crate::gql::mutation::{
+    comment::create_comment,
+    mention::{store_mentions, MentionSource},
+    notification::notify_comment,
+};
+use crate::ratelimit::RateLimiter;
+
+use self::dm::DmServer;
+use self::event::{EventManager, NotificationEvent};
 
 use self::packet::{
     ActiveUser, Connect, ConnectNotification, Disconnect, DisconnectNotification, InComment,
-    ListActiveUsers, OutComment, OutPacket,
+    KickUser, ListActiveUsers, OutComment, OutPacket,
 };
 
+pub mod dm;
+pub mod dm_session;
 pub mod event;
 pub mod event_session;
 pub mod packet;
 pub mod session;
 
+#[derive(Clone, Copy, Debug)]
+pub struct RtConfig {
+    /// How often sessions ping their client
+    pub heartbeat_interval: Duration,
+    /// Sessions without a ping/pong for this long are dropped
+    pub session_timeout: Duration,
+    /// How often rooms nobody is watching anymore are removed
+    pub room_gc_interval: Duration,
+    pub max_connections_per_user: usize,
+}
+
+impl Default for RtConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: Duration::from_secs(5),
+            session_timeout: Duration::from_secs(10),
+            room_gc_interval: Duration::from_secs(60),
+            max_connections_per_user: 5,
+        }
+    }
+}
+
+impl RtConfig {
+    /// Reads `RT_HEARTBEAT_INTERVAL`, `RT_SESSION_TIMEOUT`, `RT_ROOM_GC_INTERVAL` (seconds)
+    /// and `RT_MAX_CONNECTIONS_PER_USER`, keeping the defaults for anything unset
+    pub fn from_env() -> Self {
+        fn secs(key: &str, default: Duration) -> Duration {
+            env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        }
+
+        let default = Self::default();
+        Self {
+            heartbeat_interval: secs("RT_HEARTBEAT_INTERVAL", default.heartbeat_interval),
+            session_timeout: secs("RT_SESSION_TIMEOUT", default.session_timeout),
+            room_gc_interval: secs("RT_ROOM_GC_INTERVAL", default.room_gc_interval),
+            max_connections_per_user: env::var("RT_MAX_CONNECTIONS_PER_USER")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_connections_per_user),
+        }
+    }
+}
+
+/// Shared by the websocket handlers to start sessions
+#[derive(Clone)]
+pub struct RtState {
+    pub rt_server: Addr<RtServer>,
+    pub dm_server: Addr<DmServer>,
+    pub limiter: RateLimiter,
+    pub config: RtConfig,
+}
+
 pub struct RtServer {
     active_broadcasts: HashMap<String, Recipient<OutPacket>>,
     broadcasting_posts: HashMap<i32, HashSet<String>>,
+    user_connections: HashMap<i32, HashSet<String>>,
+    config: RtConfig,
     pool: crate::Pool,
+    event_manager: Addr<EventManager>,
 }
 
 impl RtServer {
-    pub fn new(pool: crate::Pool) -> Self {
+    pub fn new(pool: crate::Pool, config: RtConfig, event_manager: Addr<EventManager>) -> Self {
         Self {
             active_broadcasts: HashMap::new(),
             broadcasting_posts: HashMap::new(),
+            user_connections: HashMap::new(),
+            config,
             pool,
+            event_manager,
         }
     }
 }
@@ -38,16 +112,36 @@ impl RtServer {
         if rt.is_err() {
             return;
         }
-        match rt.unwrap().block_on(create_comment(
-            inc.user.id,
-            inc.post_id,
-            inc.forum_id,
-            inc.parent_id,
-            inc.content.clone(),
-            inc.media.ids(),
-            &self.pool,
-        )) {
-            Ok(comment) => {
+        match rt.unwrap().block_on(async {
+            let comment = create_comment(
+                inc.user.id,
+                inc.post_id,
+                inc.forum_id,
+                inc.parent_id,
+                inc.content.clone(),
+                inc.media.ids(),
+                &self.pool,
+            )
+            .await?;
+            let mentions = store_mentions(
+                MentionSource::Comment(comment.id),
+                Some(&comment.content),
+                &self.pool,
+            )
+            .await;
+            let notifications = notify_comment(&comment, &mentions, &self.pool).await;
+            anyhow::Ok((comment, notifications))
+        }) {
+            Ok((comment, notifications)) => {
+                match notifications {
+                    Ok(notifications) => {
+                        for notification in notifications {
+                            self.event_manager
+                                .do_send(NotificationEvent { notification });
+                        }
+                    }
+                    Err(e) => log::error!("{e:?}"),
+                }
                 if let Some(listners) = self.broadcasting_posts.get(&post_id) {
                     for listner in listners {
                         if let Some(addr) = self.active_broadcasts.get(listner) {
@@ -59,6 +153,7 @@ impl RtServer {
                                 forum_id: inc.forum_id,
                                 parent_id: inc.parent_id,
                                 content: inc.content.clone(),
+                                content_html: comment.content_html.clone(),
                                 media: inc.media.clone(),
                             }));
                         }
@@ -91,21 +186,48 @@ impl RtServer {
             }
         }
     }
+
+    /// Drops sessions that are no longer active and rooms/users left without any
+    fn collect_garbage(&mut self) {
+        let active = &self.active_broadcasts;
+        self.broadcasting_posts.retain(|_, sessions| {
+            sessions.retain(|id| active.contains_key(id));
+            !sessions.is_empty()
+        });
+        self.user_connections.retain(|_, sessions| {
+            sessions.retain(|id| active.contains_key(id));
+            !sessions.is_empty()
+        });
+    }
 }
 
 impl Actor for RtServer {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.config.room_gc_interval, |act, _| {
+            act.collect_garbage();
+        });
+    }
 }
 
 impl Handler<Connect> for RtServer {
-    type Result = ();
+    type Result = bool;
     fn handle(&mut self, event: Connect, _: &mut Self::Context) -> Self::Result {
+        let user_id = event.notif.user.id;
+        let connections = self.user_connections.entry(user_id).or_default();
+        if connections.len() >= self.config.max_connections_per_user {
+            return false;
+        }
+        connections.insert(event.id.clone());
+
         self.active_broadcasts.insert(event.id.clone(), event.addr);
         self.broadcasting_posts
             .entry(event.post_id)
             .or_insert_with(HashSet::new)
             .insert(event.id);
         self.notify_connect(event.post_id, event.notif);
+        true
     }
 }
 
@@ -113,10 +235,22 @@ impl Handler<Disconnect> for RtServer {
     type Result = ();
 
     fn handle(&mut self, msg: Disconnect, _: &mut Self::Context) -> Self::Result {
-        self.active_broadcasts.remove(&msg.id);
-        self.broadcasting_posts.entry(msg.post_id).and_modify(|x| {
-            x.remove(&msg.id);
-        });
+        // Rejected or already disconnected sessions were never announced
+        if self.active_broadcasts.remove(&msg.id).is_none() {
+            return;
+        }
+        if let Some(sessions) = self.broadcasting_posts.get_mut(&msg.post_id) {
+            sessions.remove(&msg.id);
+            if sessions.is_empty() {
+                self.broadcasting_posts.remove(&msg.post_id);
+            }
+        }
+        if let Some(sessions) = self.user_connections.get_mut(&msg.notif.id) {
+            sessions.remove(&msg.id);
+            if sessions.is_empty() {
+                self.user_connections.remove(&msg.notif.id);
+            }
+        }
         self.notify_disconnect(msg.post_id, msg.notif);
     }
 }
@@ -129,6 +263,20 @@ impl Handler<InComment> for RtServer {
     }
 }
 
+impl Handler<KickUser> for RtServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: KickUser, _: &mut Self::Context) {
+        if let Some(connections) = self.user_connections.get(&msg.user_id) {
+            for id in connections {
+                if let Some(addr) = self.active_broadcasts.get(id) {
+                    addr.do_send(OutPacket::Kick(msg.session_id.clone()));
+                }
+            }
+        }
+    }
+}
+
 impl Handler<ListActiveUsers> for RtServer {
     type Result = Vec<ActiveUser>;
 
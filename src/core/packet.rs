@@ -2,7 +2,7 @@ use actix::prelude::*;
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
-use crate::db::models::{FileList, MaybeEmptyFile};
+use crate::db::models::{conversation::Message as DirectMessage, FileList, MaybeEmptyFile};
 
 #[derive(Clone, Debug, Deserialize)]
 pub enum InPacket {
@@ -45,6 +45,7 @@ pub struct OutComment {
     pub forum_id: i32,
     pub parent_id: Option<i32>,
     pub content: String,
+    pub content_html: Option<String>,
     pub media: FileList,
 }
 
@@ -55,7 +56,17 @@ pub enum OutPacket {
     DisconnectNotification(DisconnectNotification),
     OutComment(OutComment),
     ActiveUserList(Vec<ActiveUser>),
+    Error(ErrorPacket),
     Identify,
+    /// Closes the connection if it was opened with this login session, or any with `None`
+    Kick(Option<String>),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorPacket {
+    pub code: u16,
+    pub message: String,
+    pub retry_after: Option<u64>,
 }
 
 #[derive(Clone, Debug, Message, Serialize)]
@@ -70,8 +81,9 @@ pub struct DisconnectNotification {
     pub id: i32,
 }
 
+/// Resolves to `false` if the server refused the connection
 #[derive(Debug, Message)]
-#[rtype(result = "()")]
+#[rtype(result = "bool")]
 pub struct Connect {
     pub addr: Recipient<OutPacket>,
     pub id: String,
@@ -92,3 +104,63 @@ pub struct Disconnect {
 pub struct ListActiveUsers {
     pub post_id: i32,
 }
+
+/// Closes connections (comment and dm ones) of a user whose login session was revoked,
+/// all of them without `session_id`
+#[derive(Clone, Debug, Message)]
+#[rtype(result = "()")]
+pub struct KickUser {
+    pub user_id: i32,
+    pub session_id: Option<String>,
+}
+
+// Direct messages
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum DmInPacket {
+    Message { content: String, media: FileList },
+}
+
+#[derive(Debug, Message, Serialize)]
+#[rtype(result = "()")]
+pub enum DmOutPacket {
+    Message(DirectMessage),
+    Error(ErrorPacket),
+    /// Same as [`OutPacket::Kick`]
+    Kick(Option<String>),
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "bool")]
+pub struct DmConnect {
+    pub addr: Recipient<DmOutPacket>,
+    pub id: String,
+    pub user_id: i32,
+    pub conversation_id: i32,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct DmDisconnect {
+    pub id: String,
+    pub user_id: i32,
+    pub conversation_id: i32,
+}
+
+/// A message sent over a dm socket, `session_id` receives any errors
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct InMessage {
+    pub session_id: String,
+    pub sender_id: i32,
+    pub conversation_id: i32,
+    pub content: String,
+    pub media: FileList,
+}
+
+/// A message that was already stored (eg. through gql) and only needs delivering
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct DmBroadcast {
+    pub message: DirectMessage,
+}
@@ -4,31 +4,38 @@ use actix::prelude::*;
 use actix::{Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Handler, WrapFuture};
 use actix_web_actors::ws::{self, WebsocketContext};
 
-use super::packet::{InComment, InPacket, ListActiveUsers};
+use super::packet::{ErrorPacket, InComment, InPacket, ListActiveUsers};
 use super::{
     packet::{
         ActiveUser, Connect, ConnectNotification, Disconnect, DisconnectNotification, OutPacket,
     },
     RtServer,
 };
-
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-const SESSION_TIMEOUT: Duration = Duration::from_secs(10);
+use crate::db::models::role::{Permission, SiteRole};
+use crate::ratelimit::{Action, RateKey, RateLimiter};
 
 #[derive(Debug)]
 pub struct RtSession {
     pub id: String,
     pub hb: Instant,
+    pub heartbeat_interval: Duration,
+    pub session_timeout: Duration,
     pub post_id: i32,
     pub forum_id: i32,
     pub user: ActiveUser,
+    pub role: SiteRole,
+    /// Api tokens without the post scope can only watch
+    pub can_post: bool,
+    /// Login session the connection was opened with, `None` for api tokens
+    pub login_session: Option<String>,
     pub addr: Addr<RtServer>,
+    pub limiter: RateLimiter,
 }
 
 impl RtSession {
     fn hb(&self, ctx: &mut WebsocketContext<Self>) {
-        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
-            if Instant::now().duration_since(act.hb) > SESSION_TIMEOUT {
+        ctx.run_interval(self.heartbeat_interval, |act, ctx| {
+            if Instant::now().duration_since(act.hb) > act.session_timeout {
                 act.addr.do_send(Disconnect {
                     id: act.id.clone(),
                     post_id: act.post_id,
@@ -40,6 +47,18 @@ impl RtSession {
             ctx.ping(b"");
         });
     }
+
+    /// Limits the user across all of their connections
+    fn check_rate_limit(&self, action: Action) -> Result<(), ErrorPacket> {
+        let elevated = self.role.has_permission(Permission::ElevatedRateLimits);
+        self.limiter
+            .check(RateKey::User(self.user.id), action, elevated)
+            .map_err(|e| ErrorPacket {
+                code: 429,
+                message: e.to_string(),
+                retry_after: Some(e.retry_after_secs()),
+            })
+    }
 }
 
 impl Actor for RtSession {
@@ -60,9 +79,22 @@ impl Actor for RtSession {
                 },
             })
             .into_actor(self)
-            .then(|res, _, ctx| {
+            .then(|res, act, ctx| {
                 match res {
-                    Ok(_) => {}
+                    Ok(true) => {}
+                    Ok(false) => {
+                        actix::Handler::handle(
+                            act,
+                            OutPacket::Error(ErrorPacket {
+                                code: 429,
+                                message: "Too many open connections".into(),
+                                retry_after: None,
+                            }),
+                            ctx,
+                        );
+                        ctx.close(Some(ws::CloseCode::Policy.into()));
+                        ctx.stop();
+                    }
                     _ => ctx.stop(),
                 }
                 fut::ready(())
@@ -88,6 +120,13 @@ impl Handler<OutPacket> for RtSession {
             OutPacket::Identify => {
                 return Some(self.user.clone());
             }
+            OutPacket::Kick(session_id) => {
+                if session_id.is_none() || session_id == self.login_session {
+                    ctx.close(Some(ws::CloseCode::Policy.into()));
+                    ctx.stop();
+                }
+                return None;
+            }
             x => x,
         };
         let msg = serde_json::to_string(&msg);
@@ -123,6 +162,22 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for RtSession {
                             content,
                             media,
                         } => {
+                            if !self.can_post {
+                                actix::Handler::handle(
+                                    self,
+                                    OutPacket::Error(ErrorPacket {
+                                        code: 403,
+                                        message: "Api token is missing the Post scope".into(),
+                                        retry_after: None,
+                                    }),
+                                    ctx,
+                                );
+                                return;
+                            }
+                            if let Err(e) = self.check_rate_limit(Action::Comment) {
+                                actix::Handler::handle(self, OutPacket::Error(e), ctx);
+                                return;
+                            }
                             self.addr.do_send(InComment {
                                 user: self.user.clone(),
                                 post_id: self.post_id,
@@ -0,0 +1,38 @@
+use async_graphql::{Enum, SimpleObject};
+use chrono::NaiveDateTime;
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+
+/// What a request made with an api token may do. Queries and subscriptions need `Read`,
+/// mutations need `Post` and roles above `User` additionally need `Moderate`
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, sqlx::Type)]
+#[sqlx(type_name = "api_scope", rename_all = "snake_case")]
+pub enum ApiScope {
+    Read,
+    Post,
+    Moderate,
+}
+
+impl PgHasArrayType for ApiScope {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_api_scope")
+    }
+}
+
+/// Personal access token for bots and non-browser clients, only its hash is stored
+#[derive(SimpleObject, Clone, Debug)]
+pub struct ApiToken {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Returned once on creation, the token can't be looked up later
+#[derive(SimpleObject, Clone, Debug)]
+pub struct CreatedApiToken {
+    /// Send as `Authorization: Bearer <token>`
+    pub token: String,
+    pub api_token: ApiToken,
+}
@@ -1,10 +1,19 @@
-use async_graphql::SimpleObject;
+use async_graphql::{ComplexObject, Context, Result, SimpleObject};
 use chrono::NaiveDateTime;
 use sqlx::FromRow;
 
-use super::{user::User, FileList};
+use super::{forum::Forum, revision::CommentRevision, user::User, FileList};
+use crate::gql::{
+    mutation::mention::MentionSource,
+    query::{
+        mention::{get_linked_forums, get_mentioned_users},
+        revision::get_comment_revisions,
+    },
+};
+use crate::markdown;
 
 #[derive(Clone, Debug, SimpleObject, FromRow)]
+#[graphql(complex)]
 pub struct Comment {
     pub id: i32,
     pub user_id: i32,
@@ -17,6 +26,36 @@ pub struct Comment {
     pub created_at: NaiveDateTime,
     pub edited: bool,
     pub edited_at: Option<NaiveDateTime>,
+    #[graphql(skip)]
+    pub content_html: Option<String>,
+}
+
+#[ComplexObject]
+impl Comment {
+    /// Content rendered from markdown and sanitized
+    async fn content_html(&self) -> String {
+        self.content_html
+            .clone()
+            .unwrap_or_else(|| markdown::render(&self.content))
+    }
+
+    /// Previous versions, newest first
+    async fn revisions<'c>(&self, ctx: &Context<'c>) -> Result<Vec<CommentRevision>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(get_comment_revisions(self.id, pool).await?)
+    }
+
+    /// Existing users @mentioned in the content
+    async fn mentions<'c>(&self, ctx: &Context<'c>) -> Result<Vec<User>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(get_mentioned_users(MentionSource::Comment(self.id), pool).await?)
+    }
+
+    /// Existing forums linked with f/name in the content
+    async fn forum_links<'c>(&self, ctx: &Context<'c>) -> Result<Vec<Forum>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(get_linked_forums(MentionSource::Comment(self.id), pool).await?)
+    }
 }
 
 #[derive(Debug)]
@@ -30,6 +69,7 @@ pub struct UpdateComment {
 }
 
 #[derive(SimpleObject)]
+#[graphql(complex)]
 pub struct CommentHierarchy {
     pub id: i32,
     pub user_id: i32,
@@ -41,10 +81,40 @@ pub struct CommentHierarchy {
     pub created_at: NaiveDateTime,
     pub edited: bool,
     pub edited_at: Option<NaiveDateTime>,
+    #[graphql(skip)]
+    pub content_html: Option<String>,
     pub user: User,
     pub child_comments: Option<Vec<CommentHierarchy>>,
 }
 
+#[ComplexObject]
+impl CommentHierarchy {
+    /// Content rendered from markdown and sanitized
+    async fn content_html(&self) -> String {
+        self.content_html
+            .clone()
+            .unwrap_or_else(|| markdown::render(&self.content))
+    }
+
+    /// Previous versions, newest first
+    async fn revisions<'c>(&self, ctx: &Context<'c>) -> Result<Vec<CommentRevision>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(get_comment_revisions(self.id, pool).await?)
+    }
+
+    /// Existing users @mentioned in the content
+    async fn mentions<'c>(&self, ctx: &Context<'c>) -> Result<Vec<User>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(get_mentioned_users(MentionSource::Comment(self.id), pool).await?)
+    }
+
+    /// Existing forums linked with f/name in the content
+    async fn forum_links<'c>(&self, ctx: &Context<'c>) -> Result<Vec<Forum>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(get_linked_forums(MentionSource::Comment(self.id), pool).await?)
+    }
+}
+
 impl CommentHierarchy {
     pub fn load_hierarchy(
         comments: &Vec<(Comment, User)>,
@@ -64,6 +134,7 @@ impl CommentHierarchy {
                 created_at: comment.0.created_at,
                 edited: comment.0.edited,
                 edited_at: comment.0.edited_at,
+                content_html: comment.0.content_html.clone(),
                 user: comment.1.clone(),
                 child_comments: Some(CommentHierarchy::load_hierarchy(
                     comments,
@@ -81,5 +152,6 @@ pub struct NewComment {
     pub forum_id: i32,
     pub parent_id: Option<i32>,
     pub content: String,
+    pub content_html: String,
     pub media: Option<Vec<String>>,
 }
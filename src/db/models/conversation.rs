@@ -0,0 +1,33 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use super::FileList;
+
+#[derive(Clone, Debug, SimpleObject, FromRow)]
+pub struct Conversation {
+    pub id: i32,
+    pub title: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_message_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, SimpleObject, Serialize, FromRow)]
+pub struct Message {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub sender_id: i32,
+    pub content: String,
+    #[sqlx(try_from = "Option<Vec<String>>")]
+    pub media: FileList,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug)]
+pub struct NewMessage {
+    pub conversation_id: i32,
+    pub sender_id: i32,
+    pub content: String,
+    pub media: Option<Vec<String>>,
+}
@@ -0,0 +1,9 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+
+/// Archive of the data of a user, downloadable from `url` until `expires_at`
+#[derive(SimpleObject, Clone, Debug)]
+pub struct DataExport {
+    pub url: String,
+    pub expires_at: NaiveDateTime,
+}
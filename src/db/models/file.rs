@@ -1,10 +1,12 @@
-use async_graphql::{ComplexObject, SimpleObject};
+use async_graphql::{ComplexObject, Context, Enum, Result, SimpleObject};
+use chrono::NaiveDateTime;
 use opendal::Operator;
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 
-use std::{io::prelude::*, path::Path};
-
-use crate::constants::CDN_PATH;
+use super::user::User;
+use crate::gql::query::file::{get_file, get_renditions};
+use crate::media::signing::UrlSigner;
 
 #[derive(Debug, Clone, SimpleObject, Deserialize, Serialize)]
 #[graphql(complex)]
@@ -17,6 +19,91 @@ pub struct FileList {
     pub files: Option<Vec<MaybeEmptyFile>>,
 }
 
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "rendition_kind", rename_all = "snake_case")]
+pub enum RenditionKind {
+    /// Upright and stripped of metadata, in full size
+    Original,
+    Medium,
+    Thumbnail,
+    /// Still frame of a video
+    Poster,
+}
+
+impl RenditionKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Original => "original",
+            Self::Medium => "medium",
+            Self::Thumbnail => "thumbnail",
+            Self::Poster => "poster",
+        }
+    }
+}
+
+/// How clients should render a file
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "media_kind", rename_all = "snake_case")]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+    Other,
+}
+
+impl MediaKind {
+    /// For files that weren't probed
+    pub fn from_mime(mime: &str) -> Self {
+        match mime.split('/').next() {
+            Some("image") => Self::Image,
+            Some("video") => Self::Video,
+            Some("audio") => Self::Audio,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject, FromRow)]
+#[graphql(complex)]
+pub struct Rendition {
+    pub kind: RenditionKind,
+    #[graphql(skip)]
+    pub path: String,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[ComplexObject]
+impl Rendition {
+    /// Signed url that includes the CDN prefix, it expires after a while
+    async fn absolute_path<'c>(&self, ctx: &Context<'c>) -> Result<String> {
+        Ok(ctx.data::<UrlSigner>()?.sign(&self.path))
+    }
+}
+
+/// What's known about an upload
+#[derive(Debug, Clone, FromRow)]
+pub struct FileRecord {
+    pub owner_id: i32,
+    pub size: i64,
+    pub mime: String,
+    pub kind: MediaKind,
+    pub duration: Option<f64>,
+    pub codec: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug)]
+pub struct NewFile {
+    pub id: String,
+    pub owner_id: i32,
+    pub size: i64,
+    pub mime: String,
+    pub kind: MediaKind,
+    pub duration: Option<f64>,
+    pub codec: Option<String>,
+}
+
 pub enum FileStatus {
     Empty,
     Exists,
@@ -41,12 +128,91 @@ impl FileStatus {
 
 #[ComplexObject]
 impl MaybeEmptyFile {
-    /// Returns path that includes the CDN prefix
-    async fn absolute_path(&self) -> Option<String> {
+    /// Signed url that includes the CDN prefix, it expires after a while
+    async fn absolute_path<'c>(&self, ctx: &Context<'c>) -> Result<Option<String>> {
+        if let Some(id) = &self.id {
+            return Ok(Some(ctx.data::<UrlSigner>()?.sign(id)));
+        }
+        Ok(None)
+    }
+
+    /// Size in bytes of the stored file (the original for images)
+    async fn size<'c>(&self, ctx: &Context<'c>) -> Result<Option<i64>> {
+        Ok(self.record(ctx).await?.map(|f| f.size))
+    }
+
+    /// Content type sniffed from the upload
+    async fn mime_type<'c>(&self, ctx: &Context<'c>) -> Result<Option<String>> {
+        Ok(self.record(ctx).await?.map(|f| f.mime))
+    }
+
+    async fn kind<'c>(&self, ctx: &Context<'c>) -> Result<Option<MediaKind>> {
+        Ok(self.record(ctx).await?.map(|f| f.kind))
+    }
+
+    /// Seconds of audio and video
+    async fn duration<'c>(&self, ctx: &Context<'c>) -> Result<Option<f64>> {
+        Ok(self.record(ctx).await?.and_then(|f| f.duration))
+    }
+
+    /// Codecs of the audio and video streams, comma separated
+    async fn codec<'c>(&self, ctx: &Context<'c>) -> Result<Option<String>> {
+        Ok(self.record(ctx).await?.and_then(|f| f.codec))
+    }
+
+    async fn uploader<'c>(&self, ctx: &Context<'c>) -> Result<Option<User>> {
+        if let Some(file) = self.record(ctx).await? {
+            let pool = ctx.data::<crate::Pool>()?;
+            let user = sqlx::query_as!(User, "SELECT * FROM users WHERE id = $1", file.owner_id)
+                .fetch_one(pool)
+                .await?;
+            return Ok(Some(user));
+        }
+        Ok(None)
+    }
+
+    async fn uploaded_at<'c>(&self, ctx: &Context<'c>) -> Result<Option<NaiveDateTime>> {
+        Ok(self.record(ctx).await?.map(|f| f.created_at))
+    }
+
+    /// Width of images, null for other files
+    async fn width<'c>(&self, ctx: &Context<'c>) -> Result<Option<i32>> {
+        Ok(self
+            .rendition(ctx, RenditionKind::Original)
+            .await?
+            .map(|r| r.width))
+    }
+
+    /// Height of images, null for other files
+    async fn height<'c>(&self, ctx: &Context<'c>) -> Result<Option<i32>> {
+        Ok(self
+            .rendition(ctx, RenditionKind::Original)
+            .await?
+            .map(|r| r.height))
+    }
+
+    /// Every stored size of an image and the poster of videos, empty for other files
+    async fn renditions<'c>(&self, ctx: &Context<'c>) -> Result<Vec<Rendition>> {
         if let Some(id) = &self.id {
-            return Some(format!("{}/{}", CDN_PATH, id));
+            let pool = ctx.data::<crate::Pool>()?;
+            return Ok(get_renditions(id, pool).await?);
         }
-        None
+        Ok(vec![])
+    }
+
+    /// Falls back to the original for images smaller than the requested size
+    async fn rendition<'c>(
+        &self,
+        ctx: &Context<'c>,
+        kind: RenditionKind,
+    ) -> Result<Option<Rendition>> {
+        let mut renditions = self.renditions(ctx).await?;
+        let pos = renditions.iter().position(|r| r.kind == kind).or_else(|| {
+            renditions
+                .iter()
+                .position(|r| r.kind == RenditionKind::Original)
+        });
+        Ok(pos.map(|pos| renditions.swap_remove(pos)))
     }
 }
 
@@ -80,11 +246,17 @@ impl MaybeEmptyFile {
         Self { id: None }
     }
 
-    pub async fn status(&self) -> anyhow::Result<FileStatus> {
+    /// Files only exist for the user that uploaded them
+    pub async fn status(&self, owner_id: i32, pool: &crate::Pool) -> anyhow::Result<FileStatus> {
         if let Some(id) = &self.id {
-            let loc = &format!("./data/{}", id);
-            let path = Path::new(&loc);
-            let exists = tokio::fs::metadata(path).await.map(|m| m.is_file())?;
+            let exists = sqlx::query_scalar!(
+                "SELECT EXISTS(SELECT 1 FROM files WHERE id = $1 AND owner_id = $2);",
+                id,
+                owner_id,
+            )
+            .fetch_one(pool)
+            .await?
+            .unwrap_or(false);
 
             return match exists {
                 true => Ok(FileStatus::Exists),
@@ -95,11 +267,17 @@ impl MaybeEmptyFile {
         }
     }
 
-    pub async fn save(&self, fp: &mut std::fs::File, op: &Operator) -> anyhow::Result<()> {
+    async fn record(&self, ctx: &Context<'_>) -> Result<Option<FileRecord>> {
         if let Some(id) = &self.id {
-            let mut buffer = Vec::with_capacity(fp.metadata()?.len() as usize);
-            fp.read_to_end(&mut buffer)?;
-            op.write(id, buffer).await?;
+            let pool = ctx.data::<crate::Pool>()?;
+            return Ok(get_file(id, pool).await?);
+        }
+        Ok(None)
+    }
+
+    pub async fn save(&self, bytes: Vec<u8>, op: &Operator) -> anyhow::Result<()> {
+        if let Some(id) = &self.id {
+            op.write(id, bytes).await?;
             return Ok(());
         }
         Err(anyhow::Error::msg("File is empty"))
@@ -119,6 +297,21 @@ impl FileList {
         Self { files: None }
     }
 
+    /// Errors on the first file that wasn't uploaded by `owner_id`
+    pub async fn check_insertable(&self, owner_id: i32, pool: &crate::Pool) -> anyhow::Result<()> {
+        if let Some(files) = &self.files {
+            for file in files {
+                if !file.status(owner_id, pool).await?.insertable() {
+                    return Err(anyhow::Error::msg(format!(
+                        "File with id: {} doesn't exist (media upload)",
+                        &file.id.clone().unwrap_or("null".into())
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn ids(&self) -> Option<Vec<String>> {
         let files = &self.files;
         let x = files.as_ref().map(|x| {
@@ -0,0 +1,20 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+
+/// What failed logins are counted by
+#[derive(Copy, Clone, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "login_failure_kind", rename_all = "snake_case")]
+pub enum LoginFailureKind {
+    Username,
+    Ip,
+}
+
+/// Account that can't log in until `locked_until` because of failed attempts
+#[derive(SimpleObject, Clone, Debug)]
+pub struct LockedAccount {
+    pub user_id: i32,
+    pub username: String,
+    pub failures: i32,
+    pub last_failed_at: NaiveDateTime,
+    pub locked_until: NaiveDateTime,
+}
@@ -1,7 +1,20 @@
+pub mod api_token;
 pub mod comment;
+pub mod conversation;
+pub mod export;
 pub mod file;
 pub mod forum;
+pub mod login_failure;
+pub mod notification;
+pub mod oidc;
 pub mod post;
+pub mod revision;
+pub mod role;
+pub mod session;
+pub mod settings;
+pub mod token;
+pub mod two_factor;
+pub mod upload;
 pub mod user;
 
 pub use file::FileList;
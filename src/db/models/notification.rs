@@ -0,0 +1,48 @@
+use async_graphql::{ComplexObject, Context, Enum, Result, SimpleObject};
+use chrono::NaiveDateTime;
+use sqlx::FromRow;
+
+use super::user::User;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "notification_kind", rename_all = "snake_case")]
+pub enum NotificationKind {
+    PostReply,
+    CommentReply,
+    Mention,
+    Star,
+}
+
+#[derive(Clone, Debug, SimpleObject, FromRow)]
+#[graphql(complex)]
+pub struct Notification {
+    pub id: i32,
+    pub user_id: i32,
+    pub actor_id: i32,
+    pub kind: NotificationKind,
+    pub post_id: Option<i32>,
+    pub comment_id: Option<i32>,
+    pub read: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[ComplexObject]
+impl Notification {
+    /// The user that replied, mentioned or starred
+    async fn actor<'c>(&self, ctx: &Context<'c>) -> Result<User> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let user = sqlx::query_as!(User, "SELECT * FROM users WHERE id = $1", self.actor_id)
+            .fetch_one(pool)
+            .await?;
+        Ok(user)
+    }
+}
+
+#[derive(Debug)]
+pub struct NewNotification {
+    pub user_id: i32,
+    pub actor_id: i32,
+    pub kind: NotificationKind,
+    pub post_id: Option<i32>,
+    pub comment_id: Option<i32>,
+}
@@ -0,0 +1,12 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+
+/// Account of an OpenID Connect provider linked to a user
+#[derive(SimpleObject, Clone, Debug)]
+pub struct OidcIdentity {
+    pub issuer: String,
+    pub subject: String,
+    /// Email the provider had for the account when it was linked
+    pub email: Option<String>,
+    pub created_at: NaiveDateTime,
+}
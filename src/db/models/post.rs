@@ -1,12 +1,21 @@
-use async_graphql::{InputObject, SimpleObject};
+use async_graphql::{ComplexObject, Context, InputObject, Result, SimpleObject};
 use chrono::NaiveDateTime;
 use sqlx::FromRow;
 use tantivy::{doc, Document};
 
-use super::FileList;
+use super::{forum::Forum, revision::PostRevision, user::User, FileList};
+use crate::gql::{
+    mutation::mention::MentionSource,
+    query::{
+        mention::{get_linked_forums, get_mentioned_users},
+        revision::get_post_revisions,
+    },
+};
+use crate::markdown;
 use crate::search::ToDoc;
 
 #[derive(Clone, Debug, SimpleObject, FromRow)]
+#[graphql(complex)]
 pub struct Post {
     pub id: i32,
     pub tags: Option<Vec<String>>,
@@ -21,6 +30,36 @@ pub struct Post {
     pub edited_at: Option<NaiveDateTime>,
     pub forum_id: i32,
     pub poster_id: i32,
+    #[graphql(skip)]
+    pub content_html: Option<String>,
+}
+
+#[ComplexObject]
+impl Post {
+    /// Content rendered from markdown and sanitized
+    async fn content_html(&self) -> Option<String> {
+        self.content_html
+            .clone()
+            .or_else(|| self.content.as_deref().map(markdown::render))
+    }
+
+    /// Previous versions, newest first
+    async fn revisions<'c>(&self, ctx: &Context<'c>) -> Result<Vec<PostRevision>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(get_post_revisions(self.id, pool).await?)
+    }
+
+    /// Existing users @mentioned in the content
+    async fn mentions<'c>(&self, ctx: &Context<'c>) -> Result<Vec<User>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(get_mentioned_users(MentionSource::Post(self.id), pool).await?)
+    }
+
+    /// Existing forums linked with f/name in the content
+    async fn forum_links<'c>(&self, ctx: &Context<'c>) -> Result<Vec<Forum>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(get_linked_forums(MentionSource::Post(self.id), pool).await?)
+    }
 }
 
 #[derive(Debug)]
@@ -43,6 +82,7 @@ pub struct NewPost {
     pub title: String,
     pub slug: String,
     pub content: Option<String>,
+    pub content_html: Option<String>,
     pub media: Option<Vec<String>>,
     pub forum_id: i32,
     pub poster_id: i32,
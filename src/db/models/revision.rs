@@ -0,0 +1,35 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+use sqlx::FromRow;
+
+use super::FileList;
+
+/// State of a post before one of its edits
+#[derive(Clone, Debug, SimpleObject, FromRow)]
+pub struct PostRevision {
+    pub id: i32,
+    pub post_id: i32,
+    pub title: String,
+    pub tags: Option<Vec<String>>,
+    pub content: Option<String>,
+    #[sqlx(try_from = "Option<Vec<String>>")]
+    pub media: FileList,
+    /// When this version was posted or edited in
+    pub created_at: NaiveDateTime,
+    /// When this version was replaced
+    pub archived_at: NaiveDateTime,
+}
+
+/// State of a comment before one of its edits
+#[derive(Clone, Debug, SimpleObject, FromRow)]
+pub struct CommentRevision {
+    pub id: i32,
+    pub comment_id: i32,
+    pub content: String,
+    #[sqlx(try_from = "Option<Vec<String>>")]
+    pub media: FileList,
+    /// When this version was posted or edited in
+    pub created_at: NaiveDateTime,
+    /// When this version was replaced
+    pub archived_at: NaiveDateTime,
+}
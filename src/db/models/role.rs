@@ -0,0 +1,73 @@
+use async_graphql::{Enum, SimpleObject};
+use chrono::NaiveDateTime;
+
+/// Role of a user on the whole site, everyone without one is a `User`
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, sqlx::Type)]
+#[sqlx(type_name = "site_role", rename_all = "snake_case")]
+pub enum SiteRole {
+    User,
+    /// Moderates every forum and locked accounts
+    Moderator,
+    Admin,
+}
+
+/// Something only some site roles may do, checked with `PermissionGuard`
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Permission {
+    /// Site settings and media sweeps
+    ManageSite,
+    /// Granting and revoking site roles
+    ManageRoles,
+    /// Listing and unlocking accounts locked by failed logins
+    ManageLockedAccounts,
+    /// Acting as a moderator of every forum
+    ModerateForums,
+    /// The `_ADMIN` rate limits instead of the user ones
+    ElevatedRateLimits,
+}
+
+impl SiteRole {
+    pub fn has_permission(self, permission: Permission) -> bool {
+        match permission {
+            Permission::ManageSite | Permission::ManageRoles => self >= Self::Admin,
+            Permission::ManageLockedAccounts
+            | Permission::ModerateForums
+            | Permission::ElevatedRateLimits => self >= Self::Moderator,
+        }
+    }
+}
+
+/// Role of a user in one forum, the owner is always its `Admin`
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, sqlx::Type)]
+#[sqlx(type_name = "forum_role", rename_all = "snake_case")]
+pub enum ForumRole {
+    /// Edits posts and comments of others
+    Moderator,
+    /// Edits the forum and grants forum roles
+    Admin,
+}
+
+/// Something only some forum roles may do, checked with `ForumGuard`
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ForumPermission {
+    Moderate,
+    Manage,
+}
+
+impl ForumRole {
+    pub fn has_permission(self, permission: ForumPermission) -> bool {
+        match permission {
+            ForumPermission::Moderate => self >= Self::Moderator,
+            ForumPermission::Manage => self >= Self::Admin,
+        }
+    }
+}
+
+/// User with a role in a forum, owners aren't listed
+#[derive(SimpleObject, Clone, Debug)]
+pub struct ForumMember {
+    pub forum_id: i32,
+    pub user_id: i32,
+    pub role: ForumRole,
+    pub created_at: NaiveDateTime,
+}
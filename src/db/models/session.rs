@@ -0,0 +1,14 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+
+/// Logged in browser session, the session itself is kept in redis
+#[derive(SimpleObject, Clone, Debug)]
+pub struct UserSession {
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    /// The session this request was made with
+    pub current: bool,
+}
@@ -0,0 +1,8 @@
+use async_graphql::SimpleObject;
+
+/// Site wide switches admins can flip without a restart
+#[derive(SimpleObject, Clone, Debug, sqlx::FromRow)]
+pub struct SiteSettings {
+    /// Users need a verified email before they can post or comment
+    pub require_verified_email: bool,
+}
@@ -0,0 +1,8 @@
+/// What a mailed token can be used for, each token works for one purpose only
+#[derive(Copy, Clone, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "token_purpose", rename_all = "snake_case")]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+    LoginChallenge,
+}
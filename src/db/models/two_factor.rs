@@ -0,0 +1,27 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+
+use super::user::User;
+
+#[derive(Clone, Debug)]
+pub struct TotpSecret {
+    /// Base32, like authenticator apps take it
+    pub secret: String,
+    /// Two factor authentication is only enabled once a code was confirmed
+    pub confirmed_at: Option<NaiveDateTime>,
+}
+
+/// Secret to add to an authenticator app, enabled with `confirmTotp`
+#[derive(SimpleObject, Clone, Debug)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    /// `otpauth://` uri for QR codes
+    pub uri: String,
+}
+
+/// Either the logged in user, or a challenge to pass to `completeLogin` with a code
+#[derive(SimpleObject, Clone, Debug)]
+pub struct LoginResponse {
+    pub user: Option<User>,
+    pub challenge: Option<String>,
+}
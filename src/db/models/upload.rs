@@ -0,0 +1,54 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+use sqlx::FromRow;
+
+/// A file being uploaded in chunks, chunks can be sent in any order and again after
+/// a disconnect until the upload is completed
+#[derive(Debug, Clone, SimpleObject, FromRow)]
+pub struct UploadSession {
+    pub id: String,
+    #[graphql(skip)]
+    pub owner_id: i32,
+    pub filename: String,
+    /// Total size in bytes
+    pub size: i64,
+    /// Every chunk but the last one has exactly this size
+    pub chunk_size: i32,
+    pub chunk_count: i32,
+    /// Indexes of the chunks already stored
+    pub received: Vec<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl UploadSession {
+    /// Size the chunk at `index` must have
+    pub fn chunk_len(&self, index: i32) -> i64 {
+        match index == self.chunk_count - 1 {
+            true => self.size - self.chunk_size as i64 * index as i64,
+            false => self.chunk_size as i64,
+        }
+    }
+
+    /// Where the chunk is kept until the upload is completed
+    pub fn chunk_path(&self, index: i32) -> String {
+        format!("{}{}", self.chunk_dir(), index)
+    }
+
+    pub fn chunk_dir(&self) -> String {
+        format!("uploads/{}/", self.id)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received.len() as i32 == self.chunk_count
+    }
+}
+
+#[derive(Debug)]
+pub struct NewUploadSession {
+    pub owner_id: i32,
+    pub filename: String,
+    pub size: i64,
+    pub chunk_size: i32,
+    pub chunk_count: i32,
+}
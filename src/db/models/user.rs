@@ -1,12 +1,17 @@
-use crate::{db::models::MaybeEmptyFile, search::ToDoc};
+use crate::{
+    db::models::{role::SiteRole, MaybeEmptyFile},
+    gql::query::role::SiteRoleLoader,
+    search::ToDoc,
+};
 
-use async_graphql::SimpleObject;
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, Result, SimpleObject};
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use tantivy::{doc, Document};
 
 #[derive(Clone, Debug, SimpleObject, Deserialize, Serialize, FromRow)]
+#[graphql(complex)]
 pub struct User {
     pub id: i32,
     pub username: String,
@@ -22,7 +27,19 @@ pub struct User {
     #[graphql(skip)]
     // Not currently in use
     pub v: i32,
-    pub admin: bool,
+    #[graphql(skip)]
+    pub email: Option<String>,
+    #[graphql(skip)]
+    pub email_verified_at: Option<NaiveDateTime>,
+}
+
+#[ComplexObject]
+impl User {
+    /// Batched, lists of users don't make a query per user
+    async fn role<'c>(&self, ctx: &Context<'c>) -> Result<SiteRole> {
+        let loader = ctx.data::<DataLoader<SiteRoleLoader>>()?;
+        Ok(loader.load_one(self.id).await?.unwrap_or(SiteRole::User))
+    }
 }
 
 #[derive(Debug)]
@@ -34,7 +51,6 @@ pub struct UpdateUser {
     pub bio: Option<Option<String>>,
     pub pfp: Option<MaybeEmptyFile>,
     pub banner: Option<MaybeEmptyFile>,
-    pub admin: Option<bool>,
 }
 
 /// Represents a new user that will be inserted into the db
@@ -43,6 +59,7 @@ pub struct NewUser<'a> {
     pub username: &'a str,
     pub password: &'a str,
     pub display_name: &'a str,
+    pub email: Option<&'a str>,
 }
 
 #[derive(Debug)]
@@ -9,6 +9,9 @@ pub enum UserCreationError<'a> {
     PasswordTooShort(&'a str),
     PasswordTooLong(&'a str),
     LowPasswordStrength(&'a str),
+    // Email errors
+    InvalidEmail(&'a str),
+    EmailAlreadyExists(&'a str),
     // Db errors
     InternalError(&'a str),
 }
@@ -24,6 +27,8 @@ impl<'a> std::fmt::Display for UserCreationError<'a> {
             Self::PasswordTooShort(m) => write!(f, "{}", m),
             Self::PasswordTooLong(m) => write!(f, "{}", m),
             Self::LowPasswordStrength(m) => write!(f, "{}", m),
+            Self::InvalidEmail(m) => write!(f, "{}", m),
+            Self::EmailAlreadyExists(m) => write!(f, "{}", m),
             Self::InternalError(m) => write!(f, "{}", m),
         }
     }
@@ -34,6 +39,8 @@ impl<'a> std::fmt::Display for UserCreationError<'a> {
 pub enum UserAuthError<'a> {
     InvalidUsernameOrPassword(&'a str),
     UserNotFound(&'a str),
+    InvalidToken(&'a str),
+    InvalidTwoFactorCode(&'a str),
     // Db errors
     InternalError(&'a str),
 }
@@ -45,6 +52,8 @@ impl<'a> std::fmt::Display for UserAuthError<'a> {
         match *self {
             Self::InvalidUsernameOrPassword(m) => write!(f, "{}", m),
             Self::UserNotFound(m) => write!(f, "{}", m),
+            Self::InvalidToken(m) => write!(f, "{}", m),
+            Self::InvalidTwoFactorCode(m) => write!(f, "{}", m),
             Self::InternalError(m) => write!(f, "{}", m),
         }
     }
@@ -79,6 +88,7 @@ impl<'a> std::fmt::Display for ForumCreationError<'a> {
 pub enum PostCreationError<'a> {
     InternalError(&'a str),
     ForumNotFound(&'a str),
+    EmailNotVerified(&'a str),
 }
 
 impl<'a> std::error::Error for PostCreationError<'a> {}
@@ -88,6 +98,7 @@ impl<'a> std::fmt::Display for PostCreationError<'a> {
         match *self {
             Self::InternalError(m) => write!(f, "{}", m),
             Self::ForumNotFound(m) => write!(f, "{}", m),
+            Self::EmailNotVerified(m) => write!(f, "{}", m),
         }
     }
 }
@@ -0,0 +1,192 @@
+use chrono::{Datelike, NaiveDateTime, Timelike};
+
+/// Writes a zip archive entry by entry so it can be streamed to the storage. Entries are
+/// stored uncompressed, most media already is compressed. Without zip64 an archive is
+/// limited to 4 GiB and 65535 entries, far more than the upload quota allows
+pub struct ZipWriter {
+    offset: u64,
+    central: Vec<u8>,
+    entries: u16,
+    time: u16,
+    date: u16,
+}
+
+fn put_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+/// Names are utf-8
+const FLAGS: u16 = 1 << 11;
+const VERSION: u16 = 20;
+
+impl ZipWriter {
+    /// Every entry gets `modified` as its modification time
+    pub fn new(modified: NaiveDateTime) -> Self {
+        // Dos time and date, seconds are stored halved
+        let time = (modified.hour() << 11) | (modified.minute() << 5) | (modified.second() / 2);
+        let date = (((modified.year().max(1980) - 1980) as u32) << 9)
+            | (modified.month() << 5)
+            | modified.day();
+        Self {
+            offset: 0,
+            central: Vec::new(),
+            entries: 0,
+            time: time as u16,
+            date: date as u16,
+        }
+    }
+
+    /// Bytes of the entry `name` holding `data`, to be written in the order they're made
+    pub fn entry(&mut self, name: &str, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let size = u32::try_from(data.len())
+            .map_err(|_| anyhow::Error::msg(format!("{name} is too large for the archive")))?;
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| anyhow::Error::msg("Entry name is too long for the archive"))?;
+        let offset =
+            u32::try_from(self.offset).map_err(|_| anyhow::Error::msg("Archive is too large"))?;
+        self.entries = self
+            .entries
+            .checked_add(1)
+            .ok_or_else(|| anyhow::Error::msg("Archive has too many entries"))?;
+        let crc = crc32fast::hash(data);
+
+        let mut local = Vec::with_capacity(30 + name.len() + data.len());
+        put_u32(&mut local, 0x04034b50);
+        put_u16(&mut local, VERSION);
+        put_u16(&mut local, FLAGS);
+        put_u16(&mut local, 0);
+        put_u16(&mut local, self.time);
+        put_u16(&mut local, self.date);
+        put_u32(&mut local, crc);
+        put_u32(&mut local, size);
+        put_u32(&mut local, size);
+        put_u16(&mut local, name_len);
+        put_u16(&mut local, 0);
+        local.extend_from_slice(name.as_bytes());
+        local.extend_from_slice(data);
+
+        let central = &mut self.central;
+        put_u32(central, 0x02014b50);
+        put_u16(central, VERSION);
+        put_u16(central, VERSION);
+        put_u16(central, FLAGS);
+        put_u16(central, 0);
+        put_u16(central, self.time);
+        put_u16(central, self.date);
+        put_u32(central, crc);
+        put_u32(central, size);
+        put_u32(central, size);
+        put_u16(central, name_len);
+        // Extra field, comment, disk and attributes
+        put_u16(central, 0);
+        put_u16(central, 0);
+        put_u16(central, 0);
+        put_u16(central, 0);
+        put_u32(central, 0);
+        put_u32(central, offset);
+        central.extend_from_slice(name.as_bytes());
+
+        self.offset += local.len() as u64;
+        Ok(local)
+    }
+
+    /// The central directory, written after the last entry
+    pub fn finish(self) -> anyhow::Result<Vec<u8>> {
+        let offset =
+            u32::try_from(self.offset).map_err(|_| anyhow::Error::msg("Archive is too large"))?;
+        let mut end = self.central;
+        let size = end.len() as u32;
+        put_u32(&mut end, 0x06054b50);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, self.entries);
+        put_u16(&mut end, self.entries);
+        put_u32(&mut end, size);
+        put_u32(&mut end, offset);
+        put_u16(&mut end, 0);
+        Ok(end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn modified() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 8, 30)
+            .unwrap()
+            .and_hms_opt(14, 25, 37)
+            .unwrap()
+    }
+
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(modified());
+        let mut bytes = Vec::new();
+        for (name, data) in entries {
+            bytes.extend(writer.entry(name, data).unwrap());
+        }
+        bytes.extend(writer.finish().unwrap());
+        bytes
+    }
+
+    #[test]
+    fn archive_reads_back() {
+        let media = (0..70_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let entries: [(&str, &[u8]); 4] = [
+            ("profile.json", br#"{"username":"someone"}"#),
+            ("empty.txt", b""),
+            ("media/ünïcødé.bin", &media),
+            ("posts/1.json", b"[]"),
+        ];
+        let bytes = archive(&entries);
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(zip.len(), entries.len());
+        for (i, (name, data)) in entries.iter().enumerate() {
+            let mut file = zip.by_index(i).unwrap();
+            assert_eq!(file.name(), *name);
+            assert_eq!(file.compression(), zip::CompressionMethod::Stored);
+            assert_eq!(file.size(), data.len() as u64);
+            assert_eq!(file.crc32(), crc32fast::hash(data));
+            let modified = file.last_modified();
+            assert_eq!(
+                (modified.year(), modified.month(), modified.day()),
+                (2023, 8, 30)
+            );
+            // Seconds are rounded down to even ones
+            assert_eq!(
+                (modified.hour(), modified.minute(), modified.second()),
+                (14, 25, 36)
+            );
+            // Reading to the end checks the crc
+            let mut content = Vec::new();
+            file.read_to_end(&mut content).unwrap();
+            assert_eq!(content, *data);
+        }
+    }
+
+    #[test]
+    fn long_names_are_refused() {
+        let mut writer = ZipWriter::new(modified());
+        let name = "a".repeat(u16::MAX as usize + 1);
+        assert!(writer.entry(&name, b"data").is_err());
+        // Nothing was added for the refused entry
+        let bytes = writer.finish().unwrap();
+        let zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(zip.len(), 0);
+    }
+
+    #[test]
+    fn empty_archive_reads_back() {
+        let zip = zip::ZipArchive::new(Cursor::new(archive(&[]))).unwrap();
+        assert_eq!(zip.len(), 0);
+    }
+}
@@ -0,0 +1,209 @@
+use async_graphql::{Context, ErrorExtensions, Guard, Result};
+
+use crate::auth::SharedSession;
+use crate::constants;
+use crate::db::models::{
+    api_token::ApiScope,
+    role::{ForumPermission, Permission},
+};
+use crate::gql::query::role::{
+    get_comment_forum_id, get_forum_owner, get_forum_role, get_post_forum_id,
+};
+
+fn unauthenticated() -> async_graphql::Error {
+    async_graphql::Error::new(constants::UNAUTHEMTICATED_MESSAGE)
+        .extend_with(|_, e| e.set("code", "401"))
+}
+
+fn forbidden() -> async_graphql::Error {
+    async_graphql::Error::new(constants::FORBIDDEN_MESSAGE).extend_with(|_, e| e.set("code", "403"))
+}
+
+/// Id of the logged in user, for resolvers behind one of the guards below
+pub fn session_user_id(ctx: &Context<'_>) -> Result<i32> {
+    let session = ctx.data::<SharedSession>()?;
+    session.get::<i32>("id")?.ok_or_else(unauthenticated)
+}
+
+pub fn session_username(ctx: &Context<'_>) -> Result<String> {
+    let session = ctx.data::<SharedSession>()?;
+    session
+        .get::<String>("username")?
+        .ok_or_else(unauthenticated)
+}
+
+/// Logged in with a cookie session or an api token
+pub struct LoginGuard;
+
+#[async_graphql::async_trait::async_trait]
+impl Guard for LoginGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        session_user_id(ctx).map(|_| ())
+    }
+}
+
+/// Logged in with a cookie session. Account security can't be managed with an api token,
+/// only from a logged in browser
+pub struct SessionGuard;
+
+#[async_graphql::async_trait::async_trait]
+impl Guard for SessionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        session_user_id(ctx)?;
+        if ctx.data::<SharedSession>()?.is_token() {
+            return Err(forbidden());
+        }
+        Ok(())
+    }
+}
+
+/// Logged in with a site role that has the permission
+pub struct PermissionGuard(pub Permission);
+
+#[async_graphql::async_trait::async_trait]
+impl Guard for PermissionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        session_user_id(ctx)?;
+        if !ctx.data::<SharedSession>()?.has_permission(self.0) {
+            return Err(forbidden());
+        }
+        Ok(())
+    }
+}
+
+/// Forum a permission is checked in, given by itself or by something posted in it
+#[derive(Copy, Clone, Debug)]
+pub enum ForumTarget {
+    Forum(i32),
+    Post(i32),
+    Comment(i32),
+}
+
+/// Whether the logged in user has the permission in the forum of `target`. Site admins have
+/// every permission in every forum, site moderators can moderate all of them
+pub async fn has_forum_permission(
+    ctx: &Context<'_>,
+    target: ForumTarget,
+    permission: ForumPermission,
+) -> Result<bool> {
+    let user_id = session_user_id(ctx)?;
+    let session = ctx.data::<SharedSession>()?;
+    if session.has_permission(Permission::ManageSite)
+        || (permission == ForumPermission::Moderate
+            && session.has_permission(Permission::ModerateForums))
+    {
+        return Ok(true);
+    }
+    // Like site roles, forum roles only apply to tokens with the moderate scope
+    if !session.has_scope(ApiScope::Moderate) {
+        return Ok(false);
+    }
+
+    let pool = ctx.data::<crate::Pool>()?;
+    let forum_id = match target {
+        ForumTarget::Forum(id) => Some(id),
+        ForumTarget::Post(id) => get_post_forum_id(id, pool).await?,
+        ForumTarget::Comment(id) => get_comment_forum_id(id, pool).await?,
+    };
+    let role = match forum_id {
+        Some(forum_id) => get_forum_role(forum_id, user_id, pool).await?,
+        None => None,
+    };
+    Ok(role.is_some_and(|role| role.has_permission(permission)))
+}
+
+/// Whether the logged in user owns the forum or is a site admin, the only ones who appoint
+/// forum admins and hand the forum over
+pub async fn owns_forum(ctx: &Context<'_>, forum_id: i32) -> Result<bool> {
+    let user_id = session_user_id(ctx)?;
+    let session = ctx.data::<SharedSession>()?;
+    if session.has_permission(Permission::ManageSite) {
+        return Ok(true);
+    }
+    if !session.has_scope(ApiScope::Moderate) {
+        return Ok(false);
+    }
+    let pool = ctx.data::<crate::Pool>()?;
+    Ok(get_forum_owner(forum_id, pool).await? == Some(user_id))
+}
+
+/// Logged in with a role in the forum (or on the site) that has the permission, eg.
+/// `ForumGuard::new(ForumPermission::Manage, ForumTarget::Forum(forum_id))` with the
+/// `forum_id` argument of the resolver
+pub struct ForumGuard {
+    permission: ForumPermission,
+    target: ForumTarget,
+}
+
+impl ForumGuard {
+    pub fn new(permission: ForumPermission, target: ForumTarget) -> Self {
+        Self { permission, target }
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Guard for ForumGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        if !has_forum_permission(ctx, self.target, self.permission).await? {
+            return Err(forbidden());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Request, Schema};
+
+    use super::*;
+    use crate::auth::{RequestAuth, TokenIdentity};
+    use crate::db::models::role::SiteRole;
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn can_moderate(&self, ctx: &Context<'_>) -> Result<bool> {
+            has_forum_permission(ctx, ForumTarget::Post(1), ForumPermission::Moderate).await
+        }
+
+        async fn can_manage(&self, ctx: &Context<'_>) -> Result<bool> {
+            has_forum_permission(ctx, ForumTarget::Forum(1), ForumPermission::Manage).await
+        }
+
+        async fn owns(&self, ctx: &Context<'_>) -> Result<bool> {
+            owns_forum(ctx, 1).await
+        }
+    }
+
+    /// The pool is never connected, forum roles mustn't even be looked up for these tokens
+    fn schema() -> Schema<Query, EmptyMutation, EmptySubscription> {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unreachable")
+            .unwrap();
+        Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(pool)
+            .finish()
+    }
+
+    fn token(scopes: Vec<ApiScope>) -> SharedSession {
+        SharedSession::new(RequestAuth::Token(TokenIdentity {
+            user_id: 1,
+            username: "moderator".into(),
+            role: SiteRole::User,
+            scopes,
+        }))
+    }
+
+    #[tokio::test]
+    async fn tokens_without_moderate_scope_have_no_forum_role() {
+        let request = Request::new("{ canModerate canManage owns }")
+            .data(token(vec![ApiScope::Read, ApiScope::Post]));
+        let response = schema().execute(request).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({ "canModerate": false, "canManage": false, "owns": false })
+        );
+    }
+}
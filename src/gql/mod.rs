@@ -1,4 +1,6 @@
+pub mod guard;
 pub mod mutation;
 pub mod query;
 pub mod root;
+pub mod scope;
 pub mod subscription;
@@ -0,0 +1,75 @@
+use crate::auth::TokenIdentity;
+use crate::db::models::{
+    api_token::{ApiScope, ApiToken},
+    role::SiteRole,
+};
+use crate::gql::mutation::token::{hash_token, random_token};
+
+/// Prefixed so leaked tokens are easy to spot, eg. by secret scanners
+const TOKEN_PREFIX: &str = "rtw_";
+
+/// Creates a token for the user, returning it with its record. Only the hash is kept
+pub async fn create_api_token(
+    user_id: i32,
+    name: &str,
+    scopes: &[ApiScope],
+    ttl: Option<chrono::Duration>,
+    pool: &crate::Pool,
+) -> anyhow::Result<(String, ApiToken)> {
+    let token = format!("{}{}", TOKEN_PREFIX, random_token());
+
+    let api_token = sqlx::query_as!(
+        ApiToken,
+        r#"
+        INSERT INTO api_tokens (token_hash, user_id, name, scopes, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, name, scopes AS "scopes: Vec<ApiScope>", expires_at,
+        last_used_at, created_at;
+        "#,
+        hash_token(&token),
+        user_id,
+        name,
+        scopes as &[ApiScope],
+        ttl.map(|ttl| chrono::Utc::now().naive_utc() + ttl),
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok((token, api_token))
+}
+
+pub async fn revoke_api_token(id: i32, user_id: i32, pool: &crate::Pool) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        "DELETE FROM api_tokens WHERE id = $1 AND user_id = $2;",
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Who the token belongs to if it exists and hasn't expired, noting that it was used
+pub async fn authenticate_api_token(
+    token: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<TokenIdentity>> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    let identity = sqlx::query_as!(
+        TokenIdentity,
+        r#"
+        UPDATE api_tokens t SET last_used_at = (now() AT TIME ZONE 'UTC')
+        FROM users u
+        WHERE t.token_hash = $1 AND u.id = t.user_id
+        AND (t.expires_at IS NULL OR t.expires_at > (now() AT TIME ZONE 'UTC'))
+        RETURNING u.id AS user_id, u.username,
+        COALESCE((SELECT role FROM user_roles r WHERE r.user_id = u.id), 'user') AS "role!: SiteRole",
+        t.scopes AS "scopes: Vec<ApiScope>";
+        "#,
+        hash_token(token),
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(identity)
+}
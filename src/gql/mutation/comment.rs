@@ -3,6 +3,8 @@ use sqlx::{Postgres, QueryBuilder};
 
 use crate::db::models::comment::{Comment, UpdateComment};
 use crate::db::models::{comment::NewComment, FileList};
+use crate::gql::query::settings::ensure_can_post;
+use crate::markdown;
 
 #[derive(InputObject)]
 pub struct BasicCommentUpdate {
@@ -30,6 +32,8 @@ impl Into<UpdateComment> for BasicCommentUpdate {
     }
 }
 
+/// Every comment goes through here, from gql and from ws, so this is where the media is
+/// checked to belong to the commenter
 pub async fn create_comment(
     _user_id: i32,
     _post_id: i32,
@@ -39,11 +43,18 @@ pub async fn create_comment(
     _media: Option<Vec<String>>,
     pool: &crate::Pool,
 ) -> anyhow::Result<Comment> {
+    ensure_can_post(_user_id, pool).await?;
+    if let Some(t) = &_media {
+        FileList::new(t.clone())
+            .check_insertable(_user_id, pool)
+            .await?;
+    }
     let new_comment = NewComment {
         user_id: _user_id,
         post_id: _post_id,
         forum_id: _forum_id,
         parent_id: _parent_id,
+        content_html: markdown::render(&_content),
         content: _content,
         media: _media,
     };
@@ -51,8 +62,8 @@ pub async fn create_comment(
     let comment = sqlx::query_as!(
         Comment,
         "
-        INSERT INTO comments (user_id, post_id, forum_id, parent_id, media, content)
-        VALUES ($1, $2, $3, $4, $5, $6)
+        INSERT INTO comments (user_id, post_id, forum_id, parent_id, media, content, content_html)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
         RETURNING *;
         ",
         new_comment.user_id,
@@ -61,6 +72,7 @@ pub async fn create_comment(
         new_comment.parent_id,
         new_comment.media.as_ref().map(Vec::as_slice),
         new_comment.content,
+        new_comment.content_html,
     )
     .fetch_one(pool)
     .await?;
@@ -68,12 +80,41 @@ pub async fn create_comment(
     Ok(comment)
 }
 
+/// Applies the changes and archives the previous version as a revision. Moderators can
+/// edit comments of others, media still has to be uploaded by the commenter
 pub async fn update_comment(
     user_id: i32,
     changes: &UpdateComment,
+    moderator: bool,
     pool: &crate::Pool,
 ) -> anyhow::Result<Comment> {
-    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("UPDATE posts SET ");
+    let mut tx = pool.begin().await?;
+
+    let commenter_id = sqlx::query_scalar!(
+        "SELECT user_id FROM comments WHERE id = $1 AND (user_id = $2 OR $3) FOR UPDATE;",
+        changes.id,
+        user_id,
+        moderator,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| anyhow::Error::msg("Comment not found"))?;
+    if let Some(v) = &changes.media {
+        v.check_insertable(commenter_id, pool).await?;
+    }
+
+    sqlx::query!(
+        "
+        INSERT INTO comment_revisions (comment_id, content, media, created_at)
+        SELECT id, content, media, COALESCE(edited_at, created_at)
+        FROM comments WHERE id = $1;
+        ",
+        changes.id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("UPDATE comments SET ");
     let mut prev = false;
 
     if let Some(v) = &changes.user_id {
@@ -88,6 +129,8 @@ pub async fn update_comment(
         prev = true;
         builder.push("content = ");
         builder.push_bind(v);
+        builder.push(", content_html = ");
+        builder.push_bind(markdown::render(v));
     }
     if let Some(v) = &changes.media {
         let files = v.ids();
@@ -108,11 +151,41 @@ pub async fn update_comment(
 
     builder.push(" WHERE id = ");
     builder.push_bind(changes.id);
-    builder.push(" AND user_id = ");
-    builder.push_bind(user_id);
     builder.push(" RETURNING *;");
 
-    let comment = builder.build_query_as::<Comment>().fetch_one(pool).await?;
+    let comment = builder
+        .build_query_as::<Comment>()
+        .fetch_one(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
 
     Ok(comment)
 }
+
+/// Validates the post and parent comment before creating a comment through gql
+pub async fn create_post_comment(
+    user_id: i32,
+    post_id: i32,
+    parent_id: Option<i32>,
+    content: String,
+    media: Option<Vec<String>>,
+    pool: &crate::Pool,
+) -> anyhow::Result<Comment> {
+    let forum_id = sqlx::query_scalar!("SELECT forum_id FROM posts WHERE id = $1", post_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| anyhow::Error::msg("Post not found"))?;
+
+    if let Some(parent_id) = parent_id {
+        let parent_post =
+            sqlx::query_scalar!("SELECT post_id FROM comments WHERE id = $1", parent_id)
+                .fetch_optional(pool)
+                .await?;
+        if parent_post != Some(post_id) {
+            return Err(anyhow::Error::msg("Parent comment not found"));
+        }
+    }
+
+    create_comment(user_id, post_id, forum_id, parent_id, content, media, pool).await
+}
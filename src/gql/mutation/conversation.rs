@@ -0,0 +1,170 @@
+use crate::constants::MAX_CONVERSATION_MEMBERS;
+use crate::db::models::conversation::{Conversation, Message, NewMessage};
+use crate::db::models::FileList;
+use crate::gql::query::conversation::is_member;
+
+pub async fn create_conversation(
+    creator_id: i32,
+    mut member_ids: Vec<i32>,
+    title: Option<String>,
+    pool: &crate::Pool,
+) -> anyhow::Result<Conversation> {
+    member_ids.push(creator_id);
+    member_ids.sort_unstable();
+    member_ids.dedup();
+
+    if member_ids.len() < 2 {
+        return Err(anyhow::Error::msg(
+            "A conversation needs at least one other member",
+        ));
+    }
+    if member_ids.len() > MAX_CONVERSATION_MEMBERS {
+        return Err(anyhow::Error::msg(format!(
+            "A conversation can have atmost {} members",
+            MAX_CONVERSATION_MEMBERS
+        )));
+    }
+
+    // One to one conversations are reused instead of starting a new thread
+    if member_ids.len() == 2 {
+        let existing = sqlx::query_as!(
+            Conversation,
+            "
+            SELECT c.*
+            FROM conversations c
+            JOIN conversation_members m ON m.conversation_id = c.id
+            GROUP BY c.id
+            HAVING COUNT(m.user_id) = 2 AND bool_and(m.user_id = ANY($1));
+            ",
+            &member_ids,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        if let Some(conversation) = existing {
+            return Ok(conversation);
+        }
+    }
+
+    let found = sqlx::query_scalar!("SELECT COUNT(*) FROM users WHERE id = ANY($1)", &member_ids)
+        .fetch_one(pool)
+        .await?
+        .unwrap_or(0);
+    if found != member_ids.len() as i64 {
+        return Err(anyhow::Error::msg("Some of the members don't exist"));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let conversation = sqlx::query_as!(
+        Conversation,
+        "
+        INSERT INTO conversations (title)
+        VALUES ($1)
+        RETURNING *;
+        ",
+        title,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        INSERT INTO conversation_members (conversation_id, user_id)
+        SELECT $1, UNNEST($2::INTEGER[]);
+        ",
+        conversation.id,
+        &member_ids,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(conversation)
+}
+
+pub async fn create_message(
+    sender_id: i32,
+    conversation_id: i32,
+    content: String,
+    media: Option<Vec<String>>,
+    pool: &crate::Pool,
+) -> anyhow::Result<Message> {
+    if !is_member(conversation_id, sender_id, pool).await? {
+        return Err(anyhow::Error::msg("Conversation not found"));
+    }
+
+    if let Some(t) = &media {
+        FileList::new(t.clone())
+            .check_insertable(sender_id, pool)
+            .await?;
+    }
+
+    let new_message = NewMessage {
+        conversation_id,
+        sender_id,
+        content,
+        media,
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let message = sqlx::query_as!(
+        Message,
+        "
+        INSERT INTO messages (conversation_id, sender_id, content, media)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *;
+        ",
+        new_message.conversation_id,
+        new_message.sender_id,
+        new_message.content,
+        new_message.media.as_ref().map(Vec::as_slice),
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE conversations SET last_message_at = $2 WHERE id = $1;",
+        message.conversation_id,
+        message.created_at,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        UPDATE conversation_members SET last_read_at = $3
+        WHERE conversation_id = $1 AND user_id = $2;
+        ",
+        message.conversation_id,
+        message.sender_id,
+        message.created_at,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(message)
+}
+
+pub async fn mark_conversation_read(
+    user_id: i32,
+    conversation_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query!(
+        "
+        UPDATE conversation_members SET last_read_at = (now() AT TIME ZONE 'UTC')
+        WHERE conversation_id = $1 AND user_id = $2;
+        ",
+        conversation_id,
+        user_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,97 @@
+use opendal::{ErrorKind, Operator, Writer};
+use serde_json::Value;
+
+use crate::export::ZipWriter;
+use crate::gql::query::export::{
+    get_export_comments, get_export_files, get_export_posts, get_export_profile,
+};
+
+/// Builds a zip of everything the user stored (profile, posts, comments and the uploads
+/// themselves) and writes it to `exports/{user_id}/`, returning the path.
+/// Uploads missing from the storage are left out
+pub async fn export_user_data(
+    user_id: i32,
+    op: &Operator,
+    pool: &crate::Pool,
+) -> anyhow::Result<String> {
+    let path = format!("exports/{}/{}.zip", user_id, uuid::Uuid::new_v4());
+    let mut writer = op
+        .writer_with(&path)
+        .content_type("application/zip")
+        .await?;
+
+    let size = match write_archive(user_id, &mut writer, op, pool).await {
+        Ok(size) => size,
+        Err(e) => {
+            writer.abort().await.ok();
+            return Err(e);
+        }
+    };
+    writer.close().await?;
+
+    sqlx::query!(
+        "INSERT INTO data_exports (path, user_id, size) VALUES ($1, $2, $3);",
+        path,
+        user_id,
+        size as i64,
+    )
+    .execute(pool)
+    .await?;
+    Ok(path)
+}
+
+async fn write_archive(
+    user_id: i32,
+    writer: &mut Writer,
+    op: &Operator,
+    pool: &crate::Pool,
+) -> anyhow::Result<u64> {
+    let files = get_export_files(user_id, pool).await?;
+    let documents = [
+        ("profile.json", get_export_profile(user_id, pool).await?),
+        (
+            "posts.json",
+            Value::from(get_export_posts(user_id, pool).await?),
+        ),
+        (
+            "comments.json",
+            Value::from(get_export_comments(user_id, pool).await?),
+        ),
+        ("files.json", Value::from(files.clone())),
+    ];
+
+    let mut zip = ZipWriter::new(chrono::Utc::now().naive_utc());
+    let mut size = 0;
+    for (name, document) in documents {
+        let entry = zip.entry(name, &serde_json::to_vec_pretty(&document)?)?;
+        size += entry.len() as u64;
+        writer.write(entry).await?;
+    }
+
+    // Uploads keep their storage path inside the archive
+    for id in files.iter().filter_map(|f| f["id"].as_str()) {
+        let data = match op.read(id).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                log::warn!("{id} is missing from the storage, leaving it out of the export");
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let entry = zip.entry(id, &data)?;
+        size += entry.len() as u64;
+        writer.write(entry).await?;
+    }
+
+    let end = zip.finish()?;
+    size += end.len() as u64;
+    writer.write(end).await?;
+    Ok(size)
+}
+
+pub async fn delete_exports(paths: &[String], pool: &crate::Pool) -> anyhow::Result<()> {
+    sqlx::query!("DELETE FROM data_exports WHERE path = ANY($1);", paths)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
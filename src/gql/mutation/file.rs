@@ -0,0 +1,460 @@
+use chrono::NaiveDateTime;
+use opendal::Operator;
+use sqlx::{Postgres, QueryBuilder};
+use tokio::io::AsyncWriteExt;
+
+use crate::db::models::file::{MediaKind, NewFile, RenditionKind};
+use crate::db::models::upload::UploadSession;
+use crate::db::models::MaybeEmptyFile;
+use crate::media::{self, av, EncodedImage, UploadConfig, UploadError};
+use crate::spawn_blocking;
+
+/// Something to write to the storage, `rendition` is the kind and dimensions for images
+/// and video posters
+struct StoredObject {
+    path: String,
+    mime: &'static str,
+    rendition: Option<(RenditionKind, i32, i32)>,
+    bytes: Vec<u8>,
+}
+
+/// Records an upload so it can only be attached by its owner
+async fn insert_file(new_file: NewFile, pool: &crate::Pool) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+        INSERT INTO files (id, owner_id, size, mime, kind, duration, codec)
+        VALUES ($1, $2, $3, $4, $5, $6, $7);
+        ",
+        new_file.id,
+        new_file.owner_id,
+        new_file.size,
+        new_file.mime,
+        new_file.kind as MediaKind,
+        new_file.duration,
+        new_file.codec,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// `rows` are the kind, path and dimensions of each rendition
+async fn insert_renditions(
+    file_id: &str,
+    rows: Vec<(RenditionKind, String, i32, i32)>,
+    pool: &crate::Pool,
+) -> anyhow::Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let mut builder: QueryBuilder<Postgres> =
+        QueryBuilder::new("INSERT INTO renditions (file_id, kind, path, width, height) ");
+    builder.push_values(rows, |mut b, (kind, path, width, height)| {
+        b.push_bind(file_id)
+            .push_bind(kind)
+            .push_bind(path)
+            .push_bind(width)
+            .push_bind(height);
+    });
+    builder.build().execute(pool).await?;
+    Ok(())
+}
+
+/// Where an upload of the user is stored, without the extension. Files are looked up by the
+/// recorded path and owner id, so uploads from before a rename keep working
+fn media_name(username: &str) -> String {
+    format!("media/{}/{}", username, uuid::Uuid::new_v4())
+}
+
+fn poster_object(name: &str, poster: EncodedImage) -> StoredObject {
+    StoredObject {
+        path: format!(
+            "{}.{}.{}",
+            name,
+            RenditionKind::Poster.as_str(),
+            poster.extension
+        ),
+        mime: poster.mime,
+        rendition: Some((
+            RenditionKind::Poster,
+            poster.width as i32,
+            poster.height as i32,
+        )),
+        bytes: poster.bytes,
+    }
+}
+
+/// Adds `size` bytes to the storage used by the user, unless that goes over the quota
+async fn reserve_storage(
+    user_id: i32,
+    size: i64,
+    default_quota: i64,
+    pool: &crate::Pool,
+) -> anyhow::Result<()> {
+    let reserved = sqlx::query!(
+        "
+        INSERT INTO storage_usage (user_id, used)
+        SELECT $1, $2::BIGINT
+        WHERE $2::BIGINT <= COALESCE((SELECT quota FROM storage_usage WHERE user_id = $1), $3)
+        ON CONFLICT (user_id) DO UPDATE SET used = storage_usage.used + EXCLUDED.used
+        WHERE storage_usage.used + EXCLUDED.used <= COALESCE(storage_usage.quota, $3)
+        RETURNING used;
+        ",
+        user_id,
+        size,
+        default_quota,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    if reserved.is_none() {
+        let quota = sqlx::query_scalar!(
+            "SELECT quota FROM storage_usage WHERE user_id = $1;",
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .flatten()
+        .unwrap_or(default_quota);
+        return Err(UploadError::QuotaExceeded {
+            needed: size,
+            quota,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+pub async fn release_storage(user_id: i32, size: i64, pool: &crate::Pool) -> anyhow::Result<()> {
+    sqlx::query!(
+        "UPDATE storage_usage SET used = GREATEST(used - $2, 0) WHERE user_id = $1;",
+        user_id,
+        size,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Writes an upload through the operator. Images are stripped of their metadata and
+/// stored along with their smaller renditions, audio and video are probed (and get a
+/// poster), other allowed files are stored as sent.
+/// Everything written counts towards the quota of the owner
+pub async fn store_upload(
+    owner_id: i32,
+    username: &str,
+    filename: &str,
+    bytes: Vec<u8>,
+    config: &UploadConfig,
+    op: &Operator,
+    pool: &crate::Pool,
+) -> anyhow::Result<MaybeEmptyFile> {
+    let mime = match media::sniff(&bytes) {
+        Some(mime) if config.allows(mime) => mime,
+        mime => return Err(UploadError::UnsupportedType(mime.map(String::from)).into()),
+    };
+
+    let (processed, bytes) = match mime.starts_with("image/") {
+        true => spawn_blocking!({
+            let processed = media::process_image(&bytes);
+            (processed, bytes)
+        })?,
+        false => (Ok(None), bytes),
+    };
+
+    let name = media_name(username);
+    let mut stored = Vec::new();
+    let new_file = match processed? {
+        Some(processed) => {
+            let id = format!("{}.{}", name, processed.original.extension);
+            let new_file = NewFile {
+                id: id.clone(),
+                owner_id,
+                size: processed.original.bytes.len() as i64,
+                mime: processed.original.mime.to_string(),
+                kind: MediaKind::Image,
+                duration: None,
+                codec: None,
+            };
+            let renditions = processed.renditions.into_iter().map(|(kind, image)| {
+                let path = format!("{}.{}.{}", name, kind.as_str(), image.extension);
+                (path, kind, image)
+            });
+            for (path, kind, image) in
+                std::iter::once((id, RenditionKind::Original, processed.original)).chain(renditions)
+            {
+                stored.push(StoredObject {
+                    path,
+                    mime: image.mime,
+                    rendition: Some((kind, image.width as i32, image.height as i32)),
+                    bytes: image.bytes,
+                });
+            }
+            new_file
+        }
+        // Images we can't decode would keep their metadata
+        None if mime.starts_with("image/") => {
+            return Err(UploadError::UnsupportedType(Some(mime.to_string())).into());
+        }
+        None => {
+            let mut probe = match av::is_av(mime) {
+                true => Some(av::probe_bytes(&bytes, config).await?),
+                false => None,
+            };
+            let id = format!("{}.{}", name, filename.replace('/', "-"));
+            let new_file = NewFile {
+                id: id.clone(),
+                owner_id,
+                size: bytes.len() as i64,
+                mime: mime.to_string(),
+                kind: probe
+                    .as_ref()
+                    .map_or_else(|| MediaKind::from_mime(mime), |p| p.kind),
+                duration: probe.as_ref().and_then(|p| p.duration),
+                codec: probe.as_ref().map(|p| p.codec.clone()),
+            };
+            stored.push(StoredObject {
+                path: id,
+                mime,
+                rendition: None,
+                bytes,
+            });
+            if let Some(poster) = probe.as_mut().and_then(|p| p.poster.take()) {
+                stored.push(poster_object(&name, poster));
+            }
+            new_file
+        }
+    };
+
+    let total = stored.iter().map(|o| o.bytes.len() as i64).sum();
+    reserve_storage(owner_id, total, config.user_quota, pool).await?;
+
+    let mut rows = Vec::with_capacity(stored.len());
+    for object in stored {
+        let written = op
+            .write_with(&object.path, object.bytes)
+            .content_type(object.mime)
+            .await;
+        if let Err(e) = written {
+            release_storage(owner_id, total, pool).await?;
+            return Err(e.into());
+        }
+        if let Some((kind, width, height)) = object.rendition {
+            rows.push((kind, object.path, width, height));
+        }
+    }
+
+    let file_id = new_file.id.clone();
+    insert_file(new_file, pool).await?;
+    insert_renditions(&file_id, rows, pool).await?;
+
+    Ok(MaybeEmptyFile::new(file_id))
+}
+
+/// Assembles the chunks of a completed upload into the same file [`store_upload`] would
+/// give. Images are put together in memory to be processed, audio and video in a temp
+/// file to be probed, and other files are streamed chunk by chunk to their final place
+pub async fn store_chunked_upload(
+    upload: &UploadSession,
+    username: &str,
+    config: &UploadConfig,
+    op: &Operator,
+    pool: &crate::Pool,
+) -> anyhow::Result<MaybeEmptyFile> {
+    let first = op.read(&upload.chunk_path(0)).await?;
+    let mime = match media::sniff(&first) {
+        Some(mime) if config.allows(mime) => mime,
+        mime => return Err(UploadError::UnsupportedType(mime.map(String::from)).into()),
+    };
+
+    if mime.starts_with("image/") {
+        if upload.size as u64 > config.max_file_size {
+            return Err(UploadError::TooLarge {
+                size: upload.size as u64,
+                max: config.max_file_size,
+            }
+            .into());
+        }
+        let mut bytes = first;
+        bytes.reserve(upload.size as usize - bytes.len());
+        for index in 1..upload.chunk_count {
+            bytes.extend(op.read(&upload.chunk_path(index)).await?);
+        }
+        return store_upload(
+            upload.owner_id,
+            username,
+            &upload.filename,
+            bytes,
+            config,
+            op,
+            pool,
+        )
+        .await;
+    }
+
+    let mut probe = match av::is_av(mime) {
+        true => {
+            let temp = av::TempFile::new();
+            let mut file = tokio::fs::File::create(temp.path()).await?;
+            file.write_all(&first).await?;
+            for index in 1..upload.chunk_count {
+                file.write_all(&op.read(&upload.chunk_path(index)).await?)
+                    .await?;
+            }
+            file.flush().await?;
+            Some(av::probe(temp.path(), config).await?)
+        }
+        false => None,
+    };
+
+    let name = media_name(username);
+    let id = format!("{}.{}", name, upload.filename.replace('/', "-"));
+    let poster = probe
+        .as_mut()
+        .and_then(|p| p.poster.take())
+        .map(|poster| poster_object(&name, poster));
+    let total = upload.size + poster.as_ref().map_or(0, |p| p.bytes.len() as i64);
+    reserve_storage(upload.owner_id, total, config.user_quota, pool).await?;
+
+    let mut writer = match op.writer_with(&id).content_type(mime).await {
+        Ok(writer) => writer,
+        Err(e) => {
+            release_storage(upload.owner_id, total, pool).await?;
+            return Err(e.into());
+        }
+    };
+    let mut chunk = Some(first);
+    for index in 0..upload.chunk_count {
+        let bytes = match chunk.take() {
+            Some(bytes) => Ok(bytes),
+            None => op.read(&upload.chunk_path(index)).await,
+        };
+        let written = match bytes {
+            Ok(bytes) => writer.write(bytes).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            writer.abort().await.ok();
+            release_storage(upload.owner_id, total, pool).await?;
+            return Err(e.into());
+        }
+    }
+    if let Err(e) = writer.close().await {
+        release_storage(upload.owner_id, total, pool).await?;
+        return Err(e.into());
+    }
+
+    let mut rows = Vec::new();
+    if let Some(poster) = poster {
+        let written = op
+            .write_with(&poster.path, poster.bytes)
+            .content_type(poster.mime)
+            .await;
+        if let Err(e) = written {
+            release_storage(upload.owner_id, total, pool).await?;
+            return Err(e.into());
+        }
+        if let Some((kind, width, height)) = poster.rendition {
+            rows.push((kind, poster.path, width, height));
+        }
+    }
+
+    insert_file(
+        NewFile {
+            id: id.clone(),
+            owner_id: upload.owner_id,
+            size: upload.size,
+            mime: mime.to_string(),
+            kind: probe
+                .as_ref()
+                .map_or_else(|| MediaKind::from_mime(mime), |p| p.kind),
+            duration: probe.as_ref().and_then(|p| p.duration),
+            codec: probe.map(|p| p.codec),
+        },
+        pool,
+    )
+    .await?;
+    insert_renditions(&id, rows, pool).await?;
+    Ok(MaybeEmptyFile::new(id))
+}
+
+/// Uploads created before `before` that nothing points to anymore. Revisions count as
+/// references so the edit history keeps its media
+pub async fn find_orphaned_files(
+    before: NaiveDateTime,
+    limit: i64,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<(String, i32)>> {
+    let files = sqlx::query!(
+        r#"
+        SELECT f.id, f.owner_id FROM files f
+        WHERE f.created_at < $1
+        AND NOT EXISTS (SELECT 1 FROM posts WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM post_revisions WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM comments WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM comment_revisions WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM messages WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM users WHERE pfp = f.id OR banner = f.id)
+        AND NOT EXISTS (SELECT 1 FROM forums WHERE icon = f.id OR banner = f.id)
+        ORDER BY f.created_at
+        LIMIT $2;
+        "#,
+        before,
+        limit,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| (r.id, r.owner_id))
+    .collect();
+    Ok(files)
+}
+
+/// Deletes the records of the files that are still unreferenced, returning the paths to
+/// remove from the storage (renditions included) along with the owner of each
+pub async fn delete_orphaned_files(
+    ids: &[String],
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<(String, i32)>> {
+    let mut tx = pool.begin().await?;
+
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM files f
+        WHERE f.id = ANY($1)
+        AND NOT EXISTS (SELECT 1 FROM posts WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM post_revisions WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM comments WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM comment_revisions WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM messages WHERE f.id = ANY(media))
+        AND NOT EXISTS (SELECT 1 FROM users WHERE pfp = f.id OR banner = f.id)
+        AND NOT EXISTS (SELECT 1 FROM forums WHERE icon = f.id OR banner = f.id)
+        RETURNING f.id, f.owner_id;
+        "#,
+        ids,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let deleted_ids = deleted.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+    let renditions = sqlx::query!(
+        "DELETE FROM renditions WHERE file_id = ANY($1) RETURNING file_id, path;",
+        &deleted_ids,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let mut paths = Vec::with_capacity(deleted.len() + renditions.len());
+    for file in deleted {
+        for rendition in renditions.iter().filter(|r| r.file_id == file.id) {
+            // The original rendition is the file itself
+            if rendition.path != file.id {
+                paths.push((rendition.path.clone(), file.owner_id));
+            }
+        }
+        paths.push((file.id, file.owner_id));
+    }
+    Ok(paths)
+}
@@ -61,9 +61,12 @@ pub async fn create_forum(
     Ok(forum)
 }
 
+/// Owners and managers can edit the forum, new icons and banners have to be uploads of
+/// the user. Callers check who may hand the forum over
 pub async fn update_forum(
     user_id: i32,
     changes: &UpdateForum,
+    manager: bool,
     pool: &crate::Pool,
 ) -> anyhow::Result<Forum> {
     let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("UPDATE forums SET ");
@@ -90,7 +93,7 @@ pub async fn update_forum(
         builder.push_bind(v);
     }
     if let Some(v) = &changes.icon {
-        if v.status().await?.updatable() {
+        if v.status(user_id, pool).await?.updatable() {
             if prev {
                 builder.push(", ");
             }
@@ -105,7 +108,7 @@ pub async fn update_forum(
         }
     }
     if let Some(v) = &changes.banner {
-        if v.status().await?.updatable() {
+        if v.status(user_id, pool).await?.updatable() {
             if prev {
                 builder.push(", ");
             }
@@ -128,11 +131,17 @@ pub async fn update_forum(
     }
     builder.push(" WHERE id = ");
     builder.push_bind(changes.id);
-    builder.push(" AND owner_id = ");
+    builder.push(" AND (owner_id = ");
     builder.push_bind(user_id);
-    builder.push(" RETURNING *;");
+    builder.push(" OR ");
+    builder.push_bind(manager);
+    builder.push(") RETURNING *;");
 
-    let forum = builder.build_query_as::<Forum>().fetch_one(pool).await?;
+    let forum = builder
+        .build_query_as::<Forum>()
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| anyhow::Error::msg("Forum not found"))?;
 
     Ok(forum)
 }
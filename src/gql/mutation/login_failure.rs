@@ -0,0 +1,72 @@
+use crate::db::models::login_failure::LoginFailureKind;
+use crate::ratelimit::login::LoginThrottleConfig;
+
+/// Counts a failed login for `key`, locking it once there were too many.
+/// Rows that were reset and aren't locked anymore are pruned on the way
+pub async fn record_login_failure(
+    kind: LoginFailureKind,
+    key: &str,
+    config: &LoginThrottleConfig,
+    pool: &crate::Pool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+        DELETE FROM login_failures
+        WHERE last_failed_at < (now() AT TIME ZONE 'UTC') - make_interval(secs => $1)
+        AND (locked_until IS NULL OR locked_until < (now() AT TIME ZONE 'UTC'));
+        ",
+        config.reset_after.as_secs_f64(),
+    )
+    .execute(pool)
+    .await?;
+
+    let failures = sqlx::query_scalar!(
+        "
+        INSERT INTO login_failures (kind, key) VALUES ($1, $2)
+        ON CONFLICT (kind, key) DO UPDATE
+        SET failures = CASE
+            WHEN login_failures.last_failed_at
+                < (now() AT TIME ZONE 'UTC') - make_interval(secs => $3)
+            THEN 1 ELSE login_failures.failures + 1 END,
+        last_failed_at = (now() AT TIME ZONE 'UTC')
+        RETURNING failures;
+        ",
+        kind as LoginFailureKind,
+        key,
+        config.reset_after.as_secs_f64(),
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if let Some(delay) = config.delay(failures as u32) {
+        sqlx::query!(
+            "
+            UPDATE login_failures
+            SET locked_until = (now() AT TIME ZONE 'UTC') + make_interval(secs => $3)
+            WHERE kind = $1 AND key = $2;
+            ",
+            kind as LoginFailureKind,
+            key,
+            delay.as_secs_f64(),
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Forgets the failed logins of `key`, eg. after a successful one
+pub async fn clear_login_failures(
+    kind: LoginFailureKind,
+    key: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        "DELETE FROM login_failures WHERE kind = $1 AND key = $2;",
+        kind as LoginFailureKind,
+        key,
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
@@ -0,0 +1,109 @@
+use crate::db::models::forum::Forum;
+use crate::db::models::user::User;
+use crate::helpers::parse_mentions;
+
+#[derive(Clone, Copy, Debug)]
+pub enum MentionSource {
+    Post(i32),
+    Comment(i32),
+}
+
+impl MentionSource {
+    fn ids(&self) -> (Option<i32>, Option<i32>) {
+        match self {
+            Self::Post(id) => (Some(*id), None),
+            Self::Comment(id) => (None, Some(*id)),
+        }
+    }
+}
+
+/// Parses `content`, keeps the references that exist and replaces the stored
+/// mentions of `source` with them. Returns the mentioned users. The post or comment is
+/// already committed, so failing to store its mentions is logged and mentions nobody.
+pub async fn store_mentions(
+    source: MentionSource,
+    content: Option<&str>,
+    pool: &crate::Pool,
+) -> Vec<User> {
+    match replace_mentions(source, content, pool).await {
+        Ok(users) => users,
+        Err(e) => {
+            log::error!("{e:?}");
+            vec![]
+        }
+    }
+}
+
+async fn replace_mentions(
+    source: MentionSource,
+    content: Option<&str>,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<User>> {
+    let parsed = content.map(parse_mentions).unwrap_or_default();
+    let (post_id, comment_id) = source.ids();
+
+    let users = if parsed.usernames.is_empty() {
+        vec![]
+    } else {
+        sqlx::query_as!(
+            User,
+            "SELECT * FROM users WHERE username = ANY($1)",
+            &parsed.usernames
+        )
+        .fetch_all(pool)
+        .await?
+    };
+
+    let forums = if parsed.forums.is_empty() {
+        vec![]
+    } else {
+        sqlx::query_as!(
+            Forum,
+            "SELECT * FROM forums WHERE name = ANY($1)",
+            &parsed.forums
+        )
+        .fetch_all(pool)
+        .await?
+    };
+
+    let user_ids = users.iter().map(|u| u.id).collect::<Vec<_>>();
+    let forum_ids = forums.iter().map(|f| f.id).collect::<Vec<_>>();
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "DELETE FROM mentions WHERE post_id = $1 OR comment_id = $2;",
+        post_id,
+        comment_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        INSERT INTO mentions (post_id, comment_id, user_id)
+        SELECT $1, $2, UNNEST($3::INTEGER[]);
+        ",
+        post_id,
+        comment_id,
+        &user_ids,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        INSERT INTO mentions (post_id, comment_id, forum_id)
+        SELECT $1, $2, UNNEST($3::INTEGER[]);
+        ",
+        post_id,
+        comment_id,
+        &forum_ids,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(users)
+}
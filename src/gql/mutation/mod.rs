@@ -1,6 +1,20 @@
+pub mod api_token;
 pub mod comment;
+pub mod conversation;
+pub mod export;
+pub mod file;
 mod forum;
+mod login_failure;
+pub mod mention;
+pub mod notification;
+mod oidc;
 mod post;
+mod role;
+pub mod session;
+mod settings;
+pub mod token;
+mod two_factor;
+pub mod upload;
 mod user;
 
 use actix::Addr;
@@ -11,33 +25,415 @@ use argon2::{
 use async_graphql::{Context, ErrorExtensions, Object, Result, Upload};
 use opendal::Operator;
 use rand::Rng;
+use std::io::Read;
 
 use crate::db::models::user::User;
 use crate::error::UserCreationError;
+use crate::gql::mutation::mention::MentionSource;
+use crate::gql::query::{
+    api_token::count_api_tokens,
+    login_failure::get_locked_until,
+    oidc::{get_oidc_user, get_user_by_verified_email},
+    role::get_forum_role,
+    two_factor::{get_totp_secret, has_two_factor},
+    upload::get_upload_session,
+    user::{get_user_by_id, get_user_by_login, last_username_change, username_held},
+};
 use crate::{
-    auth::SharedSession,
+    auth::{ClientInfo, SharedSession},
     constants,
     core::event::{
-        CommentEvent, CommentEventTy, EventManager, ForumEvent, ForumEventTy, PostEvent,
-        PostEventTy, UserEvent, UserEventTy,
+        CommentEvent, CommentEventTy, EventManager, ForumEvent, ForumEventTy, NotificationEvent,
+        PostEvent, PostEventTy, UserEvent, UserEventTy,
+    },
+    core::{
+        dm::DmServer,
+        packet::{DmBroadcast, KickUser},
+        RtServer,
     },
     db::models::{
+        api_token::{ApiScope, CreatedApiToken},
         comment::{Comment, UpdateComment},
+        conversation::{Conversation, Message},
+        export::DataExport,
         forum::{Forum, SearchForum, UpdateForum},
+        login_failure::LoginFailureKind,
+        notification::Notification,
         post::{InputPost, Post, SearchPost, UpdatePost},
+        role::{ForumMember, ForumPermission, ForumRole, Permission, SiteRole},
+        settings::SiteSettings,
+        token::TokenPurpose,
+        two_factor::{LoginResponse, TotpEnrollment},
+        upload::{NewUploadSession, UploadSession},
         user::{SearchUser, UpdateUser},
     },
     error::UserAuthError,
-    helpers::check_valid_uservane,
+    gql::guard::{
+        has_forum_permission, owns_forum, session_user_id, session_username, ForumGuard,
+        ForumTarget, LoginGuard, PermissionGuard, SessionGuard,
+    },
+    helpers::{check_valid_email, check_valid_uservane},
+    media::{
+        signing::UrlSigner,
+        sweeper::{MediaSweeper, Sweep, SweepReport},
+        UploadConfig, UploadError,
+    },
+    oidc::{OidcClient, OidcIntent, PendingLogin},
+    ratelimit::{
+        login::{LoginLocked, LoginThrottleConfig},
+        Action, RateKey, RateLimiter,
+    },
     search::SearchIndex,
+    totp,
 };
 use crate::{
     db::models::MaybeEmptyFile,
     helpers::{calculate_password_strength, check_reserved_username},
+    mail::Mailer,
 };
 
 pub struct Mutation;
 
+/// Sends created notifications to subscribers, failing to notify never fails the mutation
+fn publish_notifications(ctx: &Context<'_>, notifications: anyhow::Result<Vec<Notification>>) {
+    let notifications = match notifications {
+        Ok(notifications) => notifications,
+        Err(e) => {
+            log::error!("{e:?}");
+            return;
+        }
+    };
+    if let Ok(event_manager) = ctx.data::<Addr<EventManager>>() {
+        for notification in notifications {
+            event_manager.do_send(NotificationEvent { notification });
+        }
+    }
+}
+
+/// Closes the comment and dm connections opened with the login session, or all of the
+/// connections of the user without `session_id`
+fn kick_user(ctx: &Context<'_>, user_id: i32, session_id: Option<String>) -> Result<()> {
+    let kick = KickUser {
+        user_id,
+        session_id,
+    };
+    ctx.data::<Addr<RtServer>>()?.do_send(kick.clone());
+    ctx.data::<Addr<DmServer>>()?.do_send(kick);
+    Ok(())
+}
+
+/// Takes a token for `action` from the limiter bucket of the logged in user
+fn rate_limit(ctx: &Context<'_>, user_id: i32, action: Action) -> Result<()> {
+    let session = ctx.data::<SharedSession>()?;
+    let elevated = session.has_permission(Permission::ElevatedRateLimits);
+    let limiter = ctx.data::<RateLimiter>()?;
+    limiter
+        .check(RateKey::User(user_id), action, elevated)
+        .map_err(|e| e.extend())
+}
+
+/// Account security can't be managed with an api token, only from a logged in browser
+fn require_cookie_session(ctx: &Context<'_>) -> Result<()> {
+    let session = ctx.data::<SharedSession>()?;
+    if session.is_token() {
+        return Err(async_graphql::Error::new(constants::FORBIDDEN_MESSAGE)
+            .extend_with(|_, e| e.set("code", "403")));
+    }
+    Ok(())
+}
+
+/// Checks the password of the logged in user before account changes
+async fn confirm_password(ctx: &Context<'_>, username: &str, password: &str) -> Result<()> {
+    let pool = ctx.data::<crate::Pool>()?;
+    let hasher = ctx.data::<Argon2>()?.clone();
+
+    let user = user::verify_user(username, password, pool, &hasher).await?;
+    if user.is_none() {
+        return Err(
+            UserAuthError::InvalidUsernameOrPassword("Password is invalid")
+                .extend_with(|_, e| e.set("code", "401")),
+        );
+    }
+    Ok(())
+}
+
+/// Forum admins appoint and remove moderators, admins themselves are only appointed,
+/// demoted and removed by the owner or a site admin. `None` removes the role
+async fn check_forum_role_change(
+    ctx: &Context<'_>,
+    forum_id: i32,
+    user_id: i32,
+    role: Option<ForumRole>,
+) -> Result<()> {
+    if owns_forum(ctx, forum_id).await? {
+        return Ok(());
+    }
+    let pool = ctx.data::<crate::Pool>()?;
+    let current = get_forum_role(forum_id, user_id, pool).await?;
+    if role == Some(ForumRole::Admin) || current == Some(ForumRole::Admin) {
+        return Err(async_graphql::Error::new(
+            "Only the owner can appoint and remove forum admins",
+        )
+        .extend_with(|_, e| e.set("code", "403")));
+    }
+    Ok(())
+}
+
+/// Uses up a login with the identity provider from `oidcReauthenticationUrl`, when it
+/// was recent enough
+fn confirm_reauthentication(ctx: &Context<'_>) -> Result<()> {
+    let session = ctx.data::<SharedSession>()?;
+    let at = session.get::<i64>("reauthenticated_at")?;
+    session.remove("reauthenticated_at");
+    let fresh = at.is_some_and(|at| {
+        chrono::Utc::now().timestamp() - at <= constants::REAUTHENTICATION_TTL_MINUTES * 60
+    });
+    if !fresh {
+        return Err(UserAuthError::InvalidUsernameOrPassword(
+            "Password or a new login with the identity provider is required",
+        )
+        .extend_with(|_, e| e.set("code", "401")));
+    }
+    Ok(())
+}
+
+/// Failed logins are counted by username and by ip, when there is one
+fn login_failure_keys(ctx: &Context<'_>, username: &str) -> Vec<(LoginFailureKind, String)> {
+    let mut keys = vec![(LoginFailureKind::Username, username.to_lowercase())];
+    if let Some(ip) = ctx.data_opt::<ClientInfo>().and_then(|c| c.ip.clone()) {
+        keys.push((LoginFailureKind::Ip, ip));
+    }
+    keys
+}
+
+/// Fails while the username or the ip is locked because of failed logins
+async fn check_login_lock(keys: &[(LoginFailureKind, String)], pool: &crate::Pool) -> Result<()> {
+    let mut locked_until = None;
+    for (kind, key) in keys {
+        locked_until = locked_until.max(get_locked_until(*kind, key, pool).await?);
+    }
+    if let Some(locked_until) = locked_until {
+        let retry_after = (locked_until - chrono::Utc::now().naive_utc())
+            .to_std()
+            .unwrap_or_default();
+        return Err(LoginLocked { retry_after }.extend());
+    }
+    Ok(())
+}
+
+/// Logs the user in, recording the session so it can be listed and revoked
+async fn start_session(ctx: &Context<'_>, user: &User) -> Result<()> {
+    let pool = ctx.data::<crate::Pool>()?;
+    let session = ctx.data::<SharedSession>()?;
+
+    let client = ctx.data::<ClientInfo>().cloned().unwrap_or_default();
+    let sid = session::create_user_session(user.id, &client, pool).await?;
+    session.insert("sid", sid)?;
+    session.insert("id", user.id)?;
+    session.insert("username", user.username.clone())?;
+    Ok(())
+}
+
+/// Checks an authenticator code, or uses up a recovery code, of the user
+async fn check_second_factor(user_id: i32, code: &str, pool: &crate::Pool) -> Result<bool> {
+    let secret = match get_totp_secret(user_id, pool).await? {
+        Some(secret) if secret.confirmed_at.is_some() => secret,
+        _ => return Ok(false),
+    };
+    let now = chrono::Utc::now().timestamp() as u64;
+    if let Some(step) = totp::verify(&secret.secret, code, now) {
+        return Ok(two_factor::use_totp_step(user_id, step as i64, pool).await?);
+    }
+    Ok(two_factor::use_recovery_code(user_id, code, pool).await?)
+}
+
+/// Logs the user in, or returns a challenge for `completeLogin` when they have two factor
+/// authentication enabled
+async fn login_or_challenge(ctx: &Context<'_>, user: User) -> Result<LoginResponse> {
+    let pool = ctx.data::<crate::Pool>()?;
+    if has_two_factor(user.id, pool).await? {
+        let challenge = token::create_token(
+            user.id,
+            TokenPurpose::LoginChallenge,
+            chrono::Duration::minutes(constants::LOGIN_CHALLENGE_TTL_MINUTES),
+            pool,
+        )
+        .await?;
+        return Ok(LoginResponse {
+            user: None,
+            challenge: Some(challenge),
+        });
+    }
+    start_session(ctx, &user).await?;
+    Ok(LoginResponse {
+        user: Some(user),
+        challenge: None,
+    })
+}
+
+/// Adds a new user to the search index and tells subscribers about it
+fn publish_new_user(ctx: &Context<'_>, user: &User) -> Result<()> {
+    let index = ctx.data::<SearchIndex>()?;
+    let search_user: SearchUser = user.clone().into();
+    index.user.add(search_user)?;
+
+    let event_manager = ctx.data::<Addr<EventManager>>()?;
+
+    event_manager.do_send(UserEvent {
+        ty: UserEventTy::UserCreation,
+        user: user.clone(),
+    });
+    Ok(())
+}
+
+fn oidc_client<'c>(ctx: &Context<'c>) -> Result<&'c OidcClient> {
+    ctx.data_opt::<OidcClient>().ok_or_else(|| {
+        async_graphql::Error::new("Logging in with an identity provider isn't enabled")
+            .extend_with(|_, e| e.set("code", "404"))
+    })
+}
+
+/// Creates a user for a provider account, trying other usernames while the name is taken.
+/// The password is random, one can be set with a password reset once the user has an email
+async fn provision_oidc_user(ctx: &Context<'_>, claims: &crate::oidc::IdClaims) -> Result<User> {
+    let pool = ctx.data::<crate::Pool>()?;
+    let password = hash_password(ctx, &token::random_token())?;
+    let mut email = claims.verified_email().map(String::from);
+    let mut suffix = None;
+
+    for _ in 0..5 {
+        let username = crate::oidc::username_candidate(claims, suffix);
+        match user::create_user(username, password.clone(), email.clone(), pool).await {
+            Ok(created_user) => {
+                // The provider already verified it
+                if created_user.email.is_some() {
+                    user::mark_email_verified(created_user.id, pool).await?;
+                }
+                publish_new_user(ctx, &created_user)?;
+                return Ok(created_user);
+            }
+            Err(e) => match e.downcast_ref::<UserCreationError>() {
+                Some(UserCreationError::UsernameAlreadyExists(_)) => {
+                    suffix = Some(rand::thread_rng().gen_range(1000..10000));
+                }
+                // Verified by another account in the meantime, the new user goes without
+                Some(UserCreationError::EmailAlreadyExists(_)) => email = None,
+                _ => return Err(e.into()),
+            },
+        }
+    }
+    Err(UserCreationError::UsernameAlreadyExists("Could not find a free username").into())
+}
+
+/// Hashes and stores the password, outstanding reset tokens stop working
+async fn set_password(ctx: &Context<'_>, user_id: i32, password: &str) -> Result<()> {
+    let pool = ctx.data::<crate::Pool>()?;
+    let changes = UpdateUser {
+        id: user_id,
+        username: None,
+        password: Some(hash_password(ctx, password)?),
+        display_name: None,
+        bio: None,
+        pfp: None,
+        banner: None,
+    };
+    user::update_user(&changes, pool).await?;
+    token::revoke_tokens(user_id, TokenPurpose::PasswordReset, pool).await?;
+    Ok(())
+}
+
+/// Same rules for new accounts and renames
+fn check_username(username: &str) -> Result<()> {
+    if check_reserved_username(username) {
+        return Err(UserCreationError::ReservedUsername("This username is reserved").into());
+    }
+
+    if username.len() > 20 {
+        return Err(
+            UserCreationError::InvalidUsername("Usernames can be atmost 20 characters").into(),
+        );
+    }
+
+    if !check_valid_uservane(username) {
+        return Err(UserCreationError::InvalidUsername(
+            "Username can only be lowercase, alphanumeric and seperated by _",
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Same rules for new accounts and password changes
+fn check_password(password: &str, username: &str) -> Result<()> {
+    let pass_score = calculate_password_strength(password, username)?;
+    if pass_score < 3 {
+        return Err(UserCreationError::LowPasswordStrength(&format!(
+            "Password is too weak [score {}/4]",
+            pass_score
+        ))
+        .into());
+    }
+
+    if password.len() > 32 {
+        return Err(
+            UserCreationError::PasswordTooLong("Password can be atmost 32 characters").into(),
+        );
+    }
+    Ok(())
+}
+
+/// Trims the email, an empty one means no email
+fn check_email(email: Option<String>) -> Result<Option<String>> {
+    let email = match email.as_deref().map(str::trim) {
+        Some("") | None => return Ok(None),
+        Some(email) => email.to_string(),
+    };
+    if !check_valid_email(&email) {
+        return Err(UserCreationError::InvalidEmail("Email address is invalid").into());
+    }
+    Ok(Some(email))
+}
+
+/// Mails a fresh verification link to the email of the user, older links stop working
+async fn send_verification_mail(ctx: &Context<'_>, user: &User) -> Result<()> {
+    let email = match &user.email {
+        Some(email) => email,
+        None => return Ok(()),
+    };
+    let pool = ctx.data::<crate::Pool>()?;
+    let mailer = ctx.data::<Mailer>()?;
+
+    token::revoke_tokens(user.id, TokenPurpose::EmailVerification, pool).await?;
+    let token = token::create_token(
+        user.id,
+        TokenPurpose::EmailVerification,
+        chrono::Duration::hours(constants::EMAIL_VERIFICATION_TTL_HOURS),
+        pool,
+    )
+    .await?;
+    let body = format!(
+        "Hi {},\n\nOpen {}/verify-email?token={} within {} hours to verify the email \
+        of {}.\n\nIf you didn't sign up, you can ignore this mail.",
+        user.display_name,
+        mailer.public_url,
+        token,
+        constants::EMAIL_VERIFICATION_TTL_HOURS,
+        user.username
+    );
+    mailer.send(email, "Verify your email", body).await?;
+    Ok(())
+}
+
+fn hash_password(ctx: &Context<'_>, password: &str) -> Result<String> {
+    let hasher = ctx.data::<Argon2>()?;
+    let salt = SaltString::generate(&mut OsRng);
+    let hashed_pass = hasher
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| e.extend_with(|_, e| e.set("code", "500")))?
+        .to_string();
+    Ok(hashed_pass)
+}
+
 #[Object]
 impl Mutation {
     async fn create_user<'c>(
@@ -45,97 +441,99 @@ impl Mutation {
         ctx: &Context<'c>,
         username: String,
         password: String,
+        email: Option<String>,
     ) -> Result<User> {
-        if check_reserved_username(&username) {
-            return Err(UserCreationError::ReservedUsername("This username is reserved").into());
-        }
+        check_username(&username)?;
+        check_password(&password, &username)?;
+        let email = check_email(email)?;
 
-        if username.len() > 20 {
-            return Err(UserCreationError::InvalidUsername(
-                "Usernames can be atmost 20 characters",
-            )
-            .into());
-        }
+        let pool = ctx.data::<crate::Pool>()?;
+        let hashed_pass = hash_password(ctx, &password)?;
 
-        if !check_valid_uservane(&username) {
-            return Err(UserCreationError::InvalidUsername(
-                "Username can only be lowercase, alphanumeric and seperated by _",
-            )
-            .into());
+        let created_user = user::create_user(username, hashed_pass, email, &pool).await?;
+        // The account works without a verified email, so a failed mail can be resent later
+        if let Err(e) = send_verification_mail(ctx, &created_user).await {
+            log::error!("{:?}", e);
         }
 
-        let pass_score = calculate_password_strength(&password, &username)?;
-        if pass_score < 3 {
-            return Err(UserCreationError::LowPasswordStrength(&format!(
-                "Password is too weak [score {}/4]",
-                pass_score
-            ))
-            .into());
+        publish_new_user(ctx, &created_user)?;
+
+        Ok(created_user)
+    }
+
+    /// Renames the logged in user. The old username keeps resolving to them and nobody else
+    /// can take it for a while. Existing uploads keep their paths under the old name
+    #[graphql(guard = "SessionGuard")]
+    async fn change_username<'c>(&self, ctx: &Context<'c>, username: String) -> Result<User> {
+        let session = ctx.data::<SharedSession>()?;
+        let id = session_user_id(ctx)?;
+        let current = session_username(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        check_username(&username)?;
+        if username == current {
+            return Err(async_graphql::Error::new("This is already your username")
+                .extend_with(|_, e| e.set("code", "409")));
         }
 
-        if password.len() > 32 {
+        let pool = ctx.data::<crate::Pool>()?;
+        if let Some(changed_at) = last_username_change(id, pool).await? {
+            let interval = chrono::Duration::days(constants::USERNAME_CHANGE_INTERVAL_DAYS);
+            if changed_at + interval > chrono::Utc::now().naive_utc() {
+                return Err(async_graphql::Error::new(format!(
+                    "Usernames can only be changed once every {} days",
+                    constants::USERNAME_CHANGE_INTERVAL_DAYS
+                ))
+                .extend_with(|_, e| e.set("code", "429")));
+            }
+        }
+        if username_held(&username, Some(id), pool).await? {
             return Err(
-                UserCreationError::PasswordTooLong("Password can be atmost 32 characters").into(),
+                UserCreationError::UsernameAlreadyExists("Username is already taken").into(),
             );
         }
 
-        let pool = ctx.data::<crate::Pool>()?;
-        let hasher = ctx.data::<Argon2>()?;
-
-        let salt = SaltString::generate(&mut OsRng);
-        let hashed_pass = hasher
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|e| e.extend_with(|_, e| e.set("code", "500")))?
-            .to_string();
-
-        let created_user = user::create_user(username, hashed_pass, &pool).await?;
+        let user = user::change_username(id, &username, pool).await?;
+        session.insert("username", user.username.clone())?;
 
         let index = ctx.data::<SearchIndex>()?;
-        let search_user: SearchUser = created_user.clone().into();
-        index.user.add(search_user)?;
+        let index_update: SearchUser = user.clone().into();
+        index.user.update(index_update)?;
 
         let event_manager = ctx.data::<Addr<EventManager>>()?;
-
         event_manager.do_send(UserEvent {
-            ty: UserEventTy::UserCreation,
-            user: created_user.clone(),
+            ty: UserEventTy::UsernameChange,
+            user: user.clone(),
         });
 
-        Ok(created_user)
+        Ok(user)
     }
 
+    #[graphql(guard = "LoginGuard")]
     async fn update_user_basic<'c>(
         &self,
         ctx: &Context<'c>,
         changes: user::BasicUserUpdate,
     ) -> Result<User> {
-        let session = ctx.data::<SharedSession>()?;
-        let id = session.get::<i32>("id")?;
-
-        if let Some(id) = id {
-            let pool = ctx.data::<crate::Pool>()?;
-            let index = ctx.data::<SearchIndex>()?;
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let index = ctx.data::<SearchIndex>()?;
 
-            let mut changes: UpdateUser = changes.into();
-            changes.id = id;
-            let user = user::update_user(&changes, &pool).await?;
+        let mut changes: UpdateUser = changes.into();
+        changes.id = id;
+        let user = user::update_user(&changes, &pool).await?;
 
-            let index_update: SearchUser = user.clone().into();
-            index.user.update(index_update)?;
+        let index_update: SearchUser = user.clone().into();
+        index.user.update(index_update)?;
 
-            let event_manager = ctx.data::<Addr<EventManager>>()?;
+        let event_manager = ctx.data::<Addr<EventManager>>()?;
 
-            event_manager.do_send(UserEvent {
-                ty: UserEventTy::UserBasicUpdate,
-                user: user.clone(),
-            });
+        event_manager.do_send(UserEvent {
+            ty: UserEventTy::UserBasicUpdate,
+            user: user.clone(),
+        });
 
-            return Ok(user);
-        }
-        Err(
-            async_graphql::Error::new(constants::UNAUTHEMTICATED_MESSAGE)
-                .extend_with(|_, e| e.set("code", "401")),
-        )
+        Ok(user)
     }
 
     async fn login<'c>(
@@ -143,62 +541,769 @@ impl Mutation {
         ctx: &Context<'c>,
         username: String,
         password: String,
-    ) -> Result<User> {
+    ) -> Result<LoginResponse> {
         let pool = ctx.data::<crate::Pool>()?;
         let hasher = ctx.data::<Argon2>()?.clone();
-        let session = ctx.data::<SharedSession>()?;
+        let throttle = ctx.data::<LoginThrottleConfig>()?;
+
+        let keys = login_failure_keys(ctx, &username);
+        check_login_lock(&keys, pool).await?;
 
         let x = user::verify_user(&username, &password, pool, &hasher).await?;
 
         match x {
-            (true, user) => {
-                session.insert("id", user.id)?;
-                session.insert("admin", user.admin)?;
-                session.insert("username", username)?;
-                Ok(user)
+            Some(user) => {
+                // Only the username is cleared, one working account shouldn't unlock an ip
+                login_failure::clear_login_failures(LoginFailureKind::Username, &keys[0].1, pool)
+                    .await?;
+                login_or_challenge(ctx, user).await
             }
-            (false, _) => Err(UserAuthError::InvalidUsernameOrPassword(
-                "Username or password is invalid",
+            None => {
+                for (kind, key) in keys.iter() {
+                    login_failure::record_login_failure(*kind, key, throttle, pool).await?;
+                }
+                Err(
+                    UserAuthError::InvalidUsernameOrPassword("Username or password is invalid")
+                        .extend_with(|_, e| e.set("code", "401")),
+                )
+            }
+        }
+    }
+
+    /// Second step of logging in with two factor authentication, `code` is one from the
+    /// authenticator app or a recovery code
+    async fn complete_login<'c>(
+        &self,
+        ctx: &Context<'c>,
+        challenge: String,
+        code: String,
+    ) -> Result<User> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let invalid = || {
+            UserAuthError::InvalidToken("Login challenge is invalid or expired")
+                .extend_with(|_, e| e.set("code", "401"))
+        };
+
+        let user_id = token::find_token_user(&challenge, TokenPurpose::LoginChallenge, pool)
+            .await?
+            .ok_or_else(invalid)?;
+        rate_limit(ctx, user_id, Action::TwoFactor)?;
+        if !check_second_factor(user_id, &code, pool).await? {
+            return Err(UserAuthError::InvalidTwoFactorCode("Code is invalid")
+                .extend_with(|_, e| e.set("code", "401")));
+        }
+        token::consume_token(&challenge, TokenPurpose::LoginChallenge, pool)
+            .await?
+            .ok_or_else(invalid)?;
+
+        let user = get_user_by_id(user_id, pool).await?.user;
+        start_session(ctx, &user).await?;
+        Ok(user)
+    }
+
+    /// Starts enrolling in two factor authentication, it's enabled once `confirmTotp`
+    /// gets a code of the secret
+    #[graphql(guard = "SessionGuard")]
+    async fn enroll_totp<'c>(&self, ctx: &Context<'c>) -> Result<TotpEnrollment> {
+        let id = session_user_id(ctx)?;
+        let username = session_username(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let secret = totp::generate_secret();
+        if !two_factor::set_pending_totp(id, &secret, pool).await? {
+            return Err(async_graphql::Error::new(
+                "Two factor authentication is already enabled",
             )
-            .extend_with(|_, e| e.set("code", "401"))),
+            .extend_with(|_, e| e.set("code", "409")));
         }
+        let uri = totp::otpauth_uri(&secret, &username, constants::TOTP_ISSUER);
+        Ok(TotpEnrollment { secret, uri })
+    }
+
+    /// Enables two factor authentication, returning the recovery codes. They're only shown
+    /// here and each works once
+    #[graphql(guard = "SessionGuard")]
+    async fn confirm_totp<'c>(&self, ctx: &Context<'c>, code: String) -> Result<Vec<String>> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::TwoFactor)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let secret = match get_totp_secret(id, pool).await? {
+            Some(secret) if secret.confirmed_at.is_none() => secret,
+            _ => {
+                return Err(
+                    async_graphql::Error::new("No two factor enrollment to confirm")
+                        .extend_with(|_, e| e.set("code", "409")),
+                )
+            }
+        };
+        let now = chrono::Utc::now().timestamp() as u64;
+        let step = totp::verify(&secret.secret, &code, now).ok_or_else(|| {
+            UserAuthError::InvalidTwoFactorCode("Code is invalid")
+                .extend_with(|_, e| e.set("code", "401"))
+        })?;
+
+        two_factor::confirm_totp(id, step as i64, pool).await?;
+        let codes = two_factor::replace_recovery_codes(id, pool).await?;
+        Ok(codes)
+    }
+
+    #[graphql(guard = "SessionGuard")]
+    async fn disable_totp<'c>(&self, ctx: &Context<'c>, password: String) -> Result<bool> {
+        let id = session_user_id(ctx)?;
+        let username = session_username(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        confirm_password(ctx, &username, &password).await?;
+
+        let pool = ctx.data::<crate::Pool>()?;
+        two_factor::delete_totp(id, pool).await?;
+        Ok(true)
+    }
+
+    /// Replaces the recovery codes, the old ones stop working
+    #[graphql(guard = "SessionGuard")]
+    async fn regenerate_recovery_codes<'c>(
+        &self,
+        ctx: &Context<'c>,
+        password: String,
+    ) -> Result<Vec<String>> {
+        let id = session_user_id(ctx)?;
+        let username = session_username(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        confirm_password(ctx, &username, &password).await?;
+
+        let pool = ctx.data::<crate::Pool>()?;
+        if !has_two_factor(id, pool).await? {
+            return Err(
+                async_graphql::Error::new("Two factor authentication isn't enabled")
+                    .extend_with(|_, e| e.set("code", "409")),
+            );
+        }
+        let codes = two_factor::replace_recovery_codes(id, pool).await?;
+        Ok(codes)
+    }
+
+    /// Url of the identity provider to send the user to, it redirects back with the `code`
+    /// and `state` for `oidcLogin`. Logged in users link the provider account to theirs,
+    /// which needs their password
+    async fn oidc_authorization_url<'c>(
+        &self,
+        ctx: &Context<'c>,
+        password: Option<String>,
+    ) -> Result<String> {
+        require_cookie_session(ctx)?;
+        let oidc = oidc_client(ctx)?;
+        let session = ctx.data::<SharedSession>()?;
+
+        let intent = match session.get::<i32>("id")? {
+            Some(id) => {
+                let password = password.ok_or_else(|| {
+                    UserAuthError::InvalidUsernameOrPassword(
+                        "Password is required to link an account",
+                    )
+                    .extend_with(|_, e| e.set("code", "401"))
+                })?;
+                rate_limit(ctx, id, Action::Mutation)?;
+                confirm_password(ctx, &session_username(ctx)?, &password).await?;
+                OidcIntent::Link(id)
+            }
+            None => OidcIntent::Login,
+        };
+        let (url, pending) = oidc.authorization_url(intent).await.map_err(|e| {
+            log::error!("{:?}", e);
+            async_graphql::Error::new("Identity provider is unavailable")
+                .extend_with(|_, e| e.set("code", "502"))
+        })?;
+        session.insert("oidc", pending)?;
+        Ok(url)
+    }
+
+    /// Url of the identity provider for logging in again with a linked account, which
+    /// stands in for the password of `deleteAccount` for a few minutes
+    #[graphql(guard = "SessionGuard")]
+    async fn oidc_reauthentication_url<'c>(&self, ctx: &Context<'c>) -> Result<String> {
+        let oidc = oidc_client(ctx)?;
+        let session = ctx.data::<SharedSession>()?;
+        let id = session_user_id(ctx)?;
+
+        let (url, pending) = oidc
+            .authorization_url(OidcIntent::Reauthenticate(id))
+            .await
+            .map_err(|e| {
+                log::error!("{:?}", e);
+                async_graphql::Error::new("Identity provider is unavailable")
+                    .extend_with(|_, e| e.set("code", "502"))
+            })?;
+        session.insert("oidc", pending)?;
+        Ok(url)
+    }
+
+    /// Logs in with the identity provider account, linking it to a user with the same
+    /// verified email or creating a new user if none is linked yet.
+    /// A logged in user links the provider account to theirs instead, when they got the
+    /// url with their password
+    async fn oidc_login<'c>(
+        &self,
+        ctx: &Context<'c>,
+        code: String,
+        state: String,
+    ) -> Result<LoginResponse> {
+        require_cookie_session(ctx)?;
+        let oidc = oidc_client(ctx)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let session = ctx.data::<SharedSession>()?;
+        let invalid = || {
+            UserAuthError::InvalidToken("Login with the identity provider failed")
+                .extend_with(|_, e| e.set("code", "401"))
+        };
+
+        // The state is only good for one try
+        let pending = session.get::<PendingLogin>("oidc")?;
+        session.remove("oidc");
+        let pending = pending.filter(|p| p.state == state).ok_or_else(invalid)?;
+        let claims = oidc.exchange(&code, &pending).await.map_err(|e| {
+            log::error!("{:?}", e);
+            invalid()
+        })?;
+
+        if let Some(id) = session.get::<i32>("id")? {
+            match pending.intent {
+                // Only links that were started with the password of this user
+                OidcIntent::Link(user_id) if user_id == id => {
+                    oidc::link_oidc_identity(
+                        &claims.iss,
+                        &claims.sub,
+                        id,
+                        claims.email.as_deref(),
+                        pool,
+                    )
+                    .await?;
+                }
+                OidcIntent::Reauthenticate(user_id) if user_id == id => {
+                    if get_oidc_user(&claims.iss, &claims.sub, pool).await? != Some(id) {
+                        return Err(invalid());
+                    }
+                    session.insert("reauthenticated_at", chrono::Utc::now().timestamp())?;
+                }
+                _ => return Err(invalid()),
+            }
+            let user = get_user_by_id(id, pool).await?.user;
+            return Ok(LoginResponse {
+                user: Some(user),
+                challenge: None,
+            });
+        }
+
+        let user_id = match get_oidc_user(&claims.iss, &claims.sub, pool).await? {
+            Some(user_id) => user_id,
+            None => {
+                let existing = match claims.verified_email() {
+                    Some(email) => get_user_by_verified_email(email, pool).await?,
+                    None => None,
+                };
+                let user_id = match existing {
+                    Some(user_id) => user_id,
+                    None if oidc.config.auto_provision => {
+                        provision_oidc_user(ctx, &claims).await?.id
+                    }
+                    None => {
+                        return Err(UserAuthError::UserNotFound(
+                            "No user is linked to this account",
+                        )
+                        .extend_with(|_, e| e.set("code", "404")))
+                    }
+                };
+                oidc::link_oidc_identity(
+                    &claims.iss,
+                    &claims.sub,
+                    user_id,
+                    claims.email.as_deref(),
+                    pool,
+                )
+                .await?;
+                user_id
+            }
+        };
+
+        let user = get_user_by_id(user_id, pool).await?.user;
+        login_or_challenge(ctx, user).await
+    }
+
+    /// Users created by logging in with a provider need to set a password with a reset first
+    #[graphql(guard = "SessionGuard")]
+    async fn unlink_oidc_identity<'c>(
+        &self,
+        ctx: &Context<'c>,
+        issuer: String,
+        subject: String,
+        password: String,
+    ) -> Result<bool> {
+        let id = session_user_id(ctx)?;
+        let username = session_username(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        confirm_password(ctx, &username, &password).await?;
+
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(oidc::delete_oidc_identity(&issuer, &subject, id, pool).await?)
+    }
+
+    /// Archive of the profile, posts, comments and uploads of the logged in user
+    #[graphql(guard = "SessionGuard")]
+    async fn export_my_data<'c>(&self, ctx: &Context<'c>) -> Result<DataExport> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::DataExport)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let operator = ctx.data::<Operator>()?;
+        let signer = ctx.data::<UrlSigner>()?;
+
+        let path = export::export_user_data(id, operator, pool).await?;
+        let expires_at = chrono::Utc::now().naive_utc()
+            + chrono::Duration::hours(constants::DATA_EXPORT_TTL_HOURS);
+        Ok(DataExport {
+            url: signer.sign_until(&path, expires_at.timestamp() as u64),
+            expires_at,
+        })
+    }
+
+    /// Erases the account of the logged in user for good. Posts, comments and messages stay
+    /// without showing who wrote them, uploads are removed from them and from the storage.
+    /// Users without a password log in with the identity provider again instead
+    #[graphql(guard = "SessionGuard")]
+    async fn delete_account<'c>(
+        &self,
+        ctx: &Context<'c>,
+        password: Option<String>,
+    ) -> Result<bool> {
+        let session = ctx.data::<SharedSession>()?;
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        match password {
+            Some(password) => confirm_password(ctx, &session_username(ctx)?, &password).await?,
+            None => confirm_reauthentication(ctx)?,
+        }
+
+        let pool = ctx.data::<crate::Pool>()?;
+        let operator = ctx.data::<Operator>()?;
+        let deleted_name = format!("{}{}", constants::DELETED_USERNAME_PREFIX, id);
+        let unusable_password = hash_password(ctx, &token::random_token())?;
+
+        let erased = user::erase_user(id, &deleted_name, &unusable_password, pool).await?;
+        session.purge();
+        kick_user(ctx, id, None)?;
+
+        let index = ctx.data::<SearchIndex>()?;
+        let index_update: SearchUser = erased.user.into();
+        index.user.update(index_update)?;
+
+        // Nothing references the uploads anymore
+        let paths = file::delete_orphaned_files(&erased.files, pool).await?;
+        let mut freed = 0;
+        for (path, _) in paths.iter() {
+            let size = operator
+                .stat(path)
+                .await
+                .map_or(0, |m| m.content_length() as i64);
+            match operator.delete(path).await {
+                Ok(_) => freed += size,
+                Err(e) => log::error!("Failed to delete {path}: {e:?}"),
+            }
+        }
+        file::release_storage(id, freed, pool).await?;
+        for path in erased.exports.iter() {
+            if let Err(e) = operator.delete(path).await {
+                log::error!("Failed to delete {path}: {e:?}");
+            }
+        }
+        for upload in erased.uploads.iter() {
+            if let Err(e) = operator.remove_all(&upload.chunk_dir()).await {
+                log::error!("Failed to delete chunks of upload {}: {e:?}", upload.id);
+            }
+        }
+        Ok(true)
     }
 
     async fn logout<'c>(&self, ctx: &Context<'c>) -> Result<bool> {
         let session = ctx.data::<SharedSession>()?;
+        if let (Some(sid), Some(id)) = (session.session_id()?, session.get::<i32>("id")?) {
+            let pool = ctx.data::<crate::Pool>()?;
+            session::delete_user_session(&sid, id, pool).await?;
+            kick_user(ctx, id, Some(sid))?;
+        }
+        session.purge();
+        Ok(true)
+    }
+
+    /// Logs out one of the sessions listed by `sessions`, closing its open connections
+    #[graphql(guard = "SessionGuard")]
+    async fn revoke_session<'c>(&self, ctx: &Context<'c>, id: String) -> Result<bool> {
+        let session = ctx.data::<SharedSession>()?;
+        let user_id = session_user_id(ctx)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let revoked = session::delete_user_session(&id, user_id, pool).await?;
+        if revoked {
+            if session.session_id()?.as_deref() == Some(id.as_str()) {
+                session.purge();
+            }
+            kick_user(ctx, user_id, Some(id))?;
+        }
+        Ok(revoked)
+    }
+
+    /// Logs out every session of the user, this one included, and closes their open
+    /// connections. Api tokens stay valid
+    #[graphql(guard = "SessionGuard")]
+    async fn logout_everywhere<'c>(&self, ctx: &Context<'c>) -> Result<bool> {
+        let session = ctx.data::<SharedSession>()?;
+        let user_id = session_user_id(ctx)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        session::delete_user_sessions(user_id, pool).await?;
         session.purge();
+        kick_user(ctx, user_id, None)?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "SessionGuard")]
+    async fn change_password<'c>(
+        &self,
+        ctx: &Context<'c>,
+        old_password: String,
+        new_password: String,
+    ) -> Result<bool> {
+        let id = session_user_id(ctx)?;
+        let username = session_username(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        confirm_password(ctx, &username, &old_password).await?;
+        check_password(&new_password, &username)?;
+
+        set_password(ctx, id, &new_password).await?;
+        Ok(true)
+    }
+
+    /// Sets a new email (or removes it when empty) and mails a verification link to it
+    #[graphql(guard = "SessionGuard")]
+    async fn change_email<'c>(
+        &self,
+        ctx: &Context<'c>,
+        password: String,
+        email: Option<String>,
+    ) -> Result<bool> {
+        let id = session_user_id(ctx)?;
+        let username = session_username(ctx)?;
+        rate_limit(ctx, id, Action::VerificationMail)?;
+        confirm_password(ctx, &username, &password).await?;
+        let email = check_email(email)?;
+
+        let pool = ctx.data::<crate::Pool>()?;
+        let user = user::set_email(id, email.as_deref(), pool).await?;
+        token::revoke_tokens(id, TokenPurpose::EmailVerification, pool).await?;
+        send_verification_mail(ctx, &user).await?;
         Ok(true)
     }
 
+    /// Marks the email as verified with a token from the verification mail
+    async fn verify_email<'c>(&self, ctx: &Context<'c>, token: String) -> Result<bool> {
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let user_id = token::consume_token(&token, TokenPurpose::EmailVerification, pool)
+            .await?
+            .ok_or_else(|| {
+                UserAuthError::InvalidToken("Verification token is invalid or expired")
+                    .extend_with(|_, e| e.set("code", "400"))
+            })?;
+        user::mark_email_verified(user_id, pool).await?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn resend_verification_email<'c>(&self, ctx: &Context<'c>) -> Result<bool> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::VerificationMail)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let user = get_user_by_id(id, pool).await?.user;
+        if user.email.is_none() || user.email_verified_at.is_some() {
+            return Ok(false);
+        }
+        send_verification_mail(ctx, &user).await?;
+        Ok(true)
+    }
+
+    /// Creates a personal access token, it's only returned here so it must be copied now
+    #[graphql(guard = "SessionGuard")]
+    async fn create_api_token<'c>(
+        &self,
+        ctx: &Context<'c>,
+        #[graphql(validator(min_length = 1, max_length = 64))] name: String,
+        #[graphql(validator(min_items = 1))] scopes: Vec<ApiScope>,
+        #[graphql(validator(minimum = 1, maximum = 365))] expires_in_days: Option<i64>,
+    ) -> Result<CreatedApiToken> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        if count_api_tokens(id, pool).await? >= constants::MAX_API_TOKENS {
+            return Err(async_graphql::Error::new(format!(
+                "Can't have more than {} api tokens",
+                constants::MAX_API_TOKENS
+            ))
+            .extend_with(|_, e| e.set("code", "422")));
+        }
+        let mut scopes = scopes;
+        scopes.sort();
+        scopes.dedup();
+        let (token, api_token) = api_token::create_api_token(
+            id,
+            &name,
+            &scopes,
+            expires_in_days.map(chrono::Duration::days),
+            pool,
+        )
+        .await?;
+        Ok(CreatedApiToken { token, api_token })
+    }
+
+    #[graphql(guard = "SessionGuard")]
+    async fn revoke_api_token<'c>(&self, ctx: &Context<'c>, id: i32) -> Result<bool> {
+        let user_id = session_user_id(ctx)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let revoked = api_token::revoke_api_token(id, user_id, pool).await?;
+        Ok(revoked)
+    }
+
+    /// Mails a reset link if `login` matches the username or verified email of an account
+    /// with an email. Always succeeds so it can't be used to find accounts
+    async fn request_password_reset<'c>(&self, ctx: &Context<'c>, login: String) -> Result<bool> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let mailer = ctx.data::<Mailer>()?;
+
+        let user = match get_user_by_login(&login, pool).await? {
+            Some(user) => user,
+            None => return Ok(true),
+        };
+        let email = match &user.email {
+            Some(email) => email,
+            None => return Ok(true),
+        };
+        if rate_limit(ctx, user.id, Action::PasswordReset).is_err() {
+            return Ok(true);
+        }
+
+        let token = token::create_token(
+            user.id,
+            TokenPurpose::PasswordReset,
+            chrono::Duration::minutes(constants::PASSWORD_RESET_TTL_MINUTES),
+            pool,
+        )
+        .await?;
+        let body = format!(
+            "Hi {},\n\nSomeone asked to reset the password of {}. Open \
+            {}/reset-password?token={} within {} minutes to choose a new one.\n\n\
+            If it wasn't you, you can ignore this mail.",
+            user.display_name,
+            user.username,
+            mailer.public_url,
+            token,
+            constants::PASSWORD_RESET_TTL_MINUTES
+        );
+        mailer.send(email, "Reset your password", body).await?;
+        Ok(true)
+    }
+
+    /// Sets a new password with a token from `requestPasswordReset`, tokens work once
+    async fn reset_password<'c>(
+        &self,
+        ctx: &Context<'c>,
+        token: String,
+        new_password: String,
+    ) -> Result<bool> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let invalid = || {
+            UserAuthError::InvalidToken("Reset token is invalid or expired")
+                .extend_with(|_, e| e.set("code", "400"))
+        };
+
+        let user_id = token::find_token_user(&token, TokenPurpose::PasswordReset, pool)
+            .await?
+            .ok_or_else(invalid)?;
+        let user = get_user_by_id(user_id, pool).await?.user;
+        check_password(&new_password, &user.username)?;
+
+        // Checked again so a token can't be used twice at the same time
+        token::consume_token(&token, TokenPurpose::PasswordReset, pool)
+            .await?
+            .ok_or_else(invalid)?;
+        set_password(ctx, user_id, &new_password).await?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard")]
     async fn upload<'c>(
         &self,
         ctx: &Context<'c>,
         uploads: Vec<Upload>,
     ) -> Result<Vec<MaybeEmptyFile>> {
-        let session = ctx.data::<SharedSession>()?;
-        let username = session.get::<String>("username")?;
+        let user_id = session_user_id(ctx)?;
+        let username = session_username(ctx)?;
         let mut files = Vec::with_capacity(uploads.len());
-        if let Some(username) = username {
-            let operator = ctx.data::<Operator>()?;
-            for upload in uploads {
-                let mut upload = upload.value(ctx)?;
-                let file = MaybeEmptyFile::new(format!(
-                    "media/{}/{}.{}",
-                    username,
-                    uuid::Uuid::new_v4(),
-                    upload.filename.replace("/", "-")
-                ));
-                file.save(&mut upload.content, operator).await?;
-                files.push(file);
+        let operator = ctx.data::<Operator>()?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let config = ctx.data::<UploadConfig>()?;
+        for upload in uploads {
+            rate_limit(ctx, user_id, Action::Upload)?;
+            let mut upload = upload.value(ctx)?;
+            let size = upload.size()?;
+            if size > config.max_file_size {
+                return Err(UploadError::TooLarge {
+                    size,
+                    max: config.max_file_size,
+                }
+                .extend());
             }
-            return Ok(files);
+            let mut bytes = Vec::with_capacity(size as usize);
+            upload.content.read_to_end(&mut bytes)?;
+            let file = file::store_upload(
+                user_id,
+                &username,
+                &upload.filename,
+                bytes,
+                config,
+                operator,
+                pool,
+            )
+            .await
+            .map_err(|e| match e.downcast::<UploadError>() {
+                Ok(e) => e.extend(),
+                Err(e) => e.into(),
+            })?;
+            files.push(file);
         }
-        Err(
-            async_graphql::Error::new(constants::UNAUTHEMTICATED_MESSAGE)
-                .extend_with(|_, e| e.set("code", "401")),
-        )
+        Ok(files)
+    }
+
+    /// Starts a chunked upload, chunks are then sent with `uploadChunk`
+    #[graphql(guard = "LoginGuard")]
+    async fn start_upload<'c>(
+        &self,
+        ctx: &Context<'c>,
+        filename: String,
+        size: i64,
+    ) -> Result<UploadSession> {
+        let user_id = session_user_id(ctx)?;
+        rate_limit(ctx, user_id, Action::Upload)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let config = ctx.data::<UploadConfig>()?;
+
+        if size <= 0 {
+            return Err(async_graphql::Error::new("Upload can't be empty"));
+        }
+        if size as u64 > config.max_chunked_size {
+            return Err(UploadError::TooLarge {
+                size: size as u64,
+                max: config.max_chunked_size,
+            }
+            .extend());
+        }
+        let chunk_size = config.chunk_size as i64;
+        let new_session = NewUploadSession {
+            owner_id: user_id,
+            filename,
+            size,
+            chunk_size: chunk_size as i32,
+            chunk_count: ((size + chunk_size - 1) / chunk_size) as i32,
+        };
+        let upload_session = upload::create_upload_session(new_session, config, pool)
+            .await
+            .map_err(|e| match e.downcast::<UploadError>() {
+                Ok(e) => e.extend(),
+                Err(e) => e.into(),
+            })?;
+        Ok(upload_session)
+    }
+
+    /// Chunks can be sent in any order, sending one again replaces it
+    #[graphql(guard = "LoginGuard")]
+    async fn upload_chunk<'c>(
+        &self,
+        ctx: &Context<'c>,
+        id: String,
+        index: i32,
+        chunk: Upload,
+    ) -> Result<UploadSession> {
+        let user_id = session_user_id(ctx)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let operator = ctx.data::<Operator>()?;
+        let upload_session = get_upload_session(&id, user_id, pool)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Upload not found"))?;
+
+        if index < 0 || index >= upload_session.chunk_count {
+            return Err(async_graphql::Error::new(format!(
+                "Chunk index must be between 0 and {}",
+                upload_session.chunk_count - 1
+            )));
+        }
+        let mut chunk = chunk.value(ctx)?;
+        let size = chunk.size()? as i64;
+        let expected = upload_session.chunk_len(index);
+        if size != expected {
+            return Err(async_graphql::Error::new(format!(
+                "Chunk {} must be {} bytes, got {}",
+                index, expected, size
+            )));
+        }
+        let mut bytes = Vec::with_capacity(size as usize);
+        chunk.content.read_to_end(&mut bytes)?;
+
+        let upload_session =
+            upload::store_chunk(&upload_session, index, bytes, operator, pool).await?;
+        Ok(upload_session)
+    }
+
+    /// Assembles the chunks, giving the same file `upload` would
+    #[graphql(guard = "LoginGuard")]
+    async fn complete_upload<'c>(&self, ctx: &Context<'c>, id: String) -> Result<MaybeEmptyFile> {
+        let user_id = session_user_id(ctx)?;
+        let username = session_username(ctx)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let operator = ctx.data::<Operator>()?;
+        let config = ctx.data::<UploadConfig>()?;
+        let upload_session = get_upload_session(&id, user_id, pool)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Upload not found"))?;
+
+        if !upload_session.is_complete() {
+            return Err(async_graphql::Error::new(format!(
+                "Only {} of {} chunks were received",
+                upload_session.received.len(),
+                upload_session.chunk_count
+            )));
+        }
+
+        let file = upload::complete_upload(&upload_session, &username, config, operator, pool)
+            .await
+            .map_err(|e| match e.downcast::<UploadError>() {
+                Ok(e) => e.extend(),
+                Err(e) => e.into(),
+            })?;
+        Ok(file)
+    }
+
+    /// Drops a chunked upload along with the chunks received so far
+    #[graphql(guard = "LoginGuard")]
+    async fn cancel_upload<'c>(&self, ctx: &Context<'c>, id: String) -> Result<bool> {
+        let user_id = session_user_id(ctx)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let operator = ctx.data::<Operator>()?;
+        let upload_session = get_upload_session(&id, user_id, pool)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Upload not found"))?;
+
+        upload::delete_upload_session(&upload_session, operator, pool).await?;
+        Ok(true)
     }
 
+    #[graphql(guard = "LoginGuard")]
     async fn create_forum<'c>(
         &self,
         ctx: &Context<'c>,
@@ -206,167 +1311,377 @@ impl Mutation {
         name: Option<String>,
         description: Option<String>,
     ) -> Result<Forum> {
-        let session = ctx.data::<SharedSession>()?;
-
-        if session.get::<String>("username")?.is_some() {
-            let name = name.unwrap_or_else(|| slug::slugify(display_name.clone()));
-            let owner_id = session.get::<i32>("id")?.unwrap();
-            let pool = ctx.data::<crate::Pool>()?;
-            let x = forum::create_forum(owner_id, name, display_name, description, &pool).await?;
+        let name = name.unwrap_or_else(|| slug::slugify(display_name.clone()));
+        let owner_id = session_user_id(ctx)?;
+        rate_limit(ctx, owner_id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let x = forum::create_forum(owner_id, name, display_name, description, &pool).await?;
 
-            let index = ctx.data::<SearchIndex>()?;
-            let search_forum: SearchForum = x.clone().into();
-            index.forum.add(search_forum)?;
+        let index = ctx.data::<SearchIndex>()?;
+        let search_forum: SearchForum = x.clone().into();
+        index.forum.add(search_forum)?;
 
-            let event_manager = ctx.data::<Addr<EventManager>>()?;
+        let event_manager = ctx.data::<Addr<EventManager>>()?;
 
-            event_manager.do_send(ForumEvent {
-                ty: ForumEventTy::ForumCreation,
-                forum: x.clone(),
-            });
+        event_manager.do_send(ForumEvent {
+            ty: ForumEventTy::ForumCreation,
+            forum: x.clone(),
+        });
 
-            return Ok(x);
-        }
-        Err(
-            async_graphql::Error::new(constants::UNAUTHEMTICATED_MESSAGE)
-                .extend_with(|_, e| e.set("code", "401")),
-        )
+        Ok(x)
     }
 
+    #[graphql(guard = "LoginGuard")]
     async fn update_forum_basic<'c>(
         &self,
         ctx: &Context<'c>,
         changes: forum::BasicForumUpdate,
     ) -> Result<Forum> {
-        let session = ctx.data::<SharedSession>()?;
-        let id = session.get::<i32>("id")?;
-
-        if let Some(id) = id {
-            let pool = ctx.data::<crate::Pool>()?;
-            let index = ctx.data::<SearchIndex>()?;
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let index = ctx.data::<SearchIndex>()?;
 
-            let changes: UpdateForum = changes.into();
-            let forum = forum::update_forum(id, &changes, &pool).await?;
+        let changes: UpdateForum = changes.into();
+        if changes.owner_id.is_some() && !owns_forum(ctx, changes.id).await? {
+            return Err(
+                async_graphql::Error::new("Only the owner can hand the forum over")
+                    .extend_with(|_, e| e.set("code", "403")),
+            );
+        }
+        let manager =
+            has_forum_permission(ctx, ForumTarget::Forum(changes.id), ForumPermission::Manage)
+                .await?;
+        let forum = forum::update_forum(id, &changes, manager, &pool).await?;
 
-            let index_update: SearchForum = forum.clone().into();
-            index.forum.update(index_update)?;
+        let index_update: SearchForum = forum.clone().into();
+        index.forum.update(index_update)?;
 
-            let event_manager = ctx.data::<Addr<EventManager>>()?;
+        let event_manager = ctx.data::<Addr<EventManager>>()?;
 
-            event_manager.do_send(ForumEvent {
-                ty: ForumEventTy::ForumBasicUpdate,
-                forum: forum.clone(),
-            });
+        event_manager.do_send(ForumEvent {
+            ty: ForumEventTy::ForumBasicUpdate,
+            forum: forum.clone(),
+        });
 
-            return Ok(forum);
-        }
-        Err(
-            async_graphql::Error::new(constants::UNAUTHEMTICATED_MESSAGE)
-                .extend_with(|_, e| e.set("code", "401")),
-        )
+        Ok(forum)
     }
 
+    #[graphql(guard = "LoginGuard")]
     async fn create_post<'c>(&self, ctx: &Context<'c>, input_post: InputPost) -> Result<Post> {
-        let session = ctx.data::<SharedSession>()?;
         let random_suffix = rand::thread_rng().gen_range(1..=9999);
         let slug = format!(
             "{}-{}",
             slug::slugify(input_post.title.clone()),
             random_suffix
         );
-        if session.get::<String>("username")?.is_some() {
-            let poster_id = session.get::<i32>("id")?.unwrap();
-            let pool = ctx.data::<crate::Pool>()?;
-            let x = post::create_post(
-                input_post.tags,
-                input_post.title,
-                slug,
-                input_post.content,
-                input_post.media,
-                input_post.forum,
-                poster_id,
-                &pool,
-            )
-            .await?;
+        let poster_id = session_user_id(ctx)?;
+        rate_limit(ctx, poster_id, Action::CreatePost)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        let x = post::create_post(
+            input_post.tags,
+            input_post.title,
+            slug,
+            input_post.content,
+            input_post.media,
+            input_post.forum,
+            poster_id,
+            &pool,
+        )
+        .await?;
 
-            let index = ctx.data::<SearchIndex>()?;
-            let search_post: SearchPost = x.clone().into();
-            index.post.add(search_post)?;
+        let index = ctx.data::<SearchIndex>()?;
+        let search_post: SearchPost = x.clone().into();
+        index.post.add(search_post)?;
 
-            let event_manager = ctx.data::<Addr<EventManager>>()?;
+        let event_manager = ctx.data::<Addr<EventManager>>()?;
 
-            event_manager.do_send(PostEvent {
-                ty: PostEventTy::PostCreation,
-                post: x.clone(),
-            });
+        event_manager.do_send(PostEvent {
+            ty: PostEventTy::PostCreation,
+            post: x.clone(),
+        });
 
-            return Ok(x);
-        }
-        Err(
-            async_graphql::Error::new(constants::UNAUTHEMTICATED_MESSAGE)
-                .extend_with(|_, e| e.set("code", "401")),
-        )
+        let mentions =
+            mention::store_mentions(MentionSource::Post(x.id), x.content.as_deref(), pool).await;
+        publish_notifications(
+            ctx,
+            notification::notify_post_mentions(&x, &mentions, pool).await,
+        );
+
+        Ok(x)
     }
 
+    #[graphql(guard = "LoginGuard")]
     async fn update_post_basic<'c>(
         &self,
         ctx: &Context<'c>,
         changes: post::BasicPostUpdate,
     ) -> Result<Post> {
-        let session = ctx.data::<SharedSession>()?;
-        let id = session.get::<i32>("id")?;
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        let mut conn = ctx.data::<crate::Pool>()?;
+        let index = ctx.data::<SearchIndex>()?;
 
-        if let Some(id) = id {
-            let mut conn = ctx.data::<crate::Pool>()?;
-            let index = ctx.data::<SearchIndex>()?;
+        let changes: UpdatePost = changes.into();
+        let moderator =
+            has_forum_permission(ctx, ForumTarget::Post(changes.id), ForumPermission::Moderate)
+                .await?;
+        let post = post::update_post(id, &changes, moderator, &mut conn).await?;
 
-            let changes: UpdatePost = changes.into();
-            let post = post::update_post(id, &changes, &mut conn).await?;
+        if changes.content.is_some() {
+            mention::store_mentions(MentionSource::Post(post.id), post.content.as_deref(), conn)
+                .await;
+        }
 
-            let index_update: SearchPost = post.clone().into();
-            index.post.update(index_update)?;
+        let index_update: SearchPost = post.clone().into();
+        index.post.update(index_update)?;
 
-            let event_manager = ctx.data::<Addr<EventManager>>()?;
+        let event_manager = ctx.data::<Addr<EventManager>>()?;
 
-            event_manager.do_send(PostEvent {
-                ty: PostEventTy::PostBasicUpdate,
-                post: post.clone(),
-            });
+        event_manager.do_send(PostEvent {
+            ty: PostEventTy::PostBasicUpdate,
+            post: post.clone(),
+        });
 
-            return Ok(post);
-        }
-        Err(
-            async_graphql::Error::new(constants::UNAUTHEMTICATED_MESSAGE)
-                .extend_with(|_, e| e.set("code", "401")),
-        )
+        Ok(post)
     }
 
+    #[graphql(guard = "LoginGuard")]
     async fn update_comment_basic<'c>(
         &self,
         ctx: &Context<'c>,
         changes: comment::BasicCommentUpdate,
     ) -> Result<Comment> {
-        let session = ctx.data::<SharedSession>()?;
-        let id = session.get::<i32>("id")?;
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
 
-        if let Some(id) = id {
-            let pool = ctx.data::<crate::Pool>()?;
+        let changes: UpdateComment = changes.into();
+        let moderator = has_forum_permission(
+            ctx,
+            ForumTarget::Comment(changes.id),
+            ForumPermission::Moderate,
+        )
+        .await?;
+        let comment = comment::update_comment(id, &changes, moderator, &pool).await?;
 
-            let changes: UpdateComment = changes.into();
-            let comment = comment::update_comment(id, &changes, &pool).await?;
+        if changes.content.is_some() {
+            mention::store_mentions(
+                MentionSource::Comment(comment.id),
+                Some(&comment.content),
+                pool,
+            )
+            .await;
+        }
 
-            let event_manager = ctx.data::<Addr<EventManager>>()?;
+        let event_manager = ctx.data::<Addr<EventManager>>()?;
 
-            event_manager.do_send(CommentEvent {
-                ty: CommentEventTy::CommentCreation,
-                comment: comment.clone(),
-            });
+        event_manager.do_send(CommentEvent {
+            ty: CommentEventTy::CommentCreation,
+            comment: comment.clone(),
+        });
+
+        Ok(comment)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn create_conversation<'c>(
+        &self,
+        ctx: &Context<'c>,
+        user_ids: Vec<i32>,
+        title: Option<String>,
+    ) -> Result<Conversation> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let x = conversation::create_conversation(id, user_ids, title, pool).await?;
+
+        Ok(x)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn send_message<'c>(
+        &self,
+        ctx: &Context<'c>,
+        conversation_id: i32,
+        content: String,
+        media: Option<Vec<String>>,
+    ) -> Result<Message> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Message)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let message =
+            conversation::create_message(id, conversation_id, content, media, pool).await?;
+
+        let dm_server = ctx.data::<Addr<DmServer>>()?;
+
+        dm_server.do_send(DmBroadcast {
+            message: message.clone(),
+        });
+
+        Ok(message)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn mark_conversation_read<'c>(
+        &self,
+        ctx: &Context<'c>,
+        conversation_id: i32,
+    ) -> Result<bool> {
+        let id = session_user_id(ctx)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let x = conversation::mark_conversation_read(id, conversation_id, pool).await?;
 
-            return Ok(comment);
+        Ok(x)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn create_comment<'c>(
+        &self,
+        ctx: &Context<'c>,
+        post_id: i32,
+        parent_id: Option<i32>,
+        content: String,
+        media: Option<Vec<String>>,
+    ) -> Result<Comment> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Comment)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let x =
+            comment::create_post_comment(id, post_id, parent_id, content, media, pool).await?;
+
+        let event_manager = ctx.data::<Addr<EventManager>>()?;
+
+        event_manager.do_send(CommentEvent {
+            ty: CommentEventTy::CommentCreation,
+            comment: x.clone(),
+        });
+
+        let mentions =
+            mention::store_mentions(MentionSource::Comment(x.id), Some(&x.content), pool).await;
+        publish_notifications(ctx, notification::notify_comment(&x, &mentions, pool).await);
+
+        Ok(x)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn star_post<'c>(&self, ctx: &Context<'c>, post_id: i32) -> Result<Post> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let (post, starred) = post::star_post(id, post_id, pool).await?;
+
+        if starred {
+            publish_notifications(ctx, notification::notify_star(&post, id, pool).await);
         }
-        Err(
-            async_graphql::Error::new(constants::UNAUTHEMTICATED_MESSAGE)
-                .extend_with(|_, e| e.set("code", "401")),
+
+        Ok(post)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn unstar_post<'c>(&self, ctx: &Context<'c>, post_id: i32) -> Result<Post> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let post = post::unstar_post(id, post_id, pool).await?;
+
+        Ok(post)
+    }
+
+    /// Marks the given notifications as read, or all of them if `ids` is not set
+    #[graphql(guard = "LoginGuard")]
+    async fn mark_read<'c>(&self, ctx: &Context<'c>, ids: Option<Vec<i32>>) -> Result<i64> {
+        let id = session_user_id(ctx)?;
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let x = notification::mark_read(id, ids, pool).await?;
+
+        Ok(x)
+    }
+
+    /// Deletes (or only lists with `dry_run`) uploads nothing references anymore
+    #[graphql(guard = "PermissionGuard(Permission::ManageSite)")]
+    async fn sweep_media<'c>(&self, ctx: &Context<'c>, dry_run: bool) -> Result<SweepReport> {
+        let sweeper = ctx.data::<Addr<MediaSweeper>>()?;
+        let report = sweeper.send(Sweep { dry_run }).await??;
+        Ok(report)
+    }
+
+    /// Lifts the lock of an account after failed logins, the lock on ips stays
+    #[graphql(guard = "PermissionGuard(Permission::ManageLockedAccounts)")]
+    async fn unlock_account<'c>(&self, ctx: &Context<'c>, username: String) -> Result<bool> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let unlocked = login_failure::clear_login_failures(
+            LoginFailureKind::Username,
+            &username.to_lowercase(),
+            pool,
         )
+        .await?;
+        Ok(unlocked)
+    }
+
+    /// Gives a user a site role, `USER` takes away the one they had
+    #[graphql(guard = "PermissionGuard(Permission::ManageRoles)")]
+    async fn set_user_role<'c>(
+        &self,
+        ctx: &Context<'c>,
+        user_id: i32,
+        role: SiteRole,
+    ) -> Result<SiteRole> {
+        let id = session_user_id(ctx)?;
+        // So the last admin can't lock everyone out
+        if user_id == id {
+            return Err(async_graphql::Error::new("Can't change your own role")
+                .extend_with(|_, e| e.set("code", "409")));
+        }
+        rate_limit(ctx, id, Action::Mutation)?;
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(role::set_user_role(user_id, role, pool).await?)
+    }
+
+    #[graphql(guard = "ForumGuard::new(ForumPermission::Manage, ForumTarget::Forum(forum_id))")]
+    async fn set_forum_role<'c>(
+        &self,
+        ctx: &Context<'c>,
+        forum_id: i32,
+        user_id: i32,
+        role: ForumRole,
+    ) -> Result<ForumMember> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        check_forum_role_change(ctx, forum_id, user_id, Some(role)).await?;
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(role::set_forum_role(forum_id, user_id, role, pool).await?)
+    }
+
+    #[graphql(guard = "ForumGuard::new(ForumPermission::Manage, ForumTarget::Forum(forum_id))")]
+    async fn remove_forum_role<'c>(
+        &self,
+        ctx: &Context<'c>,
+        forum_id: i32,
+        user_id: i32,
+    ) -> Result<bool> {
+        let id = session_user_id(ctx)?;
+        rate_limit(ctx, id, Action::Mutation)?;
+        check_forum_role_change(ctx, forum_id, user_id, None).await?;
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(role::remove_forum_role(forum_id, user_id, pool).await?)
+    }
+
+    #[graphql(guard = "PermissionGuard(Permission::ManageSite)")]
+    async fn update_site_settings<'c>(
+        &self,
+        ctx: &Context<'c>,
+        require_verified_email: Option<bool>,
+    ) -> Result<SiteSettings> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let settings = settings::update_site_settings(require_verified_email, pool).await?;
+        Ok(settings)
     }
 }
@@ -0,0 +1,154 @@
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::db::models::comment::Comment;
+use crate::db::models::notification::{NewNotification, Notification, NotificationKind};
+use crate::db::models::post::Post;
+use crate::db::models::user::User;
+
+/// Inserts the notifications, skipping the ones where users would notify themselves
+pub async fn create_notifications(
+    notifications: Vec<NewNotification>,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<Notification>> {
+    let notifications = notifications
+        .into_iter()
+        .filter(|n| n.user_id != n.actor_id)
+        .collect::<Vec<_>>();
+    if notifications.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "INSERT INTO notifications (user_id, actor_id, kind, post_id, comment_id) ",
+    );
+    builder.push_values(notifications, |mut b, n| {
+        b.push_bind(n.user_id)
+            .push_bind(n.actor_id)
+            .push_bind(n.kind)
+            .push_bind(n.post_id)
+            .push_bind(n.comment_id);
+    });
+    builder.push(" RETURNING *;");
+
+    let notifications = builder
+        .build_query_as::<Notification>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(notifications)
+}
+
+/// Notifies the author of the parent comment (or the post) and everyone mentioned
+pub async fn notify_comment(
+    comment: &Comment,
+    mentioned: &[User],
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<Notification>> {
+    let (recipient, kind) = match comment.parent_id {
+        Some(parent_id) => (
+            sqlx::query_scalar!("SELECT user_id FROM comments WHERE id = $1", parent_id)
+                .fetch_one(pool)
+                .await?,
+            NotificationKind::CommentReply,
+        ),
+        None => (
+            sqlx::query_scalar!("SELECT poster_id FROM posts WHERE id = $1", comment.post_id)
+                .fetch_one(pool)
+                .await?,
+            NotificationKind::PostReply,
+        ),
+    };
+
+    let mut notifications = vec![NewNotification {
+        user_id: recipient,
+        actor_id: comment.user_id,
+        kind,
+        post_id: Some(comment.post_id),
+        comment_id: Some(comment.id),
+    }];
+
+    for user in mentioned {
+        // Already notified about the reply
+        if user.id == recipient {
+            continue;
+        }
+        notifications.push(NewNotification {
+            user_id: user.id,
+            actor_id: comment.user_id,
+            kind: NotificationKind::Mention,
+            post_id: Some(comment.post_id),
+            comment_id: Some(comment.id),
+        });
+    }
+
+    create_notifications(notifications, pool).await
+}
+
+pub async fn notify_post_mentions(
+    post: &Post,
+    mentioned: &[User],
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<Notification>> {
+    let notifications = mentioned
+        .iter()
+        .map(|user| NewNotification {
+            user_id: user.id,
+            actor_id: post.poster_id,
+            kind: NotificationKind::Mention,
+            post_id: Some(post.id),
+            comment_id: None,
+        })
+        .collect();
+
+    create_notifications(notifications, pool).await
+}
+
+pub async fn notify_star(
+    post: &Post,
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<Notification>> {
+    create_notifications(
+        vec![NewNotification {
+            user_id: post.poster_id,
+            actor_id: user_id,
+            kind: NotificationKind::Star,
+            post_id: Some(post.id),
+            comment_id: None,
+        }],
+        pool,
+    )
+    .await
+}
+
+/// Marks the given notifications (or all of them) as read, returns how many changed
+pub async fn mark_read(
+    user_id: i32,
+    ids: Option<Vec<i32>>,
+    pool: &crate::Pool,
+) -> anyhow::Result<i64> {
+    let result = match ids {
+        Some(ids) => {
+            sqlx::query!(
+                "
+                UPDATE notifications SET read = true
+                WHERE user_id = $1 AND id = ANY($2) AND read = false;
+                ",
+                user_id,
+                &ids,
+            )
+            .execute(pool)
+            .await?
+        }
+        None => {
+            sqlx::query!(
+                "UPDATE notifications SET read = true WHERE user_id = $1 AND read = false;",
+                user_id,
+            )
+            .execute(pool)
+            .await?
+        }
+    };
+
+    Ok(result.rows_affected() as i64)
+}
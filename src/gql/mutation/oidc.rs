@@ -0,0 +1,53 @@
+use crate::error::UserAuthError;
+
+/// Links the provider account `subject` to the user, failing when it's linked to someone
+/// else. Linking it again to the same user only refreshes the email
+pub async fn link_oidc_identity(
+    issuer: &str,
+    subject: &str,
+    user_id: i32,
+    email: Option<&str>,
+    pool: &crate::Pool,
+) -> anyhow::Result<()> {
+    let linked = sqlx::query!(
+        "
+        INSERT INTO oidc_identities (issuer, subject, user_id, email)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (issuer, subject) DO UPDATE SET email = EXCLUDED.email
+        WHERE oidc_identities.user_id = EXCLUDED.user_id;
+        ",
+        issuer,
+        subject,
+        user_id,
+        email,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if linked == 0 {
+        return Err(
+            UserAuthError::InvalidToken("This account is already linked to another user").into(),
+        );
+    }
+    Ok(())
+}
+
+/// Returns whether the user had the provider account linked
+pub async fn delete_oidc_identity(
+    issuer: &str,
+    subject: &str,
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    let deleted = sqlx::query!(
+        "DELETE FROM oidc_identities WHERE issuer = $1 AND subject = $2 AND user_id = $3;",
+        issuer,
+        subject,
+        user_id,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(deleted > 0)
+}
@@ -7,6 +7,8 @@ use crate::db::models::post::NewPost;
 use crate::db::models::post::Post;
 use crate::db::models::post::UpdatePost;
 use crate::db::models::FileList;
+use crate::gql::query::settings::ensure_can_post;
+use crate::markdown;
 
 #[derive(InputObject)]
 pub struct BasicPostUpdate {
@@ -59,24 +61,18 @@ pub async fn create_post<'a>(
     poster: i32,
     pool: &crate::Pool,
 ) -> anyhow::Result<Post> {
+    ensure_can_post(poster, pool).await?;
     if let Some(t) = &media {
-        let files = FileList::new(t.clone()).files;
-        if let Some(files) = files {
-            for file in files {
-                if !file.status().await?.insertable() {
-                    return Err(anyhow::Error::msg(format!(
-                        "File with id: {} doesn't exist (media upload)",
-                        &file.id.clone().unwrap_or("null".into())
-                    )));
-                }
-            }
-        }
+        FileList::new(t.clone())
+            .check_insertable(poster, pool)
+            .await?;
     }
 
     let new_post = NewPost {
         tags,
         title,
         slug,
+        content_html: content.as_deref().map(markdown::render),
         content,
         media,
         forum_id: forum,
@@ -86,13 +82,14 @@ pub async fn create_post<'a>(
     let post = sqlx::query_as!(
         Post,
         "
-        INSERT INTO posts (title, slug, content, tags, media, forum_id, poster_id)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        INSERT INTO posts (title, slug, content, content_html, tags, media, forum_id, poster_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         RETURNING *;
         ",
         new_post.title,
         new_post.slug,
         new_post.content,
+        new_post.content_html,
         new_post.tags.as_ref().map(Vec::as_slice),
         new_post.media.as_ref().map(Vec::as_slice),
         new_post.forum_id,
@@ -104,12 +101,41 @@ pub async fn create_post<'a>(
     Ok(post)
 }
 
+/// Applies the changes and archives the previous version as a revision. Moderators can
+/// edit posts of others, media still has to be uploaded by the poster
 pub async fn update_post(
     user_id: i32,
     changes: &UpdatePost,
+    moderator: bool,
     pool: &crate::Pool,
 ) -> anyhow::Result<Post> {
-    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("UPDATE forums SET ");
+    let mut tx = pool.begin().await?;
+
+    let poster_id = sqlx::query_scalar!(
+        "SELECT poster_id FROM posts WHERE id = $1 AND (poster_id = $2 OR $3) FOR UPDATE;",
+        changes.id,
+        user_id,
+        moderator,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| anyhow::Error::msg("Post not found"))?;
+    if let Some(v) = &changes.media {
+        v.check_insertable(poster_id, pool).await?;
+    }
+
+    sqlx::query!(
+        "
+        INSERT INTO post_revisions (post_id, title, tags, content, media, created_at)
+        SELECT id, title, tags, content, media, COALESCE(edited_at, created_at)
+        FROM posts WHERE id = $1;
+        ",
+        changes.id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("UPDATE posts SET ");
     let mut prev = false;
     if let Some(v) = &changes.tags {
         builder.push("tags = ");
@@ -147,6 +173,8 @@ pub async fn update_post(
         prev = true;
         builder.push("content = ");
         builder.push_bind(v);
+        builder.push(", content_html = ");
+        builder.push_bind(v.as_deref().map(markdown::render));
     }
     if let Some(v) = &changes.media {
         let files = v.ids();
@@ -175,11 +203,73 @@ pub async fn update_post(
 
     builder.push(" WHERE id = ");
     builder.push_bind(changes.id);
-    builder.push(" AND poster_id = ");
-    builder.push_bind(user_id);
     builder.push(" RETURNING *;");
 
-    let post = builder.build_query_as::<Post>().fetch_one(pool).await?;
+    let post = builder.build_query_as::<Post>().fetch_one(&mut *tx).await?;
+
+    tx.commit().await?;
+
+    Ok(post)
+}
+
+/// Returns the post and whether this user hadn't starred it before
+pub async fn star_post(
+    user_id: i32,
+    post_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<(Post, bool)> {
+    let mut tx = pool.begin().await?;
+
+    let inserted = sqlx::query!(
+        "
+        INSERT INTO post_stars (user_id, post_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING;
+        ",
+        user_id,
+        post_id,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+
+    let post = sqlx::query_as!(
+        Post,
+        "UPDATE posts SET stars = stars + $2 WHERE id = $1 RETURNING *;",
+        post_id,
+        inserted as i32,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((post, inserted))
+}
+
+pub async fn unstar_post(user_id: i32, post_id: i32, pool: &crate::Pool) -> anyhow::Result<Post> {
+    let mut tx = pool.begin().await?;
+
+    let deleted = sqlx::query!(
+        "DELETE FROM post_stars WHERE user_id = $1 AND post_id = $2;",
+        user_id,
+        post_id,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected() as i32;
+
+    let post = sqlx::query_as!(
+        Post,
+        "UPDATE posts SET stars = stars - $2 WHERE id = $1 RETURNING *;",
+        post_id,
+        deleted,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
 
     Ok(post)
 }
@@ -0,0 +1,73 @@
+use crate::db::models::role::{ForumMember, ForumRole, SiteRole};
+
+/// Gives the user a site role, `User` removes the one they had
+pub async fn set_user_role(
+    user_id: i32,
+    role: SiteRole,
+    pool: &crate::Pool,
+) -> anyhow::Result<SiteRole> {
+    if role == SiteRole::User {
+        sqlx::query!("DELETE FROM user_roles WHERE user_id = $1;", user_id)
+            .execute(pool)
+            .await?;
+        return Ok(role);
+    }
+
+    let granted = sqlx::query!(
+        "
+        INSERT INTO user_roles (user_id, role)
+        SELECT id, $2 FROM users WHERE id = $1
+        ON CONFLICT (user_id) DO UPDATE
+        SET role = EXCLUDED.role, granted_at = (now() AT TIME ZONE 'UTC');
+        ",
+        user_id,
+        role as SiteRole,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if granted == 0 {
+        return Err(anyhow::Error::msg("User not found"));
+    }
+    Ok(role)
+}
+
+/// Gives the user a role in the forum, the owner keeps being its admin
+pub async fn set_forum_role(
+    forum_id: i32,
+    user_id: i32,
+    role: ForumRole,
+    pool: &crate::Pool,
+) -> anyhow::Result<ForumMember> {
+    let member = sqlx::query_as!(
+        ForumMember,
+        r#"
+        INSERT INTO forum_roles (forum_id, user_id, role)
+        SELECT f.id, u.id, $3 FROM forums f, users u
+        WHERE f.id = $1 AND u.id = $2 AND f.owner_id <> u.id
+        ON CONFLICT (forum_id, user_id) DO UPDATE SET role = EXCLUDED.role
+        RETURNING forum_id, user_id, role AS "role: ForumRole", created_at;
+        "#,
+        forum_id,
+        user_id,
+        role as ForumRole,
+    )
+    .fetch_optional(pool)
+    .await?;
+    member.ok_or_else(|| anyhow::Error::msg("User not found or already owns the forum"))
+}
+
+pub async fn remove_forum_role(
+    forum_id: i32,
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        "DELETE FROM forum_roles WHERE forum_id = $1 AND user_id = $2;",
+        forum_id,
+        user_id,
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
@@ -0,0 +1,92 @@
+use crate::auth::ClientInfo;
+use crate::constants::SESSION_TTL_HOURS;
+use crate::db::models::role::SiteRole;
+
+/// Records a new login of the user, returning the id to keep in the cookie session.
+/// Rows of sessions that expired in the meantime are dropped
+pub async fn create_user_session(
+    user_id: i32,
+    client: &ClientInfo,
+    pool: &crate::Pool,
+) -> anyhow::Result<String> {
+    sqlx::query!(
+        "
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND created_at < (now() AT TIME ZONE 'UTC') - make_interval(hours => $2);
+        ",
+        user_id,
+        SESSION_TTL_HOURS as i32,
+    )
+    .execute(pool)
+    .await?;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query!(
+        "INSERT INTO user_sessions (id, user_id, user_agent, ip) VALUES ($1, $2, $3, $4);",
+        id,
+        user_id,
+        client.user_agent,
+        client.ip,
+    )
+    .execute(pool)
+    .await?;
+    Ok(id)
+}
+
+/// Site role and current username of the user if the session wasn't revoked,
+/// bumping `last_seen_at` at most once a minute
+pub async fn touch_user_session(
+    id: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<(SiteRole, String)>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT s.last_seen_at < (now() AT TIME ZONE 'UTC') - interval '1 minute' AS "stale!",
+        r.role AS "role?: SiteRole", u.username
+        FROM user_sessions s
+        JOIN users u ON u.id = s.user_id
+        LEFT JOIN user_roles r ON r.user_id = s.user_id
+        WHERE s.id = $1;
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    if row.stale {
+        sqlx::query!(
+            "UPDATE user_sessions SET last_seen_at = (now() AT TIME ZONE 'UTC') WHERE id = $1;",
+            id
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(Some((row.role.unwrap_or(SiteRole::User), row.username)))
+}
+
+pub async fn delete_user_session(
+    id: &str,
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        "DELETE FROM user_sessions WHERE id = $1 AND user_id = $2;",
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Revokes every session of the user, they're logged out on their next request
+pub async fn delete_user_sessions(user_id: i32, pool: &crate::Pool) -> anyhow::Result<()> {
+    sqlx::query!("DELETE FROM user_sessions WHERE user_id = $1;", user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
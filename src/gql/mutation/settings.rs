@@ -0,0 +1,19 @@
+use crate::db::models::settings::SiteSettings;
+
+pub async fn update_site_settings(
+    require_verified_email: Option<bool>,
+    pool: &crate::Pool,
+) -> anyhow::Result<SiteSettings> {
+    let settings = sqlx::query_as!(
+        SiteSettings,
+        "
+        UPDATE site_settings
+        SET require_verified_email = COALESCE($1, require_verified_email)
+        RETURNING require_verified_email;
+        ",
+        require_verified_email
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(settings)
+}
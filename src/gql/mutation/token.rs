@@ -0,0 +1,96 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::db::models::token::TokenPurpose;
+
+/// Only the hash is stored so a leaked table can't be used to take over accounts
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Creates a single use token valid for `ttl`, returning the token to send to the user
+pub async fn create_token(
+    user_id: i32,
+    purpose: TokenPurpose,
+    ttl: chrono::Duration,
+    pool: &crate::Pool,
+) -> anyhow::Result<String> {
+    let token = random_token();
+
+    sqlx::query!(
+        "
+        INSERT INTO user_tokens (token_hash, user_id, purpose, expires_at)
+        VALUES ($1, $2, $3, $4);
+        ",
+        hash_token(&token),
+        user_id,
+        purpose as TokenPurpose,
+        chrono::Utc::now().naive_utc() + ttl,
+    )
+    .execute(pool)
+    .await?;
+    Ok(token)
+}
+
+/// User the token belongs to, if it's still valid. Doesn't use it up
+pub async fn find_token_user(
+    token: &str,
+    purpose: TokenPurpose,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<i32>> {
+    let user_id = sqlx::query_scalar!(
+        "
+        SELECT user_id FROM user_tokens
+        WHERE token_hash = $1 AND purpose = $2
+        AND used_at IS NULL AND expires_at > (now() AT TIME ZONE 'UTC');
+        ",
+        hash_token(token),
+        purpose as TokenPurpose,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(user_id)
+}
+
+/// Marks the token as used, returning its user only if it was still valid
+pub async fn consume_token(
+    token: &str,
+    purpose: TokenPurpose,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<i32>> {
+    let user_id = sqlx::query_scalar!(
+        "
+        UPDATE user_tokens SET used_at = (now() AT TIME ZONE 'UTC')
+        WHERE token_hash = $1 AND purpose = $2
+        AND used_at IS NULL AND expires_at > (now() AT TIME ZONE 'UTC')
+        RETURNING user_id;
+        ",
+        hash_token(token),
+        purpose as TokenPurpose,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(user_id)
+}
+
+/// Drops the unused tokens of the user, eg. once the password was changed
+pub async fn revoke_tokens(
+    user_id: i32,
+    purpose: TokenPurpose,
+    pool: &crate::Pool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "DELETE FROM user_tokens WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL;",
+        user_id,
+        purpose as TokenPurpose,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
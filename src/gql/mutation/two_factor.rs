@@ -0,0 +1,134 @@
+use rand::RngCore;
+
+use crate::gql::mutation::token::hash_token;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Starts (or restarts) enrollment with a new secret, does nothing once it's confirmed
+pub async fn set_pending_totp(
+    user_id: i32,
+    secret: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        "
+        INSERT INTO totp_secrets (user_id, secret) VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+        SET secret = EXCLUDED.secret, created_at = EXCLUDED.created_at
+        WHERE totp_secrets.confirmed_at IS NULL;
+        ",
+        user_id,
+        secret,
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Enables two factor authentication, `step` is the one of the code used to confirm it
+pub async fn confirm_totp(user_id: i32, step: i64, pool: &crate::Pool) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+        UPDATE totp_secrets
+        SET confirmed_at = (now() AT TIME ZONE 'UTC'), last_used_step = $2
+        WHERE user_id = $1;
+        ",
+        user_id,
+        step,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Marks the step as used, `false` if a code of it (or a later step) was used already
+pub async fn use_totp_step(user_id: i32, step: i64, pool: &crate::Pool) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        "
+        UPDATE totp_secrets SET last_used_step = $2
+        WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2);
+        ",
+        user_id,
+        step,
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Disables two factor authentication, dropping the recovery codes with it
+pub async fn delete_totp(user_id: i32, pool: &crate::Pool) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM totp_secrets WHERE user_id = $1;", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1;", user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Lowercase with whitespace and dashes removed, so codes can be typed however
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Replaces the recovery codes of the user with new ones, returning them. Only their hashes
+/// are stored
+pub async fn replace_recovery_codes(
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<String>> {
+    let codes = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect::<Vec<_>>();
+    let hashes = codes
+        .iter()
+        .map(|code| hash_token(&normalize_recovery_code(code)))
+        .collect::<Vec<_>>();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1;", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "
+        INSERT INTO recovery_codes (code_hash, user_id)
+        SELECT code_hash, $2 FROM UNNEST($1::VARCHAR[]) AS code_hash;
+        ",
+        &hashes,
+        user_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(codes)
+}
+
+/// Uses up the recovery code, `false` if it isn't one of the user's unused codes
+pub async fn use_recovery_code(
+    user_id: i32,
+    code: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        "
+        UPDATE recovery_codes SET used_at = (now() AT TIME ZONE 'UTC')
+        WHERE code_hash = $1 AND user_id = $2 AND used_at IS NULL;
+        ",
+        hash_token(&normalize_recovery_code(code)),
+        user_id,
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
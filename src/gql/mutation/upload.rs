@@ -0,0 +1,148 @@
+use chrono::NaiveDateTime;
+use opendal::Operator;
+
+use crate::db::models::upload::{NewUploadSession, UploadSession};
+use crate::db::models::MaybeEmptyFile;
+use crate::gql::mutation::file::store_chunked_upload;
+use crate::media::{UploadConfig, UploadError};
+
+/// Opens a chunked upload. The uploads a user has going count towards their quota as if
+/// they were done, so starting many at once can't get around it. The quota is checked
+/// again when the upload completes
+pub async fn create_upload_session(
+    new_session: NewUploadSession,
+    config: &UploadConfig,
+    pool: &crate::Pool,
+) -> anyhow::Result<UploadSession> {
+    let mut tx = pool.begin().await?;
+
+    // Uploads of the same user start one after the other, so each sees the ones before
+    sqlx::query!(
+        "SELECT id FROM users WHERE id = $1 FOR UPDATE;",
+        new_session.owner_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let pending = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!", COALESCE(SUM(size), 0)::BIGINT AS "size!"
+        FROM upload_sessions WHERE owner_id = $1;
+        "#,
+        new_session.owner_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if pending.count >= config.max_upload_sessions {
+        return Err(UploadError::TooManyUploads(config.max_upload_sessions).into());
+    }
+
+    let usage = sqlx::query!(
+        "SELECT used, quota FROM storage_usage WHERE user_id = $1;",
+        new_session.owner_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let (used, quota) = usage.map_or((0, config.user_quota), |usage| {
+        (usage.used, usage.quota.unwrap_or(config.user_quota))
+    });
+    if used + pending.size + new_session.size > quota {
+        return Err(UploadError::QuotaExceeded {
+            needed: new_session.size,
+            quota,
+        }
+        .into());
+    }
+
+    let session = sqlx::query_as!(
+        UploadSession,
+        "
+        INSERT INTO upload_sessions (id, owner_id, filename, size, chunk_size, chunk_count)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *;
+        ",
+        uuid::Uuid::new_v4().to_string(),
+        new_session.owner_id,
+        new_session.filename,
+        new_session.size,
+        new_session.chunk_size,
+        new_session.chunk_count,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(session)
+}
+
+/// Stores a chunk, sending the same chunk again overwrites it
+pub async fn store_chunk(
+    session: &UploadSession,
+    index: i32,
+    bytes: Vec<u8>,
+    op: &Operator,
+    pool: &crate::Pool,
+) -> anyhow::Result<UploadSession> {
+    op.write(&session.chunk_path(index), bytes).await?;
+
+    let session = sqlx::query_as!(
+        UploadSession,
+        "
+        UPDATE upload_sessions SET
+            received = CASE WHEN $2 = ANY(received) THEN received
+                ELSE array_append(received, $2) END,
+            updated_at = (now() AT TIME ZONE 'UTC')
+        WHERE id = $1
+        RETURNING *;
+        ",
+        session.id,
+        index,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(session)
+}
+
+/// Turns the chunks into a file and forgets about the session
+pub async fn complete_upload(
+    session: &UploadSession,
+    username: &str,
+    config: &UploadConfig,
+    op: &Operator,
+    pool: &crate::Pool,
+) -> anyhow::Result<MaybeEmptyFile> {
+    let file = store_chunked_upload(session, username, config, op, pool).await?;
+    delete_upload_session(session, op, pool).await?;
+    Ok(file)
+}
+
+pub async fn delete_upload_session(
+    session: &UploadSession,
+    op: &Operator,
+    pool: &crate::Pool,
+) -> anyhow::Result<()> {
+    sqlx::query!("DELETE FROM upload_sessions WHERE id = $1;", session.id)
+        .execute(pool)
+        .await?;
+    op.remove_all(&session.chunk_dir()).await?;
+    Ok(())
+}
+
+/// Drops the sessions that haven't received a chunk since `before` along with their chunks
+pub async fn delete_stale_upload_sessions(
+    before: NaiveDateTime,
+    op: &Operator,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<UploadSession>> {
+    let sessions = sqlx::query_as!(
+        UploadSession,
+        "DELETE FROM upload_sessions WHERE updated_at < $1 RETURNING *;",
+        before,
+    )
+    .fetch_all(pool)
+    .await?;
+    for session in sessions.iter() {
+        op.remove_all(&session.chunk_dir()).await?;
+    }
+    Ok(sessions)
+}
@@ -1,10 +1,18 @@
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
 use async_graphql::InputObject;
+use once_cell::sync::Lazy;
 use sqlx::{QueryBuilder, Postgres};
 
+use crate::db::models::upload::UploadSession;
 use crate::db::models::user::User;
 use crate::db::models::user::{NewUser, UpdateUser};
 use crate::db::models::MaybeEmptyFile;
+use crate::error::UserCreationError;
+use crate::gql::query::oidc::get_user_by_verified_email;
+use crate::gql::query::user::username_held;
 
 #[derive(InputObject)]
 pub struct BasicUserUpdate {
@@ -24,39 +32,264 @@ impl Into<UpdateUser> for BasicUserUpdate {
             bio: self._bio.map(|x| if x.is_empty() { None } else { Some(x) }),
             pfp: self._pfp.map(MaybeEmptyFile::new),
             banner: self._banner.map(MaybeEmptyFile::new),
-            admin: None,
         }
     }
 }
 
+/// Turns unique violations on users into errors the client can show
+fn map_unique_violation(e: sqlx::Error) -> anyhow::Error {
+    if let sqlx::Error::Database(db) = &e {
+        match db.constraint() {
+            Some("users_username_key") => {
+                return UserCreationError::UsernameAlreadyExists("Username is already taken").into()
+            }
+            Some("user_email_index") => {
+                return UserCreationError::EmailAlreadyExists("Email is already in use").into()
+            }
+            _ => {}
+        }
+    }
+    e.into()
+}
+
+/// Only verified emails are unique, so the email can be taken as long as no other user
+/// verified it. Verifying is checked again by the unique index
+async fn ensure_email_free(
+    email: Option<&str>,
+    user_id: Option<i32>,
+    pool: &crate::Pool,
+) -> anyhow::Result<()> {
+    let holder = match email {
+        Some(email) => get_user_by_verified_email(email, pool).await?,
+        None => None,
+    };
+    match holder {
+        Some(holder) if Some(holder) != user_id => {
+            Err(UserCreationError::EmailAlreadyExists("Email is already in use").into())
+        }
+        _ => Ok(()),
+    }
+}
+
 pub async fn create_user(
     _username: String,
     _password: String,
+    _email: Option<String>,
     pool: &crate::Pool,
 ) -> anyhow::Result<User> {
+    if username_held(&_username, None, pool).await? {
+        return Err(UserCreationError::UsernameAlreadyExists("Username is already taken").into());
+    }
+    ensure_email_free(_email.as_deref(), None, pool).await?;
+
     let new_user = NewUser {
         username: &_username,
         password: &_password,
         display_name: &_username,
+        email: _email.as_deref(),
     };
 
     let user = sqlx::query_as!(
         User,
         "
-        INSERT INTO users (username, password, display_name)
-        VALUES ($1, $2, $3)
+        INSERT INTO users (username, password, display_name, email)
+        VALUES ($1, $2, $3, $4)
         RETURNING *;
         ",
         new_user.username,
         new_user.password,
         new_user.display_name,
+        new_user.email,
     )
     .fetch_one(pool)
+    .await
+    .map_err(map_unique_violation)?;
+
+    Ok(user)
+}
+
+/// Renames the user, keeping the old username in the history so it still resolves
+/// to them and is held for a while
+pub async fn change_username(
+    user_id: i32,
+    username: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<User> {
+    let mut tx = pool.begin().await?;
+    let old = sqlx::query_scalar!(
+        "SELECT username FROM users WHERE id = $1 FOR UPDATE;",
+        user_id
+    )
+    .fetch_one(&mut *tx)
     .await?;
 
+    let user = sqlx::query_as!(
+        User,
+        "UPDATE users SET username = $2 WHERE id = $1 RETURNING *;",
+        user_id,
+        username,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(map_unique_violation)?;
+
+    sqlx::query!(
+        "INSERT INTO username_history (user_id, username) VALUES ($1, $2);",
+        user_id,
+        old,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
     Ok(user)
 }
 
+/// What's left to remove from the storage after erasing a user
+pub struct ErasedUser {
+    pub user: User,
+    /// Ids of the uploads of the user
+    pub files: Vec<String>,
+    /// Paths of the data exports
+    pub exports: Vec<String>,
+    /// Chunked uploads that weren't finished
+    pub uploads: Vec<UploadSession>,
+}
+
+/// Anonymizes the user: the profile is cleared and renamed to `username`, the password
+/// replaced by `password` and everything used to log in is dropped. Posts, comments and
+/// messages stay, media of the user is taken out of them
+pub async fn erase_user(
+    user_id: i32,
+    username: &str,
+    password: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<ErasedUser> {
+    let mut tx = pool.begin().await?;
+
+    let files = sqlx::query_scalar!("SELECT id FROM files WHERE owner_id = $1;", user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+    for table in [
+        "posts",
+        "post_revisions",
+        "comments",
+        "comment_revisions",
+        "messages",
+    ] {
+        sqlx::query(&format!(
+            "UPDATE {table} SET media = ARRAY(SELECT m FROM unnest(media) m WHERE m <> ALL($1))
+            WHERE media && $1;"
+        ))
+        .bind(&files)
+        .execute(&mut *tx)
+        .await?;
+    }
+    sqlx::query!(
+        "
+        UPDATE forums SET
+            icon = CASE WHEN icon = ANY($1) THEN NULL ELSE icon END,
+            banner = CASE WHEN banner = ANY($1) THEN NULL ELSE banner END
+        WHERE icon = ANY($1) OR banner = ANY($1);
+        ",
+        &files,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let user = sqlx::query_as!(
+        User,
+        "
+        UPDATE users SET username = $2, password = $3, display_name = 'Deleted user',
+            bio = NULL, pfp = NULL, banner = NULL, email = NULL, email_verified_at = NULL
+        WHERE id = $1
+        RETURNING *;
+        ",
+        user_id,
+        username,
+        password,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    for table in [
+        "api_tokens",
+        "user_sessions",
+        "user_tokens",
+        "totp_secrets",
+        "recovery_codes",
+        "oidc_identities",
+        "username_history",
+        "notifications",
+        "conversation_members",
+        "user_roles",
+        "forum_roles",
+    ] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE user_id = $1;"))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let exports = sqlx::query_scalar!(
+        "DELETE FROM data_exports WHERE user_id = $1 RETURNING path;",
+        user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let uploads = sqlx::query_as!(
+        UploadSession,
+        "DELETE FROM upload_sessions WHERE owner_id = $1 RETURNING *;",
+        user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(ErasedUser {
+        user,
+        files,
+        exports,
+        uploads,
+    })
+}
+
+/// Replaces the email (or removes it with `None`), the new one isn't verified yet
+pub async fn set_email(
+    user_id: i32,
+    email: Option<&str>,
+    pool: &crate::Pool,
+) -> anyhow::Result<User> {
+    ensure_email_free(email, Some(user_id), pool).await?;
+    let user = sqlx::query_as!(
+        User,
+        "
+        UPDATE users SET email = $2, email_verified_at = NULL
+        WHERE id = $1
+        RETURNING *;
+        ",
+        user_id,
+        email,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(map_unique_violation)?;
+    Ok(user)
+}
+
+/// Fails with `EmailAlreadyExists` when another user verified the same email first
+pub async fn mark_email_verified(user_id: i32, pool: &crate::Pool) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+        UPDATE users SET email_verified_at = (now() AT TIME ZONE 'UTC')
+        WHERE id = $1 AND email IS NOT NULL;
+        ",
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .map_err(map_unique_violation)?;
+    Ok(())
+}
+
 pub async fn update_user(changes: &UpdateUser, pool: &crate::Pool) -> anyhow::Result<User> {
     let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("UPDATE users SET ");
     let mut prev = false;
@@ -90,7 +323,7 @@ pub async fn update_user(changes: &UpdateUser, pool: &crate::Pool) -> anyhow::Re
         builder.push_bind(v);
     }
     if let Some(v) = &changes.pfp {
-        if v.status().await?.updatable() {
+        if v.status(changes.id, pool).await?.updatable() {
             if prev {
                 builder.push(", ");
             }
@@ -105,11 +338,10 @@ pub async fn update_user(changes: &UpdateUser, pool: &crate::Pool) -> anyhow::Re
         }
     }
     if let Some(v) = &changes.banner {
-        if v.status().await?.updatable() {
+        if v.status(changes.id, pool).await?.updatable() {
             if prev {
                 builder.push(", ");
             }
-            prev = true;
             builder.push("banner = ");
             builder.push_bind(&v.id);
         } else {
@@ -119,13 +351,6 @@ pub async fn update_user(changes: &UpdateUser, pool: &crate::Pool) -> anyhow::Re
             )));
         }
     }
-    if let Some(v) = &changes.admin {
-        if prev {
-            builder.push(", ");
-        }
-        builder.push("admin = ");
-        builder.push_bind(v);
-    }
     builder.push(" WHERE id = ");
     builder.push_bind(changes.id);
     builder.push(" RETURNING *;");
@@ -135,23 +360,35 @@ pub async fn update_user(changes: &UpdateUser, pool: &crate::Pool) -> anyhow::Re
     Ok(user)
 }
 
+/// Verified in place of a password hash for unknown usernames, so they take as long as
+/// known ones
+static DUMMY_HASH: Lazy<String> = Lazy::new(|| {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(uuid::Uuid::new_v4().as_bytes(), &salt)
+        .expect("Could not hash the dummy password")
+        .to_string()
+});
+
+/// The user if the password is theirs, `None` for a wrong password or an unknown username
 pub async fn verify_user<'a>(
     _username: &str,
     _password: &str,
     pool: &crate::Pool,
     hasher: &Argon2<'_>,
-) -> anyhow::Result<(bool, User)> {
-    let _user: User = sqlx::query_as!(User, "SELECT * FROM users WHERE username = $1", _username)
-        .fetch_one(pool)
+) -> anyhow::Result<Option<User>> {
+    let _user = sqlx::query_as!(User, "SELECT * FROM users WHERE username = $1", _username)
+        .fetch_optional(pool)
         .await?;
 
-    let parsed_hash = PasswordHash::new(&_user.password)
-        .map_err(|_| anyhow::Error::msg("Some interbal error occured"))?;
+    let hash = _user
+        .as_ref()
+        .map_or(DUMMY_HASH.as_str(), |u| u.password.as_str());
+    let parsed_hash =
+        PasswordHash::new(hash).map_err(|_| anyhow::Error::msg("Some interbal error occured"))?;
+    let valid = hasher
+        .verify_password(_password.as_bytes(), &parsed_hash)
+        .is_ok();
 
-    Ok((
-        hasher
-            .verify_password(_password.as_bytes(), &parsed_hash)
-            .is_ok(),
-        _user,
-    ))
+    Ok(_user.filter(|_| valid))
 }
@@ -0,0 +1,27 @@
+use crate::db::models::api_token::{ApiScope, ApiToken};
+
+pub async fn get_api_tokens(user_id: i32, pool: &crate::Pool) -> anyhow::Result<Vec<ApiToken>> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT id, name, scopes AS "scopes: Vec<ApiScope>", expires_at,
+        last_used_at, created_at
+        FROM api_tokens WHERE user_id = $1
+        ORDER BY created_at DESC;
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(tokens)
+}
+
+pub async fn count_api_tokens(user_id: i32, pool: &crate::Pool) -> anyhow::Result<i64> {
+    let count = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM api_tokens WHERE user_id = $1;"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(count)
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use async_graphql::SimpleObject;
+use futures::StreamExt;
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, Row};
+
+use super::{Page, PageOrder, RawPage};
+use crate::db::models::conversation::{Conversation, Message};
+use crate::db::models::user::User;
+
+#[derive(SimpleObject)]
+pub struct ConversationResponse {
+    pub conversation: Conversation,
+    pub members: Vec<User>,
+    pub last_message: Option<Message>,
+    pub unread_count: i64,
+}
+
+pub async fn is_member(
+    conversation_id: i32,
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    let member = sqlx::query_scalar!(
+        "
+        SELECT EXISTS(
+            SELECT 1 FROM conversation_members
+            WHERE conversation_id = $1 AND user_id = $2
+        );
+        ",
+        conversation_id,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(member.unwrap_or(false))
+}
+
+pub async fn get_conversations(
+    user_id: i32,
+    page: Option<Page>,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<ConversationResponse>> {
+    let page: RawPage = page.into();
+
+    let conversations = sqlx::query(&format!(
+        "
+        SELECT c.*, (
+            SELECT COUNT(msg.id) FROM messages msg
+            WHERE msg.conversation_id = c.id AND msg.sender_id <> $1
+            AND msg.created_at > COALESCE(m.last_read_at, 'epoch'::TIMESTAMP)
+        ) AS unread_count
+        FROM conversations c
+        JOIN conversation_members m ON m.conversation_id = c.id
+        WHERE m.user_id = $1 AND c.id {}= $2
+        ORDER BY c.id {}
+        LIMIT $3;
+        ",
+        match page.order {
+            PageOrder::ASC => ">",
+            PageOrder::DESC => "<",
+        },
+        page.order.as_str()
+    ))
+    .bind(user_id)
+    .bind(page.next_from)
+    .bind(page.per)
+    .map(|row: PgRow| {
+        // cant fail because we know the fields
+        let unread_count: i64 = row.get("unread_count");
+        (Conversation::from_row(&row).unwrap(), unread_count)
+    })
+    .fetch(pool)
+    .filter_map(|x| async move { x.ok() })
+    .collect::<Vec<_>>()
+    .await;
+
+    let ids = conversations.iter().map(|(c, _)| c.id).collect::<Vec<_>>();
+
+    let mut members: HashMap<i32, Vec<User>> = HashMap::new();
+    sqlx::query(
+        "
+        SELECT m.conversation_id, u.*
+        FROM conversation_members m
+        JOIN users u ON u.id = m.user_id
+        WHERE m.conversation_id = ANY($1);
+        ",
+    )
+    .bind(&ids)
+    .map(|row: PgRow| {
+        let conversation_id: i32 = row.get("conversation_id");
+        (conversation_id, User::from_row(&row).unwrap())
+    })
+    .fetch(pool)
+    .filter_map(|x| async move { x.ok() })
+    .collect::<Vec<_>>()
+    .await
+    .into_iter()
+    .for_each(|(conversation_id, user)| {
+        members.entry(conversation_id).or_default().push(user);
+    });
+
+    let mut last_messages: HashMap<i32, Message> = sqlx::query_as::<_, Message>(
+        "
+        SELECT DISTINCT ON (conversation_id) *
+        FROM messages
+        WHERE conversation_id = ANY($1)
+        ORDER BY conversation_id, id DESC;
+        ",
+    )
+    .bind(&ids)
+    .fetch(pool)
+    .filter_map(|x| async move { x.ok() })
+    .map(|message| (message.conversation_id, message))
+    .collect()
+    .await;
+
+    Ok(conversations
+        .into_iter()
+        .map(|(conversation, unread_count)| ConversationResponse {
+            members: members.remove(&conversation.id).unwrap_or_default(),
+            last_message: last_messages.remove(&conversation.id),
+            conversation,
+            unread_count,
+        })
+        .collect())
+}
+
+pub async fn get_messages(
+    user_id: i32,
+    conversation_id: i32,
+    page: Option<Page>,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<Message>> {
+    if !is_member(conversation_id, user_id, pool).await? {
+        return Err(anyhow::Error::msg("Conversation not found"));
+    }
+
+    let page: RawPage = page.into();
+
+    let messages = sqlx::query_as::<_, Message>(&format!(
+        "
+        SELECT *
+        FROM messages
+        WHERE conversation_id = $3 AND id {}= $1
+        ORDER BY id {}
+        LIMIT $2;
+        ",
+        match page.order {
+            PageOrder::ASC => ">",
+            PageOrder::DESC => "<",
+        },
+        page.order.as_str()
+    ))
+    .bind(page.next_from)
+    .bind(page.per)
+    .bind(conversation_id)
+    .fetch(pool)
+    .filter_map(|x| async move { x.ok() })
+    .collect::<Vec<_>>()
+    .await;
+
+    Ok(messages)
+}
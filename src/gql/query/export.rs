@@ -0,0 +1,65 @@
+use chrono::NaiveDateTime;
+use serde_json::Value;
+
+fn parse_rows(rows: Vec<String>) -> anyhow::Result<Vec<Value>> {
+    Ok(rows
+        .iter()
+        .map(|row| serde_json::from_str(row))
+        .collect::<Result<_, _>>()?)
+}
+
+/// Everything stored about the user except the password hash
+pub async fn get_export_profile(user_id: i32, pool: &crate::Pool) -> anyhow::Result<Value> {
+    let profile = sqlx::query_scalar!(
+        r#"SELECT (to_jsonb(u) - 'password')::text AS "profile!" FROM users u WHERE id = $1;"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(serde_json::from_str(&profile)?)
+}
+
+pub async fn get_export_posts(user_id: i32, pool: &crate::Pool) -> anyhow::Result<Vec<Value>> {
+    let posts = sqlx::query_scalar!(
+        r#"SELECT to_jsonb(p)::text AS "post!" FROM posts p WHERE poster_id = $1 ORDER BY id;"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+    parse_rows(posts)
+}
+
+pub async fn get_export_comments(user_id: i32, pool: &crate::Pool) -> anyhow::Result<Vec<Value>> {
+    let comments = sqlx::query_scalar!(
+        r#"SELECT to_jsonb(c)::text AS "comment!" FROM comments c WHERE user_id = $1 ORDER BY id;"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+    parse_rows(comments)
+}
+
+/// Records of the uploads of the user, their `id` is the path in the storage
+pub async fn get_export_files(user_id: i32, pool: &crate::Pool) -> anyhow::Result<Vec<Value>> {
+    let files = sqlx::query_scalar!(
+        r#"SELECT to_jsonb(f)::text AS "file!" FROM files f WHERE owner_id = $1 ORDER BY created_at;"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+    parse_rows(files)
+}
+
+/// Archives created before `before`, to be removed from the storage
+pub async fn get_expired_exports(
+    before: NaiveDateTime,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<String>> {
+    let paths = sqlx::query_scalar!(
+        "SELECT path FROM data_exports WHERE created_at < $1;",
+        before
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(paths)
+}
@@ -0,0 +1,101 @@
+use async_graphql::SimpleObject;
+
+use crate::db::models::file::{FileRecord, MediaKind, Rendition};
+
+#[derive(SimpleObject)]
+pub struct StorageUsage {
+    /// Bytes stored, renditions included
+    pub used: i64,
+    pub quota: i64,
+}
+
+pub async fn get_file(id: &str, pool: &crate::Pool) -> anyhow::Result<Option<FileRecord>> {
+    let file = sqlx::query_as!(
+        FileRecord,
+        r#"
+        SELECT owner_id, size, mime, kind AS "kind: MediaKind", duration, codec, created_at
+        FROM files WHERE id = $1;
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(file)
+}
+
+pub async fn get_renditions(file_id: &str, pool: &crate::Pool) -> anyhow::Result<Vec<Rendition>> {
+    let renditions = sqlx::query_as::<_, Rendition>(
+        "
+        SELECT kind, path, width, height FROM renditions
+        WHERE file_id = $1
+        ORDER BY kind;
+        ",
+    )
+    .bind(file_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(renditions)
+}
+
+pub async fn get_storage_usage(
+    user_id: i32,
+    default_quota: i64,
+    pool: &crate::Pool,
+) -> anyhow::Result<StorageUsage> {
+    let usage = sqlx::query!(
+        "SELECT used, quota FROM storage_usage WHERE user_id = $1;",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(match usage {
+        Some(usage) => StorageUsage {
+            used: usage.used,
+            quota: usage.quota.unwrap_or(default_quota),
+        },
+        None => StorageUsage {
+            used: 0,
+            quota: default_quota,
+        },
+    })
+}
+
+/// Files only attached to direct messages are private to the members of those
+/// conversations (and the uploader). `path` can be a rendition of the file
+pub async fn can_access_file(
+    path: &str,
+    user_id: Option<i32>,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    // Data exports are only for the user they were made for
+    if let Some(rest) = path.strip_prefix("exports/") {
+        return Ok(user_id.is_some_and(|id| rest.starts_with(&format!("{id}/"))));
+    }
+
+    let allowed = sqlx::query_scalar!(
+        r#"
+        WITH f AS (
+            SELECT id, owner_id FROM files
+            WHERE id = COALESCE((SELECT file_id FROM renditions WHERE path = $1), $1)
+        )
+        SELECT (
+            NOT EXISTS (SELECT 1 FROM f, messages m WHERE f.id = ANY(m.media))
+            OR EXISTS (SELECT 1 FROM f WHERE f.owner_id = $2)
+            OR EXISTS (SELECT 1 FROM f, posts p WHERE f.id = ANY(p.media))
+            OR EXISTS (SELECT 1 FROM f, comments c WHERE f.id = ANY(c.media))
+            OR EXISTS (SELECT 1 FROM f, users u WHERE u.pfp = f.id OR u.banner = f.id)
+            OR EXISTS (SELECT 1 FROM f, forums fo WHERE fo.icon = f.id OR fo.banner = f.id)
+            OR EXISTS (
+                SELECT 1 FROM f, messages m
+                JOIN conversation_members cm ON cm.conversation_id = m.conversation_id
+                WHERE f.id = ANY(m.media) AND cm.user_id = $2
+            )
+        ) AS "allowed!";
+        "#,
+        path,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(allowed)
+}
@@ -0,0 +1,37 @@
+use crate::db::models::login_failure::{LockedAccount, LoginFailureKind};
+
+/// When the lock on `key` ends, if it's locked
+pub async fn get_locked_until(
+    kind: LoginFailureKind,
+    key: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<chrono::NaiveDateTime>> {
+    let locked_until = sqlx::query_scalar!(
+        r#"
+        SELECT locked_until AS "locked_until!" FROM login_failures
+        WHERE kind = $1 AND key = $2 AND locked_until > (now() AT TIME ZONE 'UTC');
+        "#,
+        kind as LoginFailureKind,
+        key,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(locked_until)
+}
+
+pub async fn get_locked_accounts(pool: &crate::Pool) -> anyhow::Result<Vec<LockedAccount>> {
+    let accounts = sqlx::query_as!(
+        LockedAccount,
+        r#"
+        SELECT u.id AS user_id, u.username, f.failures, f.last_failed_at,
+        f.locked_until AS "locked_until!"
+        FROM login_failures f
+        INNER JOIN users u ON u.username = f.key
+        WHERE f.kind = 'username' AND f.locked_until > (now() AT TIME ZONE 'UTC')
+        ORDER BY f.locked_until DESC;
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(accounts)
+}
@@ -0,0 +1,77 @@
+use crate::db::models::forum::Forum;
+use crate::db::models::user::User;
+use crate::gql::mutation::mention::MentionSource;
+
+pub async fn get_mentioned_users(
+    source: MentionSource,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<User>> {
+    let users = match source {
+        MentionSource::Post(id) => {
+            sqlx::query_as!(
+                User,
+                "
+                SELECT u.* FROM users u
+                JOIN mentions m ON m.user_id = u.id
+                WHERE m.post_id = $1
+                ORDER BY m.id;
+                ",
+                id
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        MentionSource::Comment(id) => {
+            sqlx::query_as!(
+                User,
+                "
+                SELECT u.* FROM users u
+                JOIN mentions m ON m.user_id = u.id
+                WHERE m.comment_id = $1
+                ORDER BY m.id;
+                ",
+                id
+            )
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(users)
+}
+
+pub async fn get_linked_forums(
+    source: MentionSource,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<Forum>> {
+    let forums = match source {
+        MentionSource::Post(id) => {
+            sqlx::query_as!(
+                Forum,
+                "
+                SELECT f.* FROM forums f
+                JOIN mentions m ON m.forum_id = f.id
+                WHERE m.post_id = $1
+                ORDER BY m.id;
+                ",
+                id
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        MentionSource::Comment(id) => {
+            sqlx::query_as!(
+                Forum,
+                "
+                SELECT f.* FROM forums f
+                JOIN mentions m ON m.forum_id = f.id
+                WHERE m.comment_id = $1
+                ORDER BY m.id;
+                ",
+                id
+            )
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(forums)
+}
@@ -1,25 +1,52 @@
+pub mod api_token;
 // mod comment;
+pub mod conversation;
+pub mod export;
+pub mod file;
 mod forum;
+pub mod login_failure;
+pub mod mention;
+pub mod notification;
+pub mod oidc;
 pub mod post;
+pub mod revision;
+pub mod role;
+pub mod session;
+pub mod settings;
+pub mod two_factor;
+pub mod upload;
 pub mod user;
 
 use forum::{ForumCriteria, ForumFilter};
 use user::{UserCriteria, UserFilter};
 
-use async_graphql::{Context, Enum, ErrorExtensions, InputObject, Object, Result};
+use async_graphql::{Context, Enum, InputObject, Object, Result};
 use futures::TryStreamExt;
 use sqlx::Row;
 
 use crate::{
     auth::SharedSession,
-    constants,
-    db::models::{comment::CommentHierarchy, user::User},
+    db::models::{
+        api_token::ApiToken,
+        conversation::Message,
+        login_failure::LockedAccount,
+        notification::Notification,
+        oidc::OidcIdentity,
+        role::{ForumMember, Permission},
+        session::UserSession,
+        settings::SiteSettings,
+        upload::UploadSession,
+    },
+    gql::guard::{session_user_id, LoginGuard, PermissionGuard},
     info::VersionInfo,
+    media::UploadConfig,
     search::SearchIndex,
 };
 
 use self::{
+    conversation::ConversationResponse,
     post::PostResponse,
+    revision::{RevisionDiff, RevisionSource},
     user::{UserOrder, UserResponse},
 };
 
@@ -79,19 +106,16 @@ impl Query {
     }
 
     // TODO: Error Handling (in responses)
+    #[graphql(guard = "LoginGuard")]
     async fn me<'c>(&self, ctx: &Context<'c>) -> Result<UserResponse> {
         let pool = ctx.data::<crate::Pool>()?;
-        let session = ctx.data::<SharedSession>()?;
-        let id = session.get::<i32>("id")?;
-
-        if let Some(id) = id {
-            let user = user::get_user_by_id(id, &pool).await?;
-            return Ok(user);
-        }
-        Err(
-            async_graphql::Error::new(constants::UNAUTHEMTICATED_MESSAGE)
-                .extend_with(|_, e| e.set("code", "401")),
-        )
+        let id = session_user_id(ctx)?;
+        let mut user = user::get_user_by_id(id, &pool).await?;
+        user.unread_notifications = Some(notification::unread_count(id, pool).await?);
+        user.email = user.user.email.clone();
+        user.email_verified = Some(user.user.email_verified_at.is_some());
+        user.two_factor_enabled = Some(two_factor::has_two_factor(id, pool).await?);
+        Ok(user)
     }
 
     async fn user<'c>(&self, ctx: &Context<'c>, id: i32) -> Result<UserResponse> {
@@ -172,6 +196,45 @@ impl Query {
         Ok(post)
     }
 
+    #[graphql(guard = "LoginGuard")]
+    async fn conversations<'c>(
+        &self,
+        ctx: &Context<'c>,
+        page: Option<Page>,
+    ) -> Result<Vec<ConversationResponse>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let id = session_user_id(ctx)?;
+        let conversations = conversation::get_conversations(id, page, pool).await?;
+        Ok(conversations)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn messages<'c>(
+        &self,
+        ctx: &Context<'c>,
+        conversation_id: i32,
+        page: Option<Page>,
+    ) -> Result<Vec<Message>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let id = session_user_id(ctx)?;
+        let messages = conversation::get_messages(id, conversation_id, page, pool).await?;
+        Ok(messages)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn notifications<'c>(
+        &self,
+        ctx: &Context<'c>,
+        page: Option<Page>,
+        #[graphql(default)] unread_only: bool,
+    ) -> Result<Vec<Notification>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let id = session_user_id(ctx)?;
+        let notifications =
+            notification::get_notifications(id, page, unread_only, pool).await?;
+        Ok(notifications)
+    }
+
     // async fn comments<'c>(
     //     &self,
     //     ctx: &Context<'c>,
@@ -186,4 +249,82 @@ impl Query {
 
     //     Ok(comments)
     // }
+
+    /// Line diff between two revisions, `to` defaults to the current version
+    async fn revision_diff<'c>(
+        &self,
+        ctx: &Context<'c>,
+        source: RevisionSource,
+        from: i32,
+        to: Option<i32>,
+    ) -> Result<RevisionDiff> {
+        let pool = ctx.data::<crate::Pool>()?;
+
+        let diff = revision::diff_revisions(source, from, to, pool).await?;
+
+        Ok(diff)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn storage_usage<'c>(&self, ctx: &Context<'c>) -> Result<file::StorageUsage> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let config = ctx.data::<UploadConfig>()?;
+        let id = session_user_id(ctx)?;
+        let usage = file::get_storage_usage(id, config.user_quota, pool).await?;
+        Ok(usage)
+    }
+
+    /// Chunks received so far, to resume an upload after a disconnect
+    #[graphql(guard = "LoginGuard")]
+    async fn upload_session<'c>(&self, ctx: &Context<'c>, id: String) -> Result<UploadSession> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let user_id = session_user_id(ctx)?;
+        let upload_session = upload::get_upload_session(&id, user_id, pool)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Upload not found"))?;
+        Ok(upload_session)
+    }
+
+    #[graphql(guard = "LoginGuard")]
+    async fn api_tokens<'c>(&self, ctx: &Context<'c>) -> Result<Vec<ApiToken>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let id = session_user_id(ctx)?;
+        Ok(api_token::get_api_tokens(id, pool).await?)
+    }
+
+    /// Browsers the user is logged in with
+    #[graphql(guard = "LoginGuard")]
+    async fn sessions<'c>(&self, ctx: &Context<'c>) -> Result<Vec<UserSession>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let session = ctx.data::<SharedSession>()?;
+        let id = session_user_id(ctx)?;
+        let current = session.session_id()?;
+        Ok(session::get_user_sessions(id, current.as_deref(), pool).await?)
+    }
+
+    /// Identity provider accounts the user can log in with
+    #[graphql(guard = "LoginGuard")]
+    async fn oidc_identities<'c>(&self, ctx: &Context<'c>) -> Result<Vec<OidcIdentity>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        let id = session_user_id(ctx)?;
+        Ok(oidc::get_oidc_identities(id, pool).await?)
+    }
+
+    /// Accounts locked because of failed logins, only for moderators and admins
+    #[graphql(guard = "PermissionGuard(Permission::ManageLockedAccounts)")]
+    async fn locked_accounts<'c>(&self, ctx: &Context<'c>) -> Result<Vec<LockedAccount>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(login_failure::get_locked_accounts(pool).await?)
+    }
+
+    /// Users with a role in the forum, besides its owner
+    async fn forum_members<'c>(&self, ctx: &Context<'c>, forum_id: i32) -> Result<Vec<ForumMember>> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(role::get_forum_members(forum_id, pool).await?)
+    }
+
+    async fn site_settings<'c>(&self, ctx: &Context<'c>) -> Result<SiteSettings> {
+        let pool = ctx.data::<crate::Pool>()?;
+        Ok(settings::get_site_settings(pool).await?)
+    }
 }
@@ -0,0 +1,49 @@
+use futures::StreamExt;
+
+use super::{Page, PageOrder, RawPage};
+use crate::db::models::notification::Notification;
+
+pub async fn get_notifications(
+    user_id: i32,
+    page: Option<Page>,
+    unread_only: bool,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<Notification>> {
+    let page: RawPage = page.into();
+
+    let notifications = sqlx::query_as::<_, Notification>(&format!(
+        "
+        SELECT *
+        FROM notifications
+        WHERE user_id = $3 AND id {}= $1 {}
+        ORDER BY id {}
+        LIMIT $2;
+        ",
+        match page.order {
+            PageOrder::ASC => ">",
+            PageOrder::DESC => "<",
+        },
+        if unread_only { "AND read = false" } else { "" },
+        page.order.as_str()
+    ))
+    .bind(page.next_from)
+    .bind(page.per)
+    .bind(user_id)
+    .fetch(pool)
+    .filter_map(|x| async move { x.ok() })
+    .collect::<Vec<_>>()
+    .await;
+
+    Ok(notifications)
+}
+
+pub async fn unread_count(user_id: i32, pool: &crate::Pool) -> anyhow::Result<i64> {
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read = false",
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count.unwrap_or(0))
+}
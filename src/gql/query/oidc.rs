@@ -0,0 +1,53 @@
+use crate::db::models::oidc::OidcIdentity;
+
+/// User the provider account `subject` is linked to
+pub async fn get_oidc_user(
+    issuer: &str,
+    subject: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<i32>> {
+    let user_id = sqlx::query_scalar!(
+        "SELECT user_id FROM oidc_identities WHERE issuer = $1 AND subject = $2;",
+        issuer,
+        subject,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(user_id)
+}
+
+pub async fn get_oidc_identities(
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<OidcIdentity>> {
+    let identities = sqlx::query_as!(
+        OidcIdentity,
+        "
+        SELECT issuer, subject, email, created_at FROM oidc_identities
+        WHERE user_id = $1
+        ORDER BY created_at;
+        ",
+        user_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(identities)
+}
+
+/// User with this verified email, provider accounts are only linked by email when both
+/// sides verified it
+pub async fn get_user_by_verified_email(
+    email: &str,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<i32>> {
+    let user_id = sqlx::query_scalar!(
+        "
+        SELECT id FROM users
+        WHERE lower(email) = lower($1) AND email_verified_at IS NOT NULL;
+        ",
+        email,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(user_id)
+}
@@ -0,0 +1,158 @@
+use async_graphql::{Enum, OneofObject, SimpleObject};
+use similar::{ChangeTag, TextDiff};
+
+use crate::db::models::revision::{CommentRevision, PostRevision};
+
+#[derive(OneofObject)]
+pub enum RevisionSource {
+    Post(i32),
+    Comment(i32),
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum DiffTag {
+    Equal,
+    Insert,
+    Delete,
+}
+
+impl From<ChangeTag> for DiffTag {
+    fn from(value: ChangeTag) -> Self {
+        match value {
+            ChangeTag::Equal => Self::Equal,
+            ChangeTag::Insert => Self::Insert,
+            ChangeTag::Delete => Self::Delete,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct DiffLine {
+    pub tag: DiffTag,
+    /// Line number in the older version, null for inserted lines
+    pub old_line: Option<i32>,
+    /// Line number in the newer version, null for deleted lines
+    pub new_line: Option<i32>,
+    pub content: String,
+}
+
+#[derive(SimpleObject)]
+pub struct RevisionDiff {
+    /// Only set for posts
+    pub title: Option<Vec<DiffLine>>,
+    pub content: Vec<DiffLine>,
+}
+
+/// Title and content of a revision, or of the live version
+struct Snapshot {
+    title: Option<String>,
+    content: String,
+}
+
+fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            tag: change.tag().into(),
+            old_line: change.old_index().map(|i| i as i32 + 1),
+            new_line: change.new_index().map(|i| i as i32 + 1),
+            content: change.value().trim_end_matches('\n').to_string(),
+        })
+        .collect()
+}
+
+pub async fn get_post_revisions(
+    post_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<PostRevision>> {
+    let revisions = sqlx::query_as::<_, PostRevision>(
+        "SELECT * FROM post_revisions WHERE post_id = $1 ORDER BY id DESC;",
+    )
+    .bind(post_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(revisions)
+}
+
+pub async fn get_comment_revisions(
+    comment_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<CommentRevision>> {
+    let revisions = sqlx::query_as::<_, CommentRevision>(
+        "SELECT * FROM comment_revisions WHERE comment_id = $1 ORDER BY id DESC;",
+    )
+    .bind(comment_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(revisions)
+}
+
+/// Loads revision `revision_id` of the source, or the current version when it's `None`
+async fn get_snapshot(
+    source: &RevisionSource,
+    revision_id: Option<i32>,
+    pool: &crate::Pool,
+) -> anyhow::Result<Snapshot> {
+    let snapshot = match (source, revision_id) {
+        (RevisionSource::Post(post_id), Some(id)) => sqlx::query!(
+            "SELECT title, content FROM post_revisions WHERE id = $1 AND post_id = $2;",
+            id,
+            post_id,
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|r| Snapshot {
+            title: Some(r.title),
+            content: r.content.unwrap_or_default(),
+        }),
+        (RevisionSource::Post(post_id), None) => {
+            sqlx::query!("SELECT title, content FROM posts WHERE id = $1;", post_id)
+                .fetch_optional(pool)
+                .await?
+                .map(|r| Snapshot {
+                    title: Some(r.title),
+                    content: r.content.unwrap_or_default(),
+                })
+        }
+        (RevisionSource::Comment(comment_id), Some(id)) => sqlx::query_scalar!(
+            "SELECT content FROM comment_revisions WHERE id = $1 AND comment_id = $2;",
+            id,
+            comment_id,
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|content| Snapshot {
+            title: None,
+            content,
+        }),
+        (RevisionSource::Comment(comment_id), None) => {
+            sqlx::query_scalar!("SELECT content FROM comments WHERE id = $1;", comment_id)
+                .fetch_optional(pool)
+                .await?
+                .map(|content| Snapshot {
+                    title: None,
+                    content,
+                })
+        }
+    };
+    snapshot.ok_or_else(|| anyhow::Error::msg("Revision not found"))
+}
+
+/// Line diff from revision `from` to revision `to` (the current version by default)
+pub async fn diff_revisions(
+    source: RevisionSource,
+    from: i32,
+    to: Option<i32>,
+    pool: &crate::Pool,
+) -> anyhow::Result<RevisionDiff> {
+    let old = get_snapshot(&source, Some(from), pool).await?;
+    let new = get_snapshot(&source, to, pool).await?;
+
+    Ok(RevisionDiff {
+        title: match (old.title, new.title) {
+            (Some(old), Some(new)) => Some(diff_lines(&old, &new)),
+            _ => None,
+        },
+        content: diff_lines(&old.content, &new.content),
+    })
+}
@@ -0,0 +1,96 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::dataloader::Loader;
+
+use crate::db::models::role::{ForumMember, ForumRole, SiteRole};
+
+/// Site roles of the users in a list, loaded with one query. Nothing is cached, role
+/// changes apply right away
+pub struct SiteRoleLoader(pub crate::Pool);
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<i32> for SiteRoleLoader {
+    type Value = SiteRole;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, user_ids: &[i32]) -> Result<HashMap<i32, SiteRole>, Self::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT user_id, role AS "role: SiteRole" FROM user_roles WHERE user_id = ANY($1);"#,
+            user_ids,
+        )
+        .fetch_all(&self.0)
+        .await
+        .map_err(|e| Arc::new(e.into()))?;
+        let mut roles = rows
+            .into_iter()
+            .map(|row| (row.user_id, row.role))
+            .collect::<HashMap<_, _>>();
+        for id in user_ids {
+            roles.entry(*id).or_insert(SiteRole::User);
+        }
+        Ok(roles)
+    }
+}
+
+/// Role of the user in the forum, `None` when they have none or the forum doesn't exist
+pub async fn get_forum_role(
+    forum_id: i32,
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<ForumRole>> {
+    let role = sqlx::query_scalar!(
+        r#"
+        SELECT CASE WHEN f.owner_id = $2 THEN 'admin' ELSE r.role END AS "role: ForumRole"
+        FROM forums f
+        LEFT JOIN forum_roles r ON r.forum_id = f.id AND r.user_id = $2
+        WHERE f.id = $1;
+        "#,
+        forum_id,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(role.flatten())
+}
+
+pub async fn get_forum_members(
+    forum_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<ForumMember>> {
+    let members = sqlx::query_as!(
+        ForumMember,
+        r#"
+        SELECT forum_id, user_id, role AS "role: ForumRole", created_at FROM forum_roles
+        WHERE forum_id = $1
+        ORDER BY role DESC, created_at;
+        "#,
+        forum_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(members)
+}
+
+pub async fn get_forum_owner(forum_id: i32, pool: &crate::Pool) -> anyhow::Result<Option<i32>> {
+    let owner_id = sqlx::query_scalar!("SELECT owner_id FROM forums WHERE id = $1;", forum_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(owner_id)
+}
+
+pub async fn get_post_forum_id(post_id: i32, pool: &crate::Pool) -> anyhow::Result<Option<i32>> {
+    let forum_id = sqlx::query_scalar!("SELECT forum_id FROM posts WHERE id = $1;", post_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(forum_id)
+}
+
+pub async fn get_comment_forum_id(
+    comment_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<i32>> {
+    let forum_id = sqlx::query_scalar!("SELECT forum_id FROM comments WHERE id = $1;", comment_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(forum_id)
+}
@@ -0,0 +1,27 @@
+use crate::constants::SESSION_TTL_HOURS;
+use crate::db::models::session::UserSession;
+
+/// Sessions of the user that haven't expired in the session store yet, `current` marks
+/// the one with id `current_id`
+pub async fn get_user_sessions(
+    user_id: i32,
+    current_id: Option<&str>,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<UserSession>> {
+    let sessions = sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT id, user_agent, ip, created_at, last_seen_at,
+        (id = $2) IS TRUE AS "current!"
+        FROM user_sessions
+        WHERE user_id = $1 AND created_at > (now() AT TIME ZONE 'UTC') - make_interval(hours => $3)
+        ORDER BY last_seen_at DESC;
+        "#,
+        user_id,
+        current_id,
+        SESSION_TTL_HOURS as i32,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(sessions)
+}
@@ -0,0 +1,29 @@
+use crate::db::models::settings::SiteSettings;
+use crate::error::PostCreationError;
+
+pub async fn get_site_settings(pool: &crate::Pool) -> anyhow::Result<SiteSettings> {
+    let settings = sqlx::query_as!(
+        SiteSettings,
+        "SELECT require_verified_email FROM site_settings;"
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(settings)
+}
+
+/// Fails when the site requires a verified email and the user doesn't have one
+pub async fn ensure_can_post(user_id: i32, pool: &crate::Pool) -> anyhow::Result<()> {
+    let allowed = sqlx::query_scalar!(
+        r#"
+        SELECT (NOT s.require_verified_email OR u.email_verified_at IS NOT NULL) AS "allowed!"
+        FROM site_settings s, users u WHERE u.id = $1;
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+    if !allowed {
+        return Err(PostCreationError::EmailNotVerified("Verify your email before posting").into());
+    }
+    Ok(())
+}
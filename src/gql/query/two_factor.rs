@@ -0,0 +1,23 @@
+use crate::db::models::two_factor::TotpSecret;
+
+pub async fn get_totp_secret(
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<TotpSecret>> {
+    let secret = sqlx::query_as!(
+        TotpSecret,
+        "SELECT secret, confirmed_at FROM totp_secrets WHERE user_id = $1;",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(secret)
+}
+
+/// Whether logging in needs a code besides the password
+pub async fn has_two_factor(user_id: i32, pool: &crate::Pool) -> anyhow::Result<bool> {
+    let enabled = get_totp_secret(user_id, pool)
+        .await?
+        .is_some_and(|secret| secret.confirmed_at.is_some());
+    Ok(enabled)
+}
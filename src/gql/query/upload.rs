@@ -0,0 +1,35 @@
+use chrono::NaiveDateTime;
+
+use crate::db::models::upload::UploadSession;
+
+/// Upload sessions are only visible to the user that started them
+pub async fn get_upload_session(
+    id: &str,
+    owner_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<UploadSession>> {
+    let session = sqlx::query_as!(
+        UploadSession,
+        "SELECT * FROM upload_sessions WHERE id = $1 AND owner_id = $2;",
+        id,
+        owner_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(session)
+}
+
+/// Sessions that haven't received a chunk since `before`
+pub async fn get_stale_upload_sessions(
+    before: NaiveDateTime,
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<UploadSession>> {
+    let sessions = sqlx::query_as!(
+        UploadSession,
+        "SELECT * FROM upload_sessions WHERE updated_at < $1;",
+        before,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(sessions)
+}
@@ -1,10 +1,12 @@
 use async_graphql::{Enum, InputObject, OneofObject, SimpleObject};
+use chrono::NaiveDateTime;
 use futures::StreamExt;
 use sqlx::postgres::PgRow;
 use sqlx::{FromRow, Row};
 
 use super::{Page, PageOrder, RawPage};
-use crate::db::models::user::User;
+use crate::constants;
+use crate::db::models::{role::SiteRole, user::User};
 use crate::search::SearchIndex;
 
 #[derive(InputObject, Default)]
@@ -14,7 +16,7 @@ pub struct UserFilter {
     post_count: Option<UserPostFilter>,
     comment_count: Option<UserCommentFilter>,
     stars: Option<UserStarFilter>,
-    admin: Option<bool>,
+    role: Option<SiteRole>,
 }
 
 #[derive(InputObject)]
@@ -127,7 +129,7 @@ struct RawUserFilter {
     post_coubt: RawUserPostFilter,
     comment_count: RawUserCommentFilter,
     stars: RawUserStarFilter,
-    admin_str: &'static str,
+    role_str: &'static str,
 }
 
 impl From<Option<UserFilter>> for RawUserFilter {
@@ -139,10 +141,11 @@ impl From<Option<UserFilter>> for RawUserFilter {
             post_coubt: value.post_count.into(),
             comment_count: value.comment_count.into(),
             stars: value.stars.into(),
-            admin_str: match value.admin {
+            role_str: match value.role {
                 None => "IS NOT NULL",
-                Some(true) => "= true",
-                Some(false) => "= false",
+                Some(SiteRole::User) => "= 'user'",
+                Some(SiteRole::Moderator) => "= 'moderator'",
+                Some(SiteRole::Admin) => "= 'admin'",
             },
         }
     }
@@ -151,6 +154,7 @@ impl From<Option<UserFilter>> for RawUserFilter {
 #[derive(OneofObject)]
 pub enum UserCriteria {
     Search(String),
+    /// Old usernames find the user who renamed from them
     ByUsernames(Vec<String>),
     ByIds(Vec<i32>),
 }
@@ -208,6 +212,14 @@ pub struct UserResponse {
     pub comment_count: i64,
     pub stars: Option<i64>,
     pub score: Option<f32>,
+    /// Only set for the logged in user (`me`)
+    pub unread_notifications: Option<i64>,
+    /// Only set for the logged in user (`me`)
+    pub email: Option<String>,
+    /// Only set for the logged in user (`me`)
+    pub email_verified: Option<bool>,
+    /// Only set for the logged in user (`me`)
+    pub two_factor_enabled: Option<bool>,
 }
 
 #[derive(SimpleObject, Debug)]
@@ -233,20 +245,18 @@ pub async fn get_users(
         LEFT JOIN forums f ON u.id = f.owner_id
         LEFT JOIN posts p ON u.id = p.poster_id
         LEFT JOIN comments c ON u.id = c.user_id
-        WHERE u.{} = ANY($11) AND u.id {}= $1 AND admin {}
+        WHERE u.id = ANY($11) AND u.id {}= $1
+        AND COALESCE((SELECT role FROM user_roles r WHERE r.user_id = u.id), 'user') {}
         GROUP BY u.id
         -- Filters
         HAVING COUNT(f.id) >= $2 AND COUNT(f.id) <= $3 AND COUNT(p.id) >= $4 AND COUNT(p.id) <= $5 AND COUNT(c.id) >= $6 AND COUNT(c.id) <= $7 AND SUM(p.stars) >= $8 AND SUM(p.stars) <= $9
         -- Filters end
         ORDER BY u.id {}
         LIMIT $10;
-    ", match &criteria {
-        UserCriteria::Search(_) | UserCriteria::ByIds(_) => {"id"},
-        UserCriteria::ByUsernames(_) => "username"
-    }, match filter.page.order {
+    ", match filter.page.order {
         PageOrder::ASC => ">",
         PageOrder::DESC => "<"
-    }, filter.admin_str, filter.page.order.as_str());
+    }, filter.role_str, filter.page.order.as_str());
 
     let sql_query = sqlx::query(query_str.as_str())
         .bind(filter.page.next_from)
@@ -274,6 +284,10 @@ pub async fn get_users(
                     comment_count: row.get("comment_count"),
                     stars: row.get("stars"),
                     score: results.map_id_score(row.get("id")),
+                    unread_notifications: None,
+                    email: None,
+                    email_verified: None,
+                    two_factor_enabled: None,
                 })
                 .fetch(pool)
                 .filter_map(|x| async move { x.ok() })
@@ -282,8 +296,9 @@ pub async fn get_users(
             users
         }
         UserCriteria::ByUsernames(usernames) => {
+            let ids = resolve_usernames(&usernames, pool).await?;
             let users = sql_query
-                .bind(usernames)
+                .bind(ids)
                 .map(|row: PgRow| UserResponse {
                     // cant fail because we know the fields
                     user: User::from_row(&row).unwrap(),
@@ -292,6 +307,10 @@ pub async fn get_users(
                     comment_count: row.get("comment_count"),
                     stars: row.get("stars"),
                     score: None,
+                    unread_notifications: None,
+                    email: None,
+                    email_verified: None,
+                    two_factor_enabled: None,
                 })
                 .fetch(pool)
                 .filter_map(|x| async move { x.ok() })
@@ -310,6 +329,10 @@ pub async fn get_users(
                     comment_count: row.get("comment_count"),
                     stars: row.get("stars"),
                     score: None,
+                    unread_notifications: None,
+                    email: None,
+                    email_verified: None,
+                    two_factor_enabled: None,
                 })
                 .fetch(pool)
                 .filter_map(|x| async move { x.ok() })
@@ -340,6 +363,10 @@ pub async fn top(pool: &crate::Pool) -> anyhow::Result<Vec<UserResponse>> {
         comment_count: row.get("comment_count"),
         stars: row.get("stars"),
         score: None,
+        unread_notifications: None,
+        email: None,
+        email_verified: None,
+        two_factor_enabled: None,
     })
     .fetch(pool)
     .filter_map(|x| async move { x.ok() })
@@ -366,6 +393,90 @@ pub async fn get_user_by_id(id: i32, pool: &crate::Pool) -> anyhow::Result<UserR
         comment_count: row.get("comment_count"),
         stars: row.get("stars"),
         score: None,
+        unread_notifications: None,
+        email: None,
+        email_verified: None,
+        two_factor_enabled: None,
     })?;
     Ok(user)
 }
+
+/// Matches the username, or a verified email case insensitively. Anyone can put an
+/// unverified email on their account, so those never identify a user
+pub async fn get_user_by_login(login: &str, pool: &crate::Pool) -> anyhow::Result<Option<User>> {
+    let user = sqlx::query_as!(
+        User,
+        "
+        SELECT * FROM users
+        WHERE username = $1 OR (lower(email) = lower($1) AND email_verified_at IS NOT NULL);
+        ",
+        login
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(user)
+}
+
+/// Whether another user had `username` within the hold period, so it can't be taken yet.
+/// Users can always go back to their own old names
+pub async fn username_held(
+    username: &str,
+    user_id: Option<i32>,
+    pool: &crate::Pool,
+) -> anyhow::Result<bool> {
+    let held = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM username_history
+            WHERE username = $1 AND user_id IS DISTINCT FROM $2
+            AND changed_at > (now() AT TIME ZONE 'UTC') - make_interval(days => $3)
+        ) AS "held!";
+        "#,
+        username,
+        user_id,
+        constants::USERNAME_HOLD_DAYS as i32,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(held)
+}
+
+/// When the user last changed their username
+pub async fn last_username_change(
+    user_id: i32,
+    pool: &crate::Pool,
+) -> anyhow::Result<Option<NaiveDateTime>> {
+    let changed_at = sqlx::query_scalar!(
+        "SELECT max(changed_at) FROM username_history WHERE user_id = $1;",
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(changed_at)
+}
+
+/// Ids of the users with these usernames. Names nobody has now resolve to the user who
+/// had them last, so old links keep working after a rename
+pub async fn resolve_usernames(
+    usernames: &[String],
+    pool: &crate::Pool,
+) -> anyhow::Result<Vec<i32>> {
+    let ids = sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(u.id, h.user_id) AS "id!"
+        FROM unnest($1::varchar[]) AS n(username)
+        LEFT JOIN users u ON u.username = n.username
+        LEFT JOIN LATERAL (
+            SELECT user_id FROM username_history
+            WHERE username = n.username
+            ORDER BY changed_at DESC
+            LIMIT 1
+        ) h ON true
+        WHERE u.id IS NOT NULL OR h.user_id IS NOT NULL;
+        "#,
+        usernames
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(ids)
+}
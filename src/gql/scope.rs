@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use async_graphql::extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery};
+use async_graphql::parser::types::{ExecutableDocument, OperationType};
+use async_graphql::{ErrorExtensions, Pos, ServerResult, Variables};
+
+use crate::auth::SharedSession;
+use crate::db::models::api_token::ApiScope;
+
+/// Rejects operations the api token of the request isn't scoped for, before anything
+/// runs. Cookie sessions pass through
+pub struct ApiScopes;
+
+impl ExtensionFactory for ApiScopes {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ApiScopesExtension)
+    }
+}
+
+struct ApiScopesExtension;
+
+#[async_graphql::async_trait::async_trait]
+impl Extension for ApiScopesExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+
+        if let Some(session) = ctx.data_opt::<SharedSession>() {
+            for (_, operation) in document.operations.iter() {
+                let scope = match operation.node.ty {
+                    OperationType::Mutation => ApiScope::Post,
+                    OperationType::Query | OperationType::Subscription => ApiScope::Read,
+                };
+                if !session.has_scope(scope) {
+                    return Err(async_graphql::Error::new(format!(
+                        "Api token is missing the {:?} scope",
+                        scope
+                    ))
+                    .extend_with(|_, e| e.set("code", "403"))
+                    .into_server_error(Pos::default()));
+                }
+            }
+        }
+        Ok(document)
+    }
+}
@@ -2,9 +2,15 @@ use actix::{Actor, Addr};
 use async_graphql::*;
 use futures::Stream;
 
-use crate::core::{
-    event::{CommentEvent, EventManager, ForumEvent, PostEvent, UserEvent},
-    event_session::{CommentEventSession, ForumEventSession, PostEventSession, UserEventSession},
+use crate::{
+    core::{
+        event::{CommentEvent, EventManager, ForumEvent, NotificationEvent, PostEvent, UserEvent},
+        event_session::{
+            CommentEventSession, ForumEventSession, NotificationEventSession, PostEventSession,
+            UserEventSession,
+        },
+    },
+    gql::guard::{session_user_id, LoginGuard},
 };
 
 pub struct Subscription;
@@ -71,4 +77,25 @@ impl Subscription {
 
         Ok(rx)
     }
+
+    /// Notifications for the logged in user
+    #[graphql(guard = "LoginGuard")]
+    async fn notifications<'c>(
+        &self,
+        ctx: &Context<'c>,
+    ) -> Result<impl Stream<Item = NotificationEvent>> {
+        let user_id = session_user_id(ctx)?;
+        let event_manager = ctx.data::<Addr<EventManager>>()?;
+
+        let (tx, rx) = futures::channel::mpsc::channel::<NotificationEvent>(100);
+
+        NotificationEventSession {
+            sender: tx,
+            user_id,
+            manager: event_manager.clone(),
+        }
+        .start();
+
+        Ok(rx)
+    }
 }
@@ -0,0 +1,139 @@
+use actix_session::Session;
+use actix_web::{
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorNotFound, ErrorUnauthorized},
+    get,
+    http::{
+        header::{self, EntityTag, HttpDate, Range},
+        StatusCode,
+    },
+    web, Error, HttpRequest, HttpResponse,
+};
+use opendal::{ErrorKind, Operator};
+
+use crate::{
+    auth::request_auth,
+    constants::{FORBIDDEN_MESSAGE, UNAUTHEMTICATED_MESSAGE},
+    db::models::api_token::ApiScope,
+    gql::query::file::can_access_file,
+    media::signing::{Signature, UrlSigner},
+};
+
+/// Streams a stored file from whatever backend the operator points to.
+/// Only urls signed by [`UrlSigner`] are served, files private to a conversation
+/// also need a session (or api token) of one of its members.
+/// Directories aren't listed, single byte ranges are served for seeking in media
+#[get("/{path:.*}")]
+pub async fn serve(
+    req: HttpRequest,
+    session: Session,
+    path: web::Path<(String,)>,
+    signature: Option<web::Query<Signature>>,
+    op: web::Data<Operator>,
+    signer: web::Data<UrlSigner>,
+    pool: web::Data<crate::Pool>,
+) -> Result<HttpResponse, Error> {
+    let (path,) = path.into_inner();
+    if path.is_empty() || path.ends_with('/') || path.split('/').any(|s| s == "..") {
+        return Err(ErrorNotFound("Not found"));
+    }
+
+    match signature {
+        Some(signature) if signer.verify(&path, &signature) => {}
+        _ => return Err(ErrorForbidden("Invalid or expired signature")),
+    }
+
+    // Public files don't need the session, which saves touching it for every image
+    let public = can_access_file(&path, None, &pool)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    if !public {
+        // Revoked sessions and tokens without the read scope count as logged out
+        let user_id = match request_auth(&req, session, &pool)
+            .await
+            .map_err(ErrorInternalServerError)?
+        {
+            Some(auth) if auth.has_scope(ApiScope::Read) => {
+                auth.get::<i32>("id").map_err(ErrorInternalServerError)?
+            }
+            _ => None,
+        };
+        let user_id = user_id.ok_or_else(|| ErrorUnauthorized(UNAUTHEMTICATED_MESSAGE))?;
+        let allowed = can_access_file(&path, Some(user_id), &pool)
+            .await
+            .map_err(ErrorInternalServerError)?;
+        if !allowed {
+            return Err(ErrorForbidden(FORBIDDEN_MESSAGE));
+        }
+    }
+
+    let meta = match op.stat(&path).await {
+        Ok(meta) if meta.is_file() => meta,
+        Ok(_) => return Err(ErrorNotFound("Not found")),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Err(ErrorNotFound("Not found")),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let length = meta.content_length();
+    let content_type = match meta.content_type() {
+        Some(content_type) => content_type.to_string(),
+        None => path
+            .rsplit_once('.')
+            .map(|(_, ext)| actix_files::file_extension_to_mime(ext).to_string())
+            .unwrap_or_else(|| "application/octet-stream".into()),
+    };
+
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .insert_header((header::ACCEPT_RANGES, "bytes"));
+
+    if let Some(etag) = meta.etag() {
+        let etag = EntityTag::new_strong(etag.trim_matches('"').to_string());
+        let matches = req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.split(',').any(|t| t.trim() == etag.to_string()));
+        if matches {
+            return Ok(HttpResponse::NotModified().finish());
+        }
+        response.insert_header((header::ETAG, etag));
+    }
+    if let Some(modified) = meta.last_modified() {
+        let modified: std::time::SystemTime = modified.into();
+        response.insert_header((header::LAST_MODIFIED, HttpDate::from(modified)));
+    }
+
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<Range>().ok());
+    if let Some(Range::Bytes(ranges)) = range {
+        // Multiple ranges would need a multipart body, the whole file is sent instead
+        if let [spec] = ranges.as_slice() {
+            return match spec.to_satisfiable_range(length) {
+                Some((start, end)) => {
+                    let reader = op
+                        .range_reader(&path, start..=end)
+                        .await
+                        .map_err(ErrorInternalServerError)?;
+                    Ok(response
+                        .status(StatusCode::PARTIAL_CONTENT)
+                        .insert_header((
+                            header::CONTENT_RANGE,
+                            format!("bytes {}-{}/{}", start, end, length),
+                        ))
+                        .no_chunking(end - start + 1)
+                        .streaming(reader))
+                }
+                None => Ok(HttpResponse::RangeNotSatisfiable()
+                    .insert_header((header::CONTENT_RANGE, format!("bytes */{}", length)))
+                    .finish()),
+            };
+        }
+    }
+
+    let reader = op.reader(&path).await.map_err(ErrorInternalServerError)?;
+    Ok(response.no_chunking(length).streaming(reader))
+}
@@ -1,19 +1,48 @@
 use actix_session::Session;
 use actix_web::{get, route, web, HttpRequest, HttpResponse, Responder};
 use actix_web_lab::respond::Html;
-use async_graphql::http::{Credentials, GraphiQLSource};
+use async_graphql::{
+    http::{Credentials, GraphiQLSource},
+    Data, ErrorExtensions, Pos,
+};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
 
-use crate::{auth::SharedSession, gql::root::Schema};
+use crate::{
+    auth::{request_auth, ClientInfo, SharedSession, TrustedProxies},
+    constants::UNAUTHEMTICATED_MESSAGE,
+    gql::root::Schema,
+};
 
 #[route("/gql", method = "GET", method = "POST")]
 pub async fn gql_handler(
     schema: web::Data<Schema>,
     req: GraphQLRequest,
+    http_req: HttpRequest,
     session: Session,
+    pool: web::Data<crate::Pool>,
+    proxies: web::Data<TrustedProxies>,
 ) -> GraphQLResponse {
-    let shared_sesiion = SharedSession::new(session);
-    let req = req.into_inner().data(shared_sesiion);
+    let auth = match request_auth(&http_req, session, &pool).await {
+        Ok(Some(auth)) => auth,
+        Ok(None) => {
+            let error = async_graphql::Error::new(UNAUTHEMTICATED_MESSAGE)
+                .extend_with(|_, e| e.set("code", "401"))
+                .into_server_error(Pos::default());
+            return async_graphql::Response::from_errors(vec![error]).into();
+        }
+        Err(e) => {
+            log::error!("{:?}", e);
+            let error = async_graphql::Error::new(e.to_string())
+                .extend_with(|_, e| e.set("code", "500"))
+                .into_server_error(Pos::default());
+            return async_graphql::Response::from_errors(vec![error]).into();
+        }
+    };
+    let shared_sesiion = SharedSession::new(auth);
+    let req = req
+        .into_inner()
+        .data(shared_sesiion)
+        .data(ClientInfo::from_request(&http_req, &proxies));
 
     schema.execute(req).await.into()
 }
@@ -22,8 +51,19 @@ pub async fn gql_ws_handler(
     schema: web::Data<Schema>,
     req: HttpRequest,
     payload: web::Payload,
+    session: Session,
+    pool: web::Data<crate::Pool>,
 ) -> actix_web::Result<HttpResponse> {
-    GraphQLSubscription::new(Schema::clone(&*schema)).start(&req, payload)
+    let auth = request_auth(&req, session, &pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized(UNAUTHEMTICATED_MESSAGE))?;
+    let mut data = Data::default();
+    data.insert(SharedSession::new(auth));
+
+    GraphQLSubscription::new(Schema::clone(&*schema))
+        .with_data(data)
+        .start(&req, payload)
 }
 
 #[get("/graphiql")]
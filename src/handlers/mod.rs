@@ -1,2 +1,3 @@
+pub mod cdn;
 pub mod gql;
 pub mod ws;
@@ -1,13 +1,14 @@
-use actix::Addr;
 use actix_session::Session;
 use actix_web::{get, web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
 use std::time::Instant;
 
 use crate::{
-    constants::UNAUTHEMTICATED_MESSAGE,
-    core::{packet::ActiveUser, session::RtSession, RtServer},
-    gql::query::{post::get_post_by_slug, user::get_user_by_id},
+    auth::request_auth,
+    constants::{FORBIDDEN_MESSAGE, UNAUTHEMTICATED_MESSAGE},
+    core::{dm_session::DmSession, packet::ActiveUser, session::RtSession, RtState},
+    db::models::api_token::ApiScope,
+    gql::query::{conversation::is_member, post::get_post_by_slug, user::get_user_by_id},
 };
 
 #[get("/connect/{post_slug}")]
@@ -17,13 +18,24 @@ pub async fn connect(
     stream: web::Payload,
     path: web::Path<(String,)>,
     pool: web::Data<crate::PgPool>,
-    rt_server: web::Data<Addr<RtServer>>,
+    state: web::Data<RtState>,
 ) -> Result<HttpResponse, Error> {
     let (post_slug,) = path.into_inner();
     log::info!("Connected to WS: {}", &post_slug);
     let id = uuid::Uuid::new_v4().to_string();
 
-    if let Some(user_id) = session.get::<i32>("id")? {
+    let auth = request_auth(&req, session, &pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized(UNAUTHEMTICATED_MESSAGE))?;
+    if !auth.has_scope(ApiScope::Read) {
+        return Err(actix_web::error::ErrorForbidden(FORBIDDEN_MESSAGE));
+    }
+
+    if let Some(user_id) = auth
+        .get::<i32>("id")
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
         let user = get_user_by_id(user_id, &pool).await
             .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
 
@@ -34,6 +46,8 @@ pub async fn connect(
             RtSession {
                 id,
                 hb: Instant::now(),
+                heartbeat_interval: state.config.heartbeat_interval,
+                session_timeout: state.config.session_timeout,
                 post_id: post.post.id,
                 forum_id: post.post.forum_id,
                 user: ActiveUser {
@@ -44,7 +58,67 @@ pub async fn connect(
                     pfp: user.user.pfp,
                     banner: user.user.banner,
                 },
-                addr: rt_server.get_ref().clone(),
+                role: auth.role(),
+                can_post: auth.has_scope(ApiScope::Post),
+                login_session: auth
+                    .session_id()
+                    .map_err(actix_web::error::ErrorInternalServerError)?,
+                addr: state.rt_server.clone(),
+                limiter: state.limiter.clone(),
+            },
+            &req,
+            stream,
+        )
+    } else {
+        Err(actix_web::error::ErrorUnauthorized(UNAUTHEMTICATED_MESSAGE))
+    }
+}
+
+#[get("/dm/{conversation_id}")]
+pub async fn connect_dm(
+    req: HttpRequest,
+    session: Session,
+    stream: web::Payload,
+    path: web::Path<(i32,)>,
+    pool: web::Data<crate::Pool>,
+    state: web::Data<RtState>,
+) -> Result<HttpResponse, Error> {
+    let (conversation_id,) = path.into_inner();
+    let id = uuid::Uuid::new_v4().to_string();
+
+    let auth = request_auth(&req, session, &pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized(UNAUTHEMTICATED_MESSAGE))?;
+    if !auth.has_scope(ApiScope::Read) {
+        return Err(actix_web::error::ErrorForbidden(FORBIDDEN_MESSAGE));
+    }
+
+    if let Some(user_id) = auth
+        .get::<i32>("id")
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        let member = is_member(conversation_id, user_id, &pool)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if !member {
+            return Err(actix_web::error::ErrorNotFound("Conversation not found"));
+        }
+        ws::start(
+            DmSession {
+                id,
+                hb: Instant::now(),
+                heartbeat_interval: state.config.heartbeat_interval,
+                session_timeout: state.config.session_timeout,
+                conversation_id,
+                user_id,
+                role: auth.role(),
+                can_post: auth.has_scope(ApiScope::Post),
+                login_session: auth
+                    .session_id()
+                    .map_err(actix_web::error::ErrorInternalServerError)?,
+                addr: state.dm_server.clone(),
+                limiter: state.limiter.clone(),
             },
             &req,
             stream,
@@ -1,6 +1,6 @@
 use std::collections::HashSet;
 
-use crate::constants::{ALLOWED_USERNAME_CHARS, RESERVED_USERNAMES};
+use crate::constants::{ALLOWED_USERNAME_CHARS, DELETED_USERNAME_PREFIX, RESERVED_USERNAMES};
 
 #[macro_export]
 macro_rules! spawn_blocking {
@@ -15,7 +15,7 @@ pub fn calculate_password_strength(password: &str, username: &str) -> anyhow::Re
 }
 
 pub fn check_reserved_username(username: &str) -> bool {
-    RESERVED_USERNAMES.contains(&username)
+    RESERVED_USERNAMES.contains(&username) || username.starts_with(DELETED_USERNAME_PREFIX)
 }
 
 pub fn check_valid_uservane(username: &str) -> bool {
@@ -31,3 +31,86 @@ pub fn check_valid_uservane(username: &str) -> bool {
     }
     true
 }
+
+/// Only catches obvious typos, the verification mail is the real check
+pub fn check_valid_email(email: &str) -> bool {
+    let (local, domain) = match email.rsplit_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    email.len() <= 254
+        && !local.is_empty()
+        && !email.chars().any(char::is_whitespace)
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ParsedMentions {
+    /// `@username` references
+    pub usernames: Vec<String>,
+    /// `f/forumname` references
+    pub forums: Vec<String>,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_forum_name_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'
+}
+
+/// Extracts `@username` and `f/forumname` references from `content`,
+/// without duplicates and in order of appearance
+pub fn parse_mentions(content: &str) -> ParsedMentions {
+    let mut parsed = ParsedMentions::default();
+    let mut prev: Option<char> = None;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let starts_word = !matches!(prev, Some(p) if is_word_char(p) || p == '/');
+        prev = Some(c);
+        if !starts_word {
+            continue;
+        }
+
+        let (start, is_user) = match c {
+            '@' => (i + 1, true),
+            'f' if matches!(chars.peek(), Some((_, '/'))) => {
+                chars.next();
+                prev = Some('/');
+                (i + 2, false)
+            }
+            _ => continue,
+        };
+
+        let mut end = start;
+        while let Some((j, n)) = chars.peek().copied() {
+            let allowed = if is_user {
+                ALLOWED_USERNAME_CHARS.contains(&n)
+            } else {
+                is_forum_name_char(n)
+            };
+            if !allowed {
+                break;
+            }
+            end = j + n.len_utf8();
+            prev = Some(n);
+            chars.next();
+        }
+
+        let name = &content[start..end];
+        let found = if is_user {
+            &mut parsed.usernames
+        } else {
+            &mut parsed.forums
+        };
+        if !name.is_empty() && name.len() <= 50 && !found.iter().any(|m| m == name) {
+            found.push(name.to_string());
+        }
+    }
+    parsed
+}
@@ -0,0 +1,111 @@
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+
+#[derive(Clone, Debug)]
+pub struct Mail {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers mails, implement it for whatever provider is used in production
+pub trait MailSender: Send + Sync {
+    fn send(&self, mail: Mail) -> BoxFuture<'_, anyhow::Result<()>>;
+}
+
+/// Only logs mails, for local development
+pub struct LogSender;
+
+impl MailSender for LogSender {
+    fn send(&self, mail: Mail) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            log::info!(
+                "Mail from {} to {}: {}\n{}",
+                mail.from,
+                mail.to,
+                mail.subject,
+                mail.body
+            );
+            Ok(())
+        })
+    }
+}
+
+/// Writes every mail to its own file in `dir`, for local development
+pub struct FileSender {
+    pub dir: PathBuf,
+}
+
+impl MailSender for FileSender {
+    fn send(&self, mail: Mail) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let name = format!(
+                "{}-{}.eml",
+                chrono::Utc::now().format("%Y%m%d%H%M%S"),
+                uuid::Uuid::new_v4()
+            );
+            let content = format!(
+                "From: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+                mail.from, mail.to, mail.subject, mail.body
+            );
+            tokio::fs::write(self.dir.join(name), content).await?;
+            Ok(())
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Mailer {
+    sender: Arc<dyn MailSender>,
+    pub from: String,
+    /// Where the frontend is served, links in mails point there
+    pub public_url: String,
+}
+
+impl Mailer {
+    pub fn new(sender: Arc<dyn MailSender>, from: String, public_url: String) -> Self {
+        Self {
+            sender,
+            from,
+            public_url,
+        }
+    }
+
+    /// Reads `MAIL_SENDER` (`log` or `file`, `log` by default), `MAIL_DIR` for the file
+    /// sender, `MAIL_FROM` and `PUBLIC_URL`
+    pub fn from_env() -> anyhow::Result<Self> {
+        let sender: Arc<dyn MailSender> = match env::var("MAIL_SENDER").as_deref() {
+            Ok("log") | Err(_) => Arc::new(LogSender),
+            Ok("file") => Arc::new(FileSender {
+                dir: env::var("MAIL_DIR").unwrap_or("mail/".into()).into(),
+            }),
+            Ok(other) => {
+                return Err(anyhow::Error::msg(format!(
+                    "Unknown mail sender: {}",
+                    other
+                )))
+            }
+        };
+        Ok(Self::new(
+            sender,
+            env::var("MAIL_FROM").unwrap_or("rtwalk <noreply@localhost>".into()),
+            env::var("PUBLIC_URL").unwrap_or("http://127.0.0.1:8000".into()),
+        ))
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> anyhow::Result<()> {
+        self.sender
+            .send(Mail {
+                from: self.from.clone(),
+                to: to.to_string(),
+                subject: subject.to_string(),
+                body,
+            })
+            .await
+    }
+}
@@ -3,34 +3,54 @@ pub mod constants;
 pub mod core;
 mod db;
 pub mod error;
+pub mod export;
 mod gql;
 mod handlers;
 pub mod helpers;
 mod info;
+pub mod mail;
+pub mod markdown;
+pub mod media;
+pub mod oidc;
+pub mod ratelimit;
 pub mod search;
+pub mod storage;
+pub mod totp;
 
 use actix::*;
 use actix_cors::Cors;
-use actix_session::{storage::RedisActorSessionStore, SessionMiddleware};
-use actix_web::{cookie::Key, guard, middleware, web, App, HttpServer};
+use actix_session::{config::BrowserSession, storage::RedisActorSessionStore, SessionMiddleware};
+use actix_web::{
+    cookie::{time::Duration, Key},
+    guard, middleware, web, App, HttpServer,
+};
 use argon2::Argon2;
+use async_graphql::{dataloader::DataLoader, http::MultipartOptions};
 use dotenvy::dotenv;
-use opendal::{
-    layers::{LoggingLayer, RetryLayer},
-    services::Fs,
-    Operator,
-};
 use sqlx::PgPool;
 
 use std::env;
 
 use crate::{
-    constants::CDN_PATH,
-    core::{event::EventManager, RtServer},
-    search::SearchIndex, handlers::ws::connect,
+    auth::TrustedProxies,
+    constants::{CDN_PATH, SESSION_TTL_HOURS},
+    core::{dm::DmServer, event::EventManager, RtConfig, RtServer, RtState},
+    mail::Mailer,
+    media::{
+        signing::UrlSigner,
+        sweeper::{MediaSweeper, SweeperConfig},
+        UploadConfig,
+    },
+    oidc::{OidcClient, OidcConfig},
+    ratelimit::{login::LoginThrottleConfig, RateLimitConfig, RateLimiter},
+    search::SearchIndex, handlers::ws::{connect, connect_dm},
+    storage::StorageBackend,
 };
 
+use self::gql::query::role::SiteRoleLoader;
 use self::gql::root::{Mutation, Query, Schema, Subscription};
+use self::gql::scope::ApiScopes;
+use self::handlers::cdn;
 use self::handlers::gql::{gql_handler, gql_playground_handler, gql_ws_handler};
 
 type Pool = sqlx::Pool<sqlx::Postgres>;
@@ -52,34 +72,69 @@ async fn main() -> std::io::Result<()> {
         version_string: "0.1.0 alpha",
     };
 
-    let mut fs_builder = Fs::default();
-    fs_builder.root("data/").enable_path_check();
-    let data = Operator::new(fs_builder)
-        .expect("Could not create fs data store")
-        .layer(LoggingLayer::default())
-        .layer(RetryLayer::new())
-        .finish();
+    let data = StorageBackend::from_env()
+        .and_then(|backend| backend.operator())
+        .expect("Could not create data store");
+
+    let signer = UrlSigner::from_env().expect("Could not create media url signer");
+    let mailer = Mailer::from_env().expect("Could not create mailer");
+    let upload_config = UploadConfig::from_env().expect("Invalid upload config");
+    let multipart_options = MultipartOptions::default()
+        .max_file_size(upload_config.max_file_size.max(upload_config.chunk_size) as usize);
 
     let index = SearchIndex::default();
+    let limiter = RateLimiter::new(RateLimitConfig::from_env());
 
-    let rt_server = RtServer::new(pool.clone()).start();
+    let rt_config = RtConfig::from_env();
     let event_manager = EventManager::default().start();
+    let rt_server = RtServer::new(pool.clone(), rt_config, event_manager.clone()).start();
+    let dm_server = DmServer::new(pool.clone(), rt_config).start();
+    let media_sweeper =
+        MediaSweeper::new(pool.clone(), data.clone(), SweeperConfig::from_env()).start();
+
+    let oidc = OidcConfig::from_env().expect("Could not read OIDC config");
+    let proxies = TrustedProxies::from_env().expect("Invalid TRUSTED_PROXIES");
 
-    let schema = Schema::build(Query, Mutation, Subscription)
+    let rt_state = RtState {
+        rt_server: rt_server.clone(),
+        dm_server: dm_server.clone(),
+        limiter: limiter.clone(),
+        config: rt_config,
+    };
+
+    let mut schema = Schema::build(Query, Mutation, Subscription)
         .data(pool.clone())
+        .data(DataLoader::new(SiteRoleLoader(pool.clone()), tokio::spawn))
         .data(hasher.clone())
         .data(data.clone())
         .data(event_manager.clone())
+        .data(dm_server.clone())
+        .data(rt_server.clone())
+        .data(media_sweeper)
         .data(index)
+        .data(limiter.clone())
+        .data(LoginThrottleConfig::from_env())
+        .data(upload_config)
+        .data(signer.clone())
+        .data(mailer)
         .data(version)
-        .finish();
+        .extension(ApiScopes);
+    if let Some(config) = oidc {
+        schema = schema.data(OidcClient::new(config));
+    }
+    let schema = schema.finish();
 
     log::info!("Running server at http://127.0.0.1:8000/graphiql");
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(schema.clone()))
-            .app_data(web::Data::new(rt_server.clone()))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(rt_state.clone()))
+            .app_data(web::Data::new(data.clone()))
+            .app_data(web::Data::new(signer.clone()))
+            .app_data(web::Data::new(proxies.clone()))
+            .app_data(multipart_options)
             .wrap(Cors::permissive())
             .service(gql_handler)
             .service(gql_playground_handler)
@@ -90,17 +145,19 @@ async fn main() -> std::io::Result<()> {
                     .to(gql_ws_handler),
             )
             .service(connect)
-            .service(
-                web::scope(CDN_PATH).service(
-                    actix_files::Files::new("/", "data/")
-                        .show_files_listing()
-                        .use_last_modified(true),
-                ),
+            .service(connect_dm)
+            .service(web::scope(CDN_PATH).service(cdn::serve))
+            .wrap(
+                SessionMiddleware::builder(
+                    RedisActorSessionStore::new(redis_url.clone()),
+                    key.clone(),
+                )
+                // Sessions listed by the `sessions` query expire after the same time
+                .session_lifecycle(
+                    BrowserSession::default().state_ttl(Duration::hours(SESSION_TTL_HOURS)),
+                )
+                .build(),
             )
-            .wrap(SessionMiddleware::new(
-                RedisActorSessionStore::new(redis_url.clone()),
-                key.clone(),
-            ))
             .wrap(middleware::Logger::default())
     })
     .bind("127.0.0.1:8000")
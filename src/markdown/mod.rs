@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+
+use ammonia::{Builder, UrlRelative};
+use once_cell::sync::Lazy;
+use pulldown_cmark::{html, Options, Parser};
+
+static SANITIZER: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .tags(HashSet::from([
+            "a",
+            "blockquote",
+            "br",
+            "code",
+            "del",
+            "em",
+            "h1",
+            "h2",
+            "h3",
+            "h4",
+            "h5",
+            "h6",
+            "hr",
+            "img",
+            "li",
+            "ol",
+            "p",
+            "pre",
+            "strong",
+            "table",
+            "tbody",
+            "td",
+            "th",
+            "thead",
+            "tr",
+            "ul",
+        ]))
+        .tag_attributes(
+            [
+                ("a", HashSet::from(["href", "title"])),
+                ("img", HashSet::from(["src", "alt", "title"])),
+                ("ol", HashSet::from(["start"])),
+                ("code", HashSet::from(["class"])),
+                ("th", HashSet::from(["align"])),
+                ("td", HashSet::from(["align"])),
+            ]
+            .into(),
+        )
+        .generic_attributes(HashSet::new())
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .url_relative(UrlRelative::PassThrough)
+        .link_rel(Some("noopener noreferrer nofollow"));
+    builder
+});
+
+/// Renders CommonMark (plus tables and strikethrough) into allowlisted html.
+/// Raw html in the source is sanitized the same way as the generated markup
+pub fn render(content: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let mut unsafe_html = String::with_capacity(content.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, Parser::new_ext(content, options));
+
+    SANITIZER.clean(&unsafe_html).to_string()
+}
@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+
+use serde::Deserialize;
+use tokio::process::Command;
+
+use super::{EncodedImage, UploadConfig, UploadError};
+use crate::db::models::file::{MediaKind, RenditionKind};
+use crate::spawn_blocking;
+
+/// What ffprobe found in an audio or video upload
+pub struct Probe {
+    pub kind: MediaKind,
+    /// Seconds
+    pub duration: Option<f64>,
+    /// Codecs of the streams, comma separated
+    pub codec: String,
+    /// First frame worth showing, only for videos
+    pub poster: Option<EncodedImage>,
+}
+
+/// File in the temp dir that's removed when dropped, ffprobe and ffmpeg need something
+/// they can seek in
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn new() -> Self {
+        Self(std::env::temp_dir().join(format!("rtwalk-{}", uuid::Uuid::new_v4())))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Default for TempFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+#[derive(Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    #[serde(default)]
+    disposition: FfprobeDisposition,
+}
+
+#[derive(Deserialize, Default)]
+struct FfprobeDisposition {
+    #[serde(default)]
+    attached_pic: u8,
+}
+
+#[derive(Deserialize)]
+struct FfprobeFormat {
+    duration: Option<String>,
+}
+
+pub fn is_av(mime: &str) -> bool {
+    mime.starts_with("video/") || mime.starts_with("audio/")
+}
+
+/// Runs ffprobe or ffmpeg for at most `config.ffmpeg_timeout`, a crafted file could
+/// otherwise keep them (and the upload waiting on them) busy forever
+async fn run(command: &mut Command, config: &UploadConfig) -> anyhow::Result<Output> {
+    // Dropping the output future on timeout kills the process
+    let output = command.stdin(Stdio::null()).kill_on_drop(true).output();
+    match tokio::time::timeout(config.ffmpeg_timeout, output).await {
+        Ok(output) => Ok(output?),
+        Err(_) => Err(UploadError::Unreadable.into()),
+    }
+}
+
+/// Reads the streams of the file with ffprobe, rejecting codecs that aren't allowed,
+/// and grabs a poster frame for videos
+pub async fn probe(path: &Path, config: &UploadConfig) -> anyhow::Result<Probe> {
+    let output = run(
+        Command::new(&config.ffprobe)
+            .args([
+                "-v",
+                "error",
+                "-print_format",
+                "json",
+                "-show_format",
+                "-show_streams",
+            ])
+            .arg(path),
+        config,
+    )
+    .await?;
+    if !output.status.success() {
+        return Err(UploadError::Unreadable.into());
+    }
+    let probed: FfprobeOutput =
+        serde_json::from_slice(&output.stdout).map_err(|_| UploadError::Unreadable)?;
+
+    // Cover art of audio files shows up as a video stream
+    let streams = probed
+        .streams
+        .iter()
+        .filter(|s| s.disposition.attached_pic == 0)
+        .filter_map(|s| match (s.codec_type.as_deref(), &s.codec_name) {
+            (Some(ty @ ("video" | "audio")), Some(codec)) => Some((ty, codec.as_str())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let kind = match streams.iter().map(|(ty, _)| *ty).collect::<Vec<_>>() {
+        types if types.contains(&"video") => MediaKind::Video,
+        types if types.contains(&"audio") => MediaKind::Audio,
+        _ => return Err(UploadError::Unreadable.into()),
+    };
+    for (_, codec) in streams.iter() {
+        if !config.allowed_codecs.iter().any(|c| c == codec) {
+            return Err(UploadError::UnsupportedCodec(codec.to_string()).into());
+        }
+    }
+
+    let duration = probed
+        .format
+        .and_then(|f| f.duration)
+        .and_then(|d| d.parse::<f64>().ok());
+    let poster = match kind {
+        MediaKind::Video => poster(path, duration, config).await?,
+        _ => None,
+    };
+
+    Ok(Probe {
+        kind,
+        duration,
+        codec: streams
+            .iter()
+            .map(|(_, codec)| *codec)
+            .collect::<Vec<_>>()
+            .join(", "),
+        poster,
+    })
+}
+
+/// [`probe`] for uploads held in memory
+pub async fn probe_bytes(bytes: &[u8], config: &UploadConfig) -> anyhow::Result<Probe> {
+    let file = TempFile::new();
+    tokio::fs::write(file.path(), bytes).await?;
+    probe(file.path(), config).await
+}
+
+/// A frame a little into the video (the first one is often black), scaled down like
+/// the medium rendition of images
+async fn poster(
+    path: &Path,
+    duration: Option<f64>,
+    config: &UploadConfig,
+) -> anyhow::Result<Option<EncodedImage>> {
+    let seek = duration.map(|d| (d / 10.0).min(1.0)).unwrap_or(0.0);
+    let output = run(
+        Command::new(&config.ffmpeg)
+            .args(["-v", "error", "-ss", &seek.to_string(), "-i"])
+            .arg(path)
+            .args(["-frames:v", "1", "-f", "image2", "-c:v", "png", "pipe:1"]),
+        config,
+    )
+    .await?;
+    if !output.status.success() || output.stdout.is_empty() {
+        log::warn!("Could not extract a poster from {}", path.display());
+        return Ok(None);
+    }
+
+    let frame = output.stdout;
+    let processed = spawn_blocking!(super::process_image(&frame))??;
+    Ok(processed.map(|mut processed| {
+        match processed
+            .renditions
+            .iter()
+            .position(|(kind, _)| *kind == RenditionKind::Medium)
+        {
+            Some(pos) => processed.renditions.swap_remove(pos).1,
+            None => processed.original,
+        }
+    }))
+}
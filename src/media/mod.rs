@@ -0,0 +1,333 @@
+pub mod av;
+pub mod signing;
+pub mod sweeper;
+
+use std::env;
+use std::io::Cursor;
+use std::time::Duration;
+
+use async_graphql::ErrorExtensions;
+use image::{imageops::FilterType, io::Limits, DynamicImage, ImageFormat, ImageOutputFormat};
+
+use crate::constants::{MAX_IMAGE_DIMENSION, MEDIUM_SIZE, THUMBNAIL_SIZE};
+use crate::db::models::file::RenditionKind;
+
+#[derive(Clone, Debug)]
+pub struct UploadConfig {
+    /// Largest accepted upload in bytes
+    pub max_file_size: u64,
+    /// Largest accepted chunked upload in bytes, images are still held to `max_file_size`
+    pub max_chunked_size: u64,
+    /// Size of every chunk of a chunked upload but the last
+    pub chunk_size: u64,
+    /// Chunked uploads a user can have going at once
+    pub max_upload_sessions: i64,
+    /// Bytes a user can store, including renditions, unless the user has its own quota
+    pub user_quota: i64,
+    /// Sniffed content types that can be uploaded, `type/*` allows a whole type. Images
+    /// are only accepted in the formats `process_image` can strip, whatever is allowed here
+    pub allowed_types: Vec<String>,
+    /// Codecs (as named by ffprobe) audio and video uploads can use
+    pub allowed_codecs: Vec<String>,
+    pub ffprobe: String,
+    pub ffmpeg: String,
+    /// How long ffprobe and ffmpeg can take on a file before they're killed
+    pub ffmpeg_timeout: Duration,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: 10 * 1024 * 1024,
+            max_chunked_size: 1024 * 1024 * 1024,
+            chunk_size: 5 * 1024 * 1024,
+            max_upload_sessions: 5,
+            user_quota: 500 * 1024 * 1024,
+            allowed_types: [
+                "image/jpeg",
+                "image/png",
+                "image/gif",
+                "image/webp",
+                "video/mp4",
+                "video/webm",
+                "audio/mpeg",
+                "audio/ogg",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            allowed_codecs: [
+                "h264", "hevc", "vp8", "vp9", "av1", "aac", "mp3", "opus", "vorbis", "flac",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            ffprobe: "ffprobe".into(),
+            ffmpeg: "ffmpeg".into(),
+            ffmpeg_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl UploadConfig {
+    /// Reads `UPLOAD_MAX_FILE_SIZE`, `UPLOAD_MAX_CHUNKED_SIZE`, `UPLOAD_CHUNK_SIZE`,
+    /// `UPLOAD_USER_QUOTA` (bytes), `UPLOAD_MAX_SESSIONS`, `UPLOAD_ALLOWED_TYPES`,
+    /// `UPLOAD_ALLOWED_CODECS` (comma separated), `FFPROBE_PATH`, `FFMPEG_PATH` and
+    /// `FFMPEG_TIMEOUT` (seconds), keeping the defaults for anything unset
+    pub fn from_env() -> anyhow::Result<Self> {
+        let default = Self::default();
+        let config = Self {
+            max_file_size: env::var("UPLOAD_MAX_FILE_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_file_size),
+            max_chunked_size: env::var("UPLOAD_MAX_CHUNKED_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_chunked_size),
+            chunk_size: env::var("UPLOAD_CHUNK_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.chunk_size),
+            max_upload_sessions: env::var("UPLOAD_MAX_SESSIONS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_upload_sessions),
+            user_quota: env::var("UPLOAD_USER_QUOTA")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.user_quota),
+            allowed_types: env::var("UPLOAD_ALLOWED_TYPES")
+                .map(|v| {
+                    v.split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty())
+                        .collect()
+                })
+                .unwrap_or(default.allowed_types),
+            allowed_codecs: env::var("UPLOAD_ALLOWED_CODECS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty())
+                        .collect()
+                })
+                .unwrap_or(default.allowed_codecs),
+            ffprobe: env::var("FFPROBE_PATH").unwrap_or(default.ffprobe),
+            ffmpeg: env::var("FFMPEG_PATH").unwrap_or(default.ffmpeg),
+            ffmpeg_timeout: env::var("FFMPEG_TIMEOUT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default.ffmpeg_timeout),
+        };
+        // Chunk sizes are stored as INTEGER and divide the upload size
+        if config.chunk_size == 0 || config.chunk_size > i32::MAX as u64 {
+            return Err(anyhow::Error::msg(format!(
+                "UPLOAD_CHUNK_SIZE has to be between 1 and {}",
+                i32::MAX
+            )));
+        }
+        Ok(config)
+    }
+
+    pub fn allows(&self, mime: &str) -> bool {
+        self.allowed_types
+            .iter()
+            .any(|allowed| match allowed.strip_suffix("/*") {
+                Some(ty) => mime.split('/').next() == Some(ty),
+                None => allowed == mime,
+            })
+    }
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    TooLarge {
+        size: u64,
+        max: u64,
+    },
+    /// Chunked uploads a user already has going
+    TooManyUploads(i64),
+    /// Sniffed content type, `None` when it couldn't be recognized
+    UnsupportedType(Option<String>),
+    /// Codec of an audio or video stream
+    UnsupportedCodec(String),
+    /// Audio or video ffprobe couldn't make sense of
+    Unreadable,
+    QuotaExceeded {
+        needed: i64,
+        quota: i64,
+    },
+}
+
+impl UploadError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::TooLarge { .. } => "413",
+            Self::TooManyUploads(_) => "429",
+            Self::UnsupportedType(_) | Self::UnsupportedCodec(_) => "415",
+            Self::Unreadable => "422",
+            Self::QuotaExceeded { .. } => "507",
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { size, max } => {
+                write!(f, "File is too large ({} bytes, at most {})", size, max)
+            }
+            Self::TooManyUploads(max) => {
+                write!(
+                    f,
+                    "Finish or cancel an upload first, at most {} can be going",
+                    max
+                )
+            }
+            Self::UnsupportedType(Some(mime)) => write!(f, "Files of type {} aren't allowed", mime),
+            Self::UnsupportedType(None) => write!(f, "Unrecognized file type"),
+            Self::UnsupportedCodec(codec) => {
+                write!(f, "Media encoded with {} isn't allowed", codec)
+            }
+            Self::Unreadable => write!(f, "Could not read the media file"),
+            Self::QuotaExceeded { needed, quota } => write!(
+                f,
+                "Storage quota exceeded ({} more bytes needed, quota is {})",
+                needed, quota
+            ),
+        }
+    }
+}
+
+impl ErrorExtensions for UploadError {
+    fn extend(&self) -> async_graphql::Error {
+        let code = self.code();
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| e.set("code", code))
+    }
+}
+
+pub struct EncodedImage {
+    pub bytes: Vec<u8>,
+    pub extension: &'static str,
+    pub mime: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct ProcessedImage {
+    pub original: EncodedImage,
+    /// Only the renditions smaller than the original
+    pub renditions: Vec<(RenditionKind, EncodedImage)>,
+}
+
+/// Content type from the magic bytes, ignoring whatever the client claims
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    infer::get(bytes).map(|t| t.mime_type())
+}
+
+/// Reads the EXIF orientation tag, 1 (upright) when there's none
+fn orientation(bytes: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> anyhow::Result<EncodedImage> {
+    let mut bytes = Vec::new();
+    let (extension, mime) = match format {
+        ImageFormat::Jpeg => {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Jpeg(85))?;
+            ("jpg", "image/jpeg")
+        }
+        _ => {
+            image.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)?;
+            ("png", "image/png")
+        }
+    };
+    Ok(EncodedImage {
+        bytes,
+        extension,
+        mime,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+/// Decodes the image, bakes the EXIF orientation in and re-encodes it so no metadata
+/// survives, then renders the smaller renditions.
+/// Returns `None` for content that isn't an image we handle. CPU bound, run it blocking
+pub fn process_image(bytes: &[u8]) -> anyhow::Result<Option<ProcessedImage>> {
+    let format = match image::guess_format(bytes) {
+        Ok(f @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP)) => f,
+        _ => return Ok(None),
+    };
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+
+    let mut reader = image::io::Reader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let image = apply_orientation(reader.decode()?, orientation(bytes));
+
+    // Jpeg stays lossy, everything else turns into png since that's the other encoder
+    // we have. Gifs can't carry EXIF so they're kept as is to not lose the animation
+    let output = match format {
+        ImageFormat::Jpeg => ImageFormat::Jpeg,
+        _ => ImageFormat::Png,
+    };
+
+    let original = match format {
+        ImageFormat::Gif => EncodedImage {
+            bytes: bytes.to_vec(),
+            extension: "gif",
+            mime: "image/gif",
+            width: image.width(),
+            height: image.height(),
+        },
+        _ => encode(&image, output)?,
+    };
+
+    let mut renditions = Vec::with_capacity(2);
+    for (kind, size) in [
+        (RenditionKind::Medium, MEDIUM_SIZE),
+        (RenditionKind::Thumbnail, THUMBNAIL_SIZE),
+    ] {
+        if image.width() <= size && image.height() <= size {
+            continue;
+        }
+        let resized = match kind {
+            RenditionKind::Thumbnail => image.thumbnail(size, size),
+            _ => image.resize(size, size, FilterType::CatmullRom),
+        };
+        renditions.push((kind, encode(&resized, output)?));
+    }
+
+    Ok(Some(ProcessedImage {
+        original,
+        renditions,
+    }))
+}
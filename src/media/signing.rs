@@ -0,0 +1,96 @@
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::constants::CDN_PATH;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Query string of a signed `/cdn` url
+#[derive(Debug, Deserialize)]
+pub struct Signature {
+    pub expires: u64,
+    pub signature: String,
+}
+
+/// Signs `/cdn` urls so stored files can't be enumerated and links stop working after a while
+#[derive(Clone)]
+pub struct UrlSigner {
+    key: Vec<u8>,
+    /// Urls stay valid for at least this long
+    ttl: Duration,
+}
+
+impl UrlSigner {
+    pub fn new(key: &[u8], ttl: Duration) -> Self {
+        Self {
+            key: key.to_vec(),
+            ttl,
+        }
+    }
+
+    /// Reads `MEDIA_SIGNING_KEY` and `MEDIA_URL_TTL` (seconds, an hour by default). The key
+    /// has to be its own, reusing the cookie key `AUTH_KEY` is refused
+    pub fn from_env() -> anyhow::Result<Self> {
+        let key = env::var("MEDIA_SIGNING_KEY")
+            .map_err(|_| anyhow::Error::msg("MEDIA_SIGNING_KEY not set"))?;
+        if env::var("AUTH_KEY").is_ok_and(|auth_key| auth_key == key) {
+            return Err(anyhow::Error::msg(
+                "MEDIA_SIGNING_KEY has to differ from AUTH_KEY",
+            ));
+        }
+        let ttl = env::var("MEDIA_URL_TTL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(60 * 60));
+        Ok(Self::new(key.as_bytes(), ttl))
+    }
+
+    fn mac(&self, path: &str, expires: u64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(path.as_bytes());
+        mac.update(b":");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    /// Url with the CDN prefix. The expiry is rounded up to the next ttl window so the
+    /// same file keeps the same url for a while and can be cached
+    pub fn sign(&self, path: &str) -> String {
+        let ttl = self.ttl.as_secs().max(1);
+        self.sign_until(path, (now() / ttl + 2) * ttl)
+    }
+
+    /// Url with the CDN prefix that stops working at `expires` (unix seconds)
+    pub fn sign_until(&self, path: &str, expires: u64) -> String {
+        let signature = hex::encode(self.mac(path, expires).finalize().into_bytes());
+        format!(
+            "{}/{}?expires={}&signature={}",
+            CDN_PATH, path, expires, signature
+        )
+    }
+
+    pub fn verify(&self, path: &str, signature: &Signature) -> bool {
+        if signature.expires < now() {
+            return false;
+        }
+        match hex::decode(&signature.signature) {
+            Ok(bytes) => self
+                .mac(path, signature.expires)
+                .verify_slice(&bytes)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+use actix::prelude::*;
+use async_graphql::SimpleObject;
+use opendal::Operator;
+
+use crate::constants::DATA_EXPORT_TTL_HOURS;
+use crate::gql::mutation::export::delete_exports;
+use crate::gql::mutation::file::{delete_orphaned_files, find_orphaned_files, release_storage};
+use crate::gql::mutation::upload::delete_stale_upload_sessions;
+use crate::gql::query::{
+    export::get_expired_exports, file::get_renditions, upload::get_stale_upload_sessions,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct SweeperConfig {
+    /// How often orphaned uploads are looked for
+    pub interval: Duration,
+    /// Uploads younger than this are kept, they may be about to be attached.
+    /// Chunked uploads that got no chunk for this long are dropped too
+    pub grace_period: Duration,
+    /// Only report what would be deleted
+    pub dry_run: bool,
+    /// Most files handled in one sweep
+    pub batch_size: i64,
+}
+
+impl Default for SweeperConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60 * 60),
+            grace_period: Duration::from_secs(24 * 60 * 60),
+            dry_run: false,
+            batch_size: 500,
+        }
+    }
+}
+
+impl SweeperConfig {
+    /// Reads `MEDIA_SWEEP_INTERVAL`, `MEDIA_SWEEP_GRACE_PERIOD` (seconds),
+    /// `MEDIA_SWEEP_DRY_RUN` and `MEDIA_SWEEP_BATCH_SIZE`, keeping the defaults for anything unset
+    pub fn from_env() -> Self {
+        fn secs(key: &str, default: Duration) -> Duration {
+            env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        }
+
+        let default = Self::default();
+        Self {
+            interval: secs("MEDIA_SWEEP_INTERVAL", default.interval),
+            grace_period: secs("MEDIA_SWEEP_GRACE_PERIOD", default.grace_period),
+            dry_run: env::var("MEDIA_SWEEP_DRY_RUN")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.dry_run),
+            batch_size: env::var("MEDIA_SWEEP_BATCH_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.batch_size),
+        }
+    }
+}
+
+#[derive(Clone, Debug, SimpleObject)]
+pub struct SweepReport {
+    pub dry_run: bool,
+    /// Ids of the orphaned uploads
+    pub files: Vec<String>,
+    /// Bytes freed (or that would be) including renditions
+    pub bytes: i64,
+    /// Ids of the abandoned chunked uploads
+    pub uploads: Vec<String>,
+    /// Paths of the data exports past `DATA_EXPORT_TTL_HOURS`
+    pub exports: Vec<String>,
+}
+
+#[derive(Message)]
+#[rtype(result = "anyhow::Result<SweepReport>")]
+pub struct Sweep {
+    pub dry_run: bool,
+}
+
+/// Deletes uploads that nothing references once they're past the grace period.
+/// Only files recorded in the files table are considered. Expired data exports go too
+pub struct MediaSweeper {
+    config: SweeperConfig,
+    pool: crate::Pool,
+    op: Operator,
+}
+
+impl MediaSweeper {
+    pub fn new(pool: crate::Pool, op: Operator, config: SweeperConfig) -> Self {
+        Self { config, pool, op }
+    }
+}
+
+async fn stored_size(path: &str, op: &Operator) -> i64 {
+    op.stat(path)
+        .await
+        .map(|m| m.content_length() as i64)
+        .unwrap_or(0)
+}
+
+async fn sweep(
+    dry_run: bool,
+    config: SweeperConfig,
+    pool: crate::Pool,
+    op: Operator,
+) -> anyhow::Result<SweepReport> {
+    let before = chrono::Utc::now().naive_utc() - chrono::Duration::from_std(config.grace_period)?;
+    let orphans = find_orphaned_files(before, config.batch_size, &pool).await?;
+    let ids = orphans.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+    let exports_before =
+        chrono::Utc::now().naive_utc() - chrono::Duration::hours(DATA_EXPORT_TTL_HOURS);
+    let exports = get_expired_exports(exports_before, &pool).await?;
+
+    if dry_run {
+        let uploads = get_stale_upload_sessions(before, &pool).await?;
+        let mut bytes = 0;
+        for id in ids.iter() {
+            bytes += stored_size(id, &op).await;
+            for rendition in get_renditions(id, &pool).await? {
+                if &rendition.path != id {
+                    bytes += stored_size(&rendition.path, &op).await;
+                }
+            }
+        }
+        return Ok(SweepReport {
+            dry_run,
+            files: ids,
+            bytes,
+            uploads: uploads.into_iter().map(|u| u.id).collect(),
+            exports,
+        });
+    }
+
+    let uploads = delete_stale_upload_sessions(before, &op, &pool).await?;
+    let paths = delete_orphaned_files(&ids, &pool).await?;
+
+    let mut freed: HashMap<i32, i64> = HashMap::new();
+    let mut files = Vec::new();
+    for (path, owner_id) in paths {
+        let size = stored_size(&path, &op).await;
+        if let Err(e) = op.delete(&path).await {
+            log::error!("Failed to delete {path}: {e:?}");
+            continue;
+        }
+        *freed.entry(owner_id).or_default() += size;
+        if ids.contains(&path) {
+            files.push(path);
+        }
+    }
+
+    for (owner_id, size) in freed.iter() {
+        release_storage(*owner_id, *size, &pool).await?;
+    }
+
+    let mut deleted_exports = Vec::new();
+    for path in exports {
+        match op.delete(&path).await {
+            Ok(_) => deleted_exports.push(path),
+            Err(e) => log::error!("Failed to delete {path}: {e:?}"),
+        }
+    }
+    delete_exports(&deleted_exports, &pool).await?;
+
+    Ok(SweepReport {
+        dry_run,
+        files,
+        bytes: freed.values().sum(),
+        uploads: uploads.into_iter().map(|u| u.id).collect(),
+        exports: deleted_exports,
+    })
+}
+
+impl Actor for MediaSweeper {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.config.interval, |act, ctx| {
+            let fut = sweep(
+                act.config.dry_run,
+                act.config,
+                act.pool.clone(),
+                act.op.clone(),
+            );
+            ctx.spawn(fut.into_actor(act).map(|res, _, _| match res {
+                Ok(report)
+                    if !report.files.is_empty()
+                        || !report.uploads.is_empty()
+                        || !report.exports.is_empty() =>
+                {
+                    log::info!(
+                        "Media sweep{}: {} files, {} bytes, {} abandoned uploads, {} expired exports {:?}",
+                        if report.dry_run { " (dry run)" } else { "" },
+                        report.files.len(),
+                        report.bytes,
+                        report.uploads.len(),
+                        report.exports.len(),
+                        report.files
+                    )
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Media sweep failed: {e:?}"),
+            }));
+        });
+    }
+}
+
+impl Handler<Sweep> for MediaSweeper {
+    type Result = ResponseFuture<anyhow::Result<SweepReport>>;
+
+    fn handle(&mut self, msg: Sweep, _: &mut Self::Context) -> Self::Result {
+        Box::pin(sweep(
+            msg.dry_run,
+            self.config,
+            self.pool.clone(),
+            self.op.clone(),
+        ))
+    }
+}
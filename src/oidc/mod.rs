@@ -0,0 +1,268 @@
+use std::env;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+
+use crate::constants::ALLOWED_USERNAME_CHARS;
+use crate::gql::mutation::token::random_token;
+use crate::helpers::check_reserved_username;
+
+/// OpenID Connect provider users can log in with. The issuer can be any provider
+/// publishing `/.well-known/openid-configuration`, a local mock server included
+#[derive(Clone, Debug)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Page of the frontend the provider sends users back to, it passes `code` and
+    /// `state` on to the `oidcLogin` mutation
+    pub redirect_url: String,
+    pub scopes: String,
+    /// Create a user for provider accounts that aren't linked to one yet
+    pub auto_provision: bool,
+}
+
+impl OidcConfig {
+    /// Reads `OIDC_ISSUER`, `OIDC_CLIENT_ID`, `OIDC_CLIENT_SECRET`, `OIDC_REDIRECT_URL`,
+    /// `OIDC_SCOPES` (`openid email profile` by default) and `OIDC_AUTO_PROVISION` (`true`
+    /// by default). `None` when no issuer is set, logging in with a provider is disabled then
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let issuer = match env::var("OIDC_ISSUER") {
+            Ok(issuer) if !issuer.is_empty() => issuer,
+            _ => return Ok(None),
+        };
+        let var =
+            |key: &str| env::var(key).map_err(|_| anyhow::Error::msg(format!("{key} not set")));
+        Ok(Some(Self {
+            issuer,
+            client_id: var("OIDC_CLIENT_ID")?,
+            client_secret: var("OIDC_CLIENT_SECRET")?,
+            redirect_url: var("OIDC_REDIRECT_URL")?,
+            scopes: env::var("OIDC_SCOPES").unwrap_or_else(|_| "openid email profile".into()),
+            auto_provision: env::var("OIDC_AUTO_PROVISION")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(true),
+        }))
+    }
+}
+
+/// The parts of the provider metadata the authorization code flow needs
+#[derive(Clone, Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// What a login with the provider is for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OidcIntent {
+    #[default]
+    Login,
+    /// Linking the provider account to the user, who confirmed their password first
+    Link(i32),
+    /// Proving it's still the user, in place of a password they might not have
+    Reauthenticate(i32),
+}
+
+/// Kept in the cookie session between sending the user to the provider and the callback
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub state: String,
+    #[serde(default)]
+    pub intent: OidcIntent,
+    nonce: String,
+    code_verifier: String,
+}
+
+/// Claims of a verified id token
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdClaims {
+    pub iss: String,
+    pub sub: String,
+    nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    pub name: Option<String>,
+}
+
+impl IdClaims {
+    /// Email to trust for linking and new users, only when the provider verified it
+    pub fn verified_email(&self) -> Option<&str> {
+        self.email.as_deref().filter(|_| self.email_verified)
+    }
+}
+
+/// Authorization code flow with PKCE against the configured provider.
+/// Provider metadata is fetched once, signing keys on every login so rotated keys work
+pub struct OidcClient {
+    pub config: OidcConfig,
+    http: reqwest::Client,
+    discovery: OnceCell<Discovery>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+            discovery: OnceCell::new(),
+        }
+    }
+
+    async fn discovery(&self) -> anyhow::Result<&Discovery> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                let discovery = self
+                    .http
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<Discovery>()
+                    .await?;
+                // Another issuer's metadata could point to endpoints and keys it controls
+                if discovery.issuer.trim_end_matches('/')
+                    != self.config.issuer.trim_end_matches('/')
+                {
+                    return Err(anyhow::anyhow!(
+                        "Discovery document is for issuer {}, expected {}",
+                        discovery.issuer,
+                        self.config.issuer
+                    ));
+                }
+                Ok(discovery)
+            })
+            .await
+    }
+
+    /// Url to send the user to, with the state to keep until they come back
+    pub async fn authorization_url(
+        &self,
+        intent: OidcIntent,
+    ) -> anyhow::Result<(String, PendingLogin)> {
+        let discovery = self.discovery().await?;
+        let pending = PendingLogin {
+            state: random_token(),
+            intent,
+            nonce: random_token(),
+            code_verifier: random_token(),
+        };
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(pending.code_verifier.as_bytes()));
+
+        let mut url = reqwest::Url::parse(&discovery.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", &pending.state)
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+        Ok((url.into(), pending))
+    }
+
+    /// Exchanges the code from the callback for an id token and verifies it
+    pub async fn exchange(&self, code: &str, pending: &PendingLogin) -> anyhow::Result<IdClaims> {
+        let discovery = self.discovery().await?;
+        let tokens = self
+            .http
+            .post(&discovery.token_endpoint)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.config.redirect_url),
+                ("client_id", &self.config.client_id),
+                ("code_verifier", &pending.code_verifier),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()
+            .await?;
+
+        let claims = self.verify_id_token(discovery, &tokens.id_token).await?;
+        if claims.nonce.as_deref() != Some(pending.nonce.as_str()) {
+            return Err(anyhow::Error::msg("Id token nonce doesn't match"));
+        }
+        Ok(claims)
+    }
+
+    async fn verify_id_token(
+        &self,
+        discovery: &Discovery,
+        id_token: &str,
+    ) -> anyhow::Result<IdClaims> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        let jwks = self
+            .http
+            .get(&discovery.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<JwkSet>()
+            .await?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| anyhow::Error::msg("No signing key found for the id token"))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        let key = DecodingKey::from_jwk(jwk)?;
+        Ok(jsonwebtoken::decode::<IdClaims>(id_token, &key, &validation)?.claims)
+    }
+}
+
+/// Username for a new user of a provider account, from its preferred username or email.
+/// Characters that aren't allowed become `_`, reserved or empty names fall back to `user`.
+/// `suffix` is appended to try again when the name is taken
+pub fn username_candidate(claims: &IdClaims, suffix: Option<u32>) -> String {
+    let base = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| claims.email.as_deref().and_then(|e| e.split('@').next()))
+        .or(claims.name.as_deref())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let mut name: String = base
+        .chars()
+        .map(|c| {
+            if ALLOWED_USERNAME_CHARS.contains(&c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    name = name.trim_matches('_').to_string();
+    if name.is_empty() || check_reserved_username(&name) {
+        name = "user".into();
+    }
+
+    let suffix = suffix.map(|s| format!("_{s}")).unwrap_or_default();
+    name.truncate(20 - suffix.len());
+    name + &suffix
+}
@@ -0,0 +1,95 @@
+use std::env;
+use std::time::Duration;
+
+use async_graphql::ErrorExtensions;
+
+/// Failed logins are counted per username and per ip, once either has failed
+/// `free_attempts` times it's locked for `base_delay`, doubling with every further failure
+#[derive(Clone, Copy, Debug)]
+pub struct LoginThrottleConfig {
+    pub free_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Failures are forgotten after this long without another one
+    pub reset_after: Duration,
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        Self {
+            free_attempts: 5,
+            base_delay: Duration::from_secs(30),
+            max_delay: Duration::from_secs(60 * 60),
+            reset_after: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+impl LoginThrottleConfig {
+    /// Reads `LOGIN_FREE_ATTEMPTS`, `LOGIN_BASE_DELAY`, `LOGIN_MAX_DELAY` and
+    /// `LOGIN_RESET_AFTER` (seconds), keeping the defaults for anything unset
+    pub fn from_env() -> Self {
+        fn secs(key: &str, default: Duration) -> Duration {
+            env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        }
+
+        let default = Self::default();
+        Self {
+            free_attempts: env::var("LOGIN_FREE_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.free_attempts),
+            base_delay: secs("LOGIN_BASE_DELAY", default.base_delay),
+            max_delay: secs("LOGIN_MAX_DELAY", default.max_delay),
+            reset_after: secs("LOGIN_RESET_AFTER", default.reset_after),
+        }
+    }
+
+    /// How long to lock after the `failures`th failed attempt in a row
+    pub fn delay(&self, failures: u32) -> Option<Duration> {
+        let over = failures.checked_sub(self.free_attempts)?;
+        let delay = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(over))
+            .unwrap_or(self.max_delay);
+        Some(delay.min(self.max_delay))
+    }
+}
+
+#[derive(Debug)]
+pub struct LoginLocked {
+    pub retry_after: Duration,
+}
+
+impl LoginLocked {
+    /// Seconds until logging in is allowed again, rounded up
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0)
+    }
+}
+
+impl std::error::Error for LoginLocked {}
+
+impl std::fmt::Display for LoginLocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Too many failed logins, retry in {}s",
+            self.retry_after_secs()
+        )
+    }
+}
+
+impl ErrorExtensions for LoginLocked {
+    fn extend(&self) -> async_graphql::Error {
+        let retry_after = self.retry_after_secs();
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            e.set("code", "429");
+            e.set("retryAfter", retry_after);
+        })
+    }
+}
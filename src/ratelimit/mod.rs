@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_graphql::ErrorExtensions;
+
+pub mod login;
+
+/// Buckets are pruned once the map grows past this many entries
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Action {
+    Comment,
+    Message,
+    CreatePost,
+    Upload,
+    PasswordReset,
+    VerificationMail,
+    TwoFactor,
+    DataExport,
+    Mutation,
+}
+
+impl Action {
+    fn env_key(&self) -> &'static str {
+        match self {
+            Self::Comment => "COMMENT",
+            Self::Message => "MESSAGE",
+            Self::CreatePost => "CREATE_POST",
+            Self::Upload => "UPLOAD",
+            Self::PasswordReset => "PASSWORD_RESET",
+            Self::VerificationMail => "VERIFICATION_MAIL",
+            Self::TwoFactor => "TWO_FACTOR",
+            Self::DataExport => "DATA_EXPORT",
+            Self::Mutation => "MUTATION",
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Comment => "comment",
+            Self::Message => "message",
+            Self::CreatePost => "create_post",
+            Self::Upload => "upload",
+            Self::PasswordReset => "password_reset",
+            Self::VerificationMail => "verification_mail",
+            Self::TwoFactor => "two_factor",
+            Self::DataExport => "data_export",
+            Self::Mutation => "mutation",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum RateKey {
+    User(i32),
+}
+
+/// `capacity` requests are allowed in a burst, refilled over `per`
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub capacity: u32,
+    pub per: Duration,
+}
+
+impl Limit {
+    pub const fn new(capacity: u32, per_secs: u64) -> Self {
+        Self {
+            capacity,
+            per: Duration::from_secs(per_secs),
+        }
+    }
+
+    fn refill_rate(&self) -> f64 {
+        self.capacity as f64 / self.per.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Parses limits of the form `<capacity>/<seconds>`, eg. `10/60`
+    fn parse(value: &str) -> Option<Self> {
+        let (capacity, per) = value.split_once('/')?;
+        let capacity = capacity.trim().parse().ok()?;
+        let per = per.trim().parse().ok()?;
+        if capacity == 0 || per == 0 {
+            return None;
+        }
+        Some(Self::new(capacity, per))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    pub user: HashMap<Action, Limit>,
+    pub admin: HashMap<Action, Limit>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let user = HashMap::from([
+            (Action::Comment, Limit::new(10, 30)),
+            (Action::Message, Limit::new(20, 30)),
+            (Action::CreatePost, Limit::new(5, 300)),
+            (Action::Upload, Limit::new(20, 300)),
+            (Action::PasswordReset, Limit::new(3, 3600)),
+            (Action::VerificationMail, Limit::new(3, 3600)),
+            (Action::TwoFactor, Limit::new(5, 300)),
+            (Action::DataExport, Limit::new(2, 3600)),
+            (Action::Mutation, Limit::new(30, 60)),
+        ]);
+        let admin = user
+            .iter()
+            .map(|(action, limit)| {
+                (
+                    *action,
+                    Limit::new(limit.capacity * 10, limit.per.as_secs()),
+                )
+            })
+            .collect();
+        Self { user, admin }
+    }
+}
+
+impl RateLimitConfig {
+    /// Reads `RATE_LIMIT_<ACTION>` and `RATE_LIMIT_<ACTION>_ADMIN` overrides, falling back to
+    /// the defaults for anything unset or malformed. The admin limits apply to moderators too
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        for action in [
+            Action::Comment,
+            Action::Message,
+            Action::CreatePost,
+            Action::Upload,
+            Action::PasswordReset,
+            Action::VerificationMail,
+            Action::TwoFactor,
+            Action::DataExport,
+            Action::Mutation,
+        ] {
+            let key = format!("RATE_LIMIT_{}", action.env_key());
+            if let Some(limit) = env::var(&key).ok().and_then(|v| Limit::parse(&v)) {
+                config.user.insert(action, limit);
+            }
+            if let Some(limit) = env::var(format!("{}_ADMIN", key))
+                .ok()
+                .and_then(|v| Limit::parse(&v))
+            {
+                config.admin.insert(action, limit);
+            }
+        }
+        config
+    }
+
+    fn limit(&self, action: Action, elevated: bool) -> Option<&Limit> {
+        if elevated {
+            self.admin.get(&action)
+        } else {
+            self.user.get(&action)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: Limit,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn full(limit: Limit) -> Self {
+        Self {
+            limit,
+            tokens: limit.capacity as f64,
+            last: Instant::now(),
+        }
+    }
+
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        (self.tokens + elapsed * self.limit.refill_rate()).min(self.limit.capacity as f64)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        self.tokens = self.tokens_at(now);
+        self.last = now;
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimited {
+    pub action: Action,
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    /// Seconds until the next request is allowed, rounded up
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Too many requests ({}), retry in {}s",
+            self.action.as_str(),
+            self.retry_after_secs()
+        )
+    }
+}
+
+impl ErrorExtensions for RateLimited {
+    fn extend(&self) -> async_graphql::Error {
+        let retry_after = self.retry_after_secs();
+        let action = self.action.as_str();
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            e.set("code", "429");
+            e.set("action", action);
+            e.set("retryAfter", retry_after);
+        })
+    }
+}
+
+/// Token bucket rate limiter shared by the gql mutations and `RtSession`
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    buckets: Arc<Mutex<HashMap<(RateKey, Action), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Takes a token from the bucket for `key`, or returns how long to wait
+    /// until one is available. Actions without a configured limit always pass.
+    pub fn check(&self, key: RateKey, action: Action, elevated: bool) -> Result<(), RateLimited> {
+        let limit = match self.config.limit(action, elevated) {
+            Some(limit) => *limit,
+            None => return Ok(()),
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > PRUNE_THRESHOLD {
+            // Buckets that refilled completely are equivalent to a fresh one
+            buckets.retain(|_, bucket| bucket.tokens_at(now) < bucket.limit.capacity as f64);
+        }
+
+        let bucket = buckets
+            .entry((key, action))
+            .or_insert_with(|| Bucket::full(limit));
+        // The role may have changed since the bucket was created
+        bucket.limit = limit;
+        bucket.refill(now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let missing = 1.0 - bucket.tokens;
+        Err(RateLimited {
+            action,
+            retry_after: Duration::from_secs_f64(missing / limit.refill_rate()),
+        })
+    }
+}
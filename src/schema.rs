@@ -1,5 +1,66 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "api_scope"))]
+    pub struct ApiScope;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "forum_role"))]
+    pub struct ForumRole;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "login_failure_kind"))]
+    pub struct LoginFailureKind;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "media_kind"))]
+    pub struct MediaKind;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "notification_kind"))]
+    pub struct NotificationKind;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "rendition_kind"))]
+    pub struct RenditionKind;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "site_role"))]
+    pub struct SiteRole;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "token_purpose"))]
+    pub struct TokenPurpose;
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ApiScope;
+
+    api_tokens (id) {
+        id -> Int4,
+        token_hash -> Varchar,
+        user_id -> Int4,
+        name -> Varchar,
+        scopes -> Array<Nullable<ApiScope>>,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    comment_revisions (id) {
+        id -> Int4,
+        comment_id -> Int4,
+        content -> Text,
+        media -> Nullable<Array<Nullable<Text>>>,
+        created_at -> Timestamp,
+        archived_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     comments (id) {
         id -> Int4,
@@ -12,6 +73,62 @@ diesel::table! {
         created_at -> Timestamp,
         edited -> Bool,
         edited_at -> Nullable<Timestamp>,
+        content_html -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    conversation_members (conversation_id, user_id) {
+        conversation_id -> Int4,
+        user_id -> Int4,
+        joined_at -> Timestamp,
+        last_read_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    conversations (id) {
+        id -> Int4,
+        title -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        last_message_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    data_exports (path) {
+        path -> Varchar,
+        user_id -> Int4,
+        size -> Int8,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::MediaKind;
+
+    files (id) {
+        id -> Varchar,
+        owner_id -> Int4,
+        size -> Int8,
+        mime -> Varchar,
+        created_at -> Timestamp,
+        kind -> MediaKind,
+        duration -> Nullable<Float8>,
+        codec -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ForumRole;
+
+    forum_roles (forum_id, user_id) {
+        forum_id -> Int4,
+        user_id -> Int4,
+        role -> ForumRole,
+        created_at -> Timestamp,
     }
 }
 
@@ -28,6 +145,88 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::LoginFailureKind;
+
+    login_failures (kind, key) {
+        kind -> LoginFailureKind,
+        key -> Varchar,
+        failures -> Int4,
+        last_failed_at -> Timestamp,
+        locked_until -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    mentions (id) {
+        id -> Int4,
+        post_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        user_id -> Nullable<Int4>,
+        forum_id -> Nullable<Int4>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    messages (id) {
+        id -> Int4,
+        conversation_id -> Int4,
+        sender_id -> Int4,
+        content -> Text,
+        media -> Nullable<Array<Nullable<Text>>>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::NotificationKind;
+
+    notifications (id) {
+        id -> Int4,
+        user_id -> Int4,
+        actor_id -> Int4,
+        kind -> NotificationKind,
+        post_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        read -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    oidc_identities (issuer, subject) {
+        issuer -> Varchar,
+        subject -> Varchar,
+        user_id -> Int4,
+        email -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    post_revisions (id) {
+        id -> Int4,
+        post_id -> Int4,
+        title -> Varchar,
+        tags -> Nullable<Array<Nullable<Text>>>,
+        content -> Nullable<Text>,
+        media -> Nullable<Array<Nullable<Text>>>,
+        created_at -> Timestamp,
+        archived_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    post_stars (user_id, post_id) {
+        user_id -> Int4,
+        post_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     posts (id) {
         id -> Int4,
@@ -42,6 +241,114 @@ diesel::table! {
         edited_at -> Nullable<Timestamp>,
         forum_id -> Int4,
         poster_id -> Int4,
+        content_html -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    recovery_codes (code_hash) {
+        code_hash -> Varchar,
+        user_id -> Int4,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::RenditionKind;
+
+    renditions (file_id, kind) {
+        file_id -> Varchar,
+        kind -> RenditionKind,
+        path -> Varchar,
+        width -> Int4,
+        height -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    site_settings (id) {
+        id -> Bool,
+        require_verified_email -> Bool,
+    }
+}
+
+diesel::table! {
+    storage_usage (user_id) {
+        user_id -> Int4,
+        used -> Int8,
+        quota -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    totp_secrets (user_id) {
+        user_id -> Int4,
+        secret -> Varchar,
+        confirmed_at -> Nullable<Timestamp>,
+        last_used_step -> Nullable<Int8>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    upload_sessions (id) {
+        id -> Varchar,
+        owner_id -> Int4,
+        filename -> Varchar,
+        size -> Int8,
+        chunk_size -> Int4,
+        chunk_count -> Int4,
+        received -> Array<Nullable<Int4>>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::SiteRole;
+
+    user_roles (user_id) {
+        user_id -> Int4,
+        role -> SiteRole,
+        granted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_sessions (id) {
+        id -> Varchar,
+        user_id -> Int4,
+        user_agent -> Nullable<Varchar>,
+        ip -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::TokenPurpose;
+
+    user_tokens (token_hash) {
+        token_hash -> Varchar,
+        user_id -> Int4,
+        purpose -> TokenPurpose,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    username_history (id) {
+        id -> Int4,
+        user_id -> Int4,
+        username -> Varchar,
+        changed_at -> Timestamp,
     }
 }
 
@@ -56,20 +363,73 @@ diesel::table! {
         banner -> Nullable<Varchar>,
         created_at -> Timestamp,
         v -> Int4,
-        admin -> Bool,
+        email -> Nullable<Varchar>,
+        email_verified_at -> Nullable<Timestamp>,
     }
 }
 
+diesel::joinable!(api_tokens -> users (user_id));
+diesel::joinable!(comment_revisions -> comments (comment_id));
 diesel::joinable!(comments -> forums (forum_id));
 diesel::joinable!(comments -> posts (post_id));
 diesel::joinable!(comments -> users (user_id));
+diesel::joinable!(conversation_members -> conversations (conversation_id));
+diesel::joinable!(conversation_members -> users (user_id));
+diesel::joinable!(data_exports -> users (user_id));
+diesel::joinable!(files -> users (owner_id));
+diesel::joinable!(forum_roles -> forums (forum_id));
+diesel::joinable!(forum_roles -> users (user_id));
 diesel::joinable!(forums -> users (owner_id));
+diesel::joinable!(mentions -> comments (comment_id));
+diesel::joinable!(mentions -> forums (forum_id));
+diesel::joinable!(mentions -> posts (post_id));
+diesel::joinable!(mentions -> users (user_id));
+diesel::joinable!(messages -> conversations (conversation_id));
+diesel::joinable!(messages -> users (sender_id));
+diesel::joinable!(notifications -> comments (comment_id));
+diesel::joinable!(notifications -> posts (post_id));
+diesel::joinable!(oidc_identities -> users (user_id));
+diesel::joinable!(post_revisions -> posts (post_id));
+diesel::joinable!(post_stars -> posts (post_id));
+diesel::joinable!(post_stars -> users (user_id));
 diesel::joinable!(posts -> forums (forum_id));
 diesel::joinable!(posts -> users (poster_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(storage_usage -> users (user_id));
+diesel::joinable!(totp_secrets -> users (user_id));
+diesel::joinable!(upload_sessions -> users (owner_id));
+diesel::joinable!(user_roles -> users (user_id));
+diesel::joinable!(user_sessions -> users (user_id));
+diesel::joinable!(user_tokens -> users (user_id));
+diesel::joinable!(username_history -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    comment_revisions,
     comments,
+    conversation_members,
+    conversations,
+    data_exports,
+    files,
+    forum_roles,
     forums,
+    login_failures,
+    mentions,
+    messages,
+    notifications,
+    oidc_identities,
+    post_revisions,
+    post_stars,
     posts,
+    recovery_codes,
+    renditions,
+    site_settings,
+    storage_usage,
+    totp_secrets,
+    upload_sessions,
+    user_roles,
+    user_sessions,
+    user_tokens,
+    username_history,
     users,
 );
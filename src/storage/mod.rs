@@ -0,0 +1,101 @@
+use std::env;
+
+use opendal::{
+    layers::{LoggingLayer, RetryLayer},
+    services::{Fs, Memory, S3},
+    Operator,
+};
+
+#[derive(Clone, Debug)]
+pub enum StorageBackend {
+    /// Local folder, `data/` by default
+    Fs { root: String },
+    /// Any S3 compatible store, `endpoint` points to MinIO and the like
+    S3 {
+        bucket: String,
+        root: String,
+        endpoint: Option<String>,
+        region: Option<String>,
+        access_key_id: Option<String>,
+        secret_access_key: Option<String>,
+    },
+    /// Lost on restart, for tests and trying things out
+    Memory,
+}
+
+impl Default for StorageBackend {
+    fn default() -> Self {
+        Self::Fs {
+            root: "data/".into(),
+        }
+    }
+}
+
+impl StorageBackend {
+    /// Reads `STORAGE_BACKEND` (`fs`, `s3` or `memory`) and `STORAGE_ROOT`.
+    /// The s3 backend also reads `S3_BUCKET` (required), `S3_ENDPOINT`, `S3_REGION`,
+    /// `S3_ACCESS_KEY_ID` and `S3_SECRET_ACCESS_KEY`
+    pub fn from_env() -> anyhow::Result<Self> {
+        let root = env::var("STORAGE_ROOT").ok();
+        let backend = match env::var("STORAGE_BACKEND").as_deref() {
+            Ok("fs") | Err(_) => match root {
+                Some(root) => Self::Fs { root },
+                None => Self::default(),
+            },
+            Ok("s3") => Self::S3 {
+                bucket: env::var("S3_BUCKET")
+                    .map_err(|_| anyhow::Error::msg("S3_BUCKET not set"))?,
+                root: root.unwrap_or("/".into()),
+                endpoint: env::var("S3_ENDPOINT").ok(),
+                region: env::var("S3_REGION").ok(),
+                access_key_id: env::var("S3_ACCESS_KEY_ID").ok(),
+                secret_access_key: env::var("S3_SECRET_ACCESS_KEY").ok(),
+            },
+            Ok("memory") => Self::Memory,
+            Ok(other) => {
+                return Err(anyhow::Error::msg(format!(
+                    "Unknown storage backend: {}",
+                    other
+                )))
+            }
+        };
+        Ok(backend)
+    }
+
+    /// Every read and write of uploads goes through this operator
+    pub fn operator(&self) -> anyhow::Result<Operator> {
+        let op = match self {
+            Self::Fs { root } => {
+                let mut builder = Fs::default();
+                builder.root(root).enable_path_check();
+                Operator::new(builder)?.finish()
+            }
+            Self::S3 {
+                bucket,
+                root,
+                endpoint,
+                region,
+                access_key_id,
+                secret_access_key,
+            } => {
+                let mut builder = S3::default();
+                builder.bucket(bucket).root(root);
+                if let Some(endpoint) = endpoint {
+                    builder.endpoint(endpoint);
+                }
+                if let Some(region) = region {
+                    builder.region(region);
+                }
+                if let Some(key) = access_key_id {
+                    builder.access_key_id(key);
+                }
+                if let Some(secret) = secret_access_key {
+                    builder.secret_access_key(secret);
+                }
+                Operator::new(builder)?.finish()
+            }
+            Self::Memory => Operator::new(Memory::default())?.finish(),
+        };
+        Ok(op.layer(LoggingLayer::default()).layer(RetryLayer::new()))
+    }
+}
@@ -0,0 +1,183 @@
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rand::RngCore;
+use sha1::Sha1;
+
+/// Seconds a code is valid for
+const STEP: u64 = 30;
+const DIGITS: u32 = 6;
+/// Codes of the steps right before and after now are accepted too, for clock drift
+const SKEW: u64 = 1;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(value: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(value.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in value.bytes().filter(|c| *c != b'=' && *c != b' ') {
+        let index = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | index as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// 160 random bits, base32 encoded like authenticator apps expect
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base32_encode(&bytes)
+}
+
+/// `otpauth://` uri to show as a QR code
+pub fn otpauth_uri(secret: &str, account: &str, issuer: &str) -> String {
+    let issuer = utf8_percent_encode(issuer, NON_ALPHANUMERIC).to_string();
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        utf8_percent_encode(account, NON_ALPHANUMERIC),
+        secret,
+        issuer,
+        DIGITS,
+        STEP
+    )
+}
+
+/// RFC 6238 code of the time step
+fn code_at(key: &[u8], step: u64, digits: u32) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(digits)
+}
+
+/// Time step of the code if it's valid at `unix_time`, so callers can reject codes of
+/// steps that were already used
+pub fn verify(secret: &str, code: &str, unix_time: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let key = base32_decode(secret)?;
+    let now = unix_time / STEP;
+    (now.saturating_sub(SKEW)..=now + SKEW).find(|step| code_at(&key, *step, DIGITS) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 4648 test vectors, the encoder leaves the padding out
+    const BASE32_VECTORS: [(&str, &str); 7] = [
+        ("", ""),
+        ("f", "MY======"),
+        ("fo", "MZXQ===="),
+        ("foo", "MZXW6==="),
+        ("foob", "MZXW6YQ="),
+        ("fooba", "MZXW6YTB"),
+        ("foobar", "MZXW6YTBOI======"),
+    ];
+
+    /// RFC 6238 Appendix B key for SHA1
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn base32_encodes_rfc_vectors() {
+        for (plain, encoded) in BASE32_VECTORS {
+            assert_eq!(
+                base32_encode(plain.as_bytes()),
+                encoded.trim_end_matches('=')
+            );
+        }
+    }
+
+    #[test]
+    fn base32_decodes_padded_unpadded_and_lowercase() {
+        for (plain, encoded) in BASE32_VECTORS {
+            let unpadded = encoded.trim_end_matches('=');
+            for input in [encoded, unpadded, &encoded.to_lowercase()] {
+                assert_eq!(base32_decode(input).as_deref(), Some(plain.as_bytes()));
+            }
+        }
+    }
+
+    #[test]
+    fn base32_round_trips() {
+        for len in 0..=40 {
+            let bytes = (0..len).map(|i| (i * 37 + 11) as u8).collect::<Vec<_>>();
+            assert_eq!(base32_decode(&base32_encode(&bytes)), Some(bytes));
+        }
+        let secret = generate_secret();
+        assert_eq!(base32_decode(&secret).map(|key| key.len()), Some(20));
+    }
+
+    #[test]
+    fn base32_rejects_other_characters() {
+        assert_eq!(base32_decode("MZXW1"), None);
+        assert_eq!(base32_decode("MZX!"), None);
+    }
+
+    #[test]
+    fn codes_match_rfc_6238_sha1_vectors() {
+        for (time, code) in [
+            (59, 94287082),
+            (1111111109, 7081804),
+            (1111111111, 14050471),
+            (1234567890, 89005924),
+            (2000000000, 69279037),
+            (20000000000, 65353130),
+        ] {
+            assert_eq!(code_at(RFC_KEY, time / STEP, 8), code, "at {time}");
+        }
+    }
+
+    #[test]
+    fn verify_accepts_neighbouring_steps_only() {
+        let secret = base32_encode(RFC_KEY);
+        assert_eq!(secret, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        // Last six digits of the RFC code at 1111111111
+        assert_eq!(
+            verify(&secret, "050471", 1111111111),
+            Some(1111111111 / STEP)
+        );
+        assert_eq!(
+            verify(&secret, " 050471 ", 1111111111 + STEP),
+            Some(1111111111 / STEP)
+        );
+        assert_eq!(verify(&secret, "050471", 1111111111 + 2 * STEP), None);
+        assert_eq!(verify(&secret, "05047", 1111111111), None);
+        assert_eq!(verify(&secret, "05047a", 1111111111), None);
+    }
+}